@@ -0,0 +1,84 @@
+//! Compares the JSON & CBOR message envelopes, run with `cargo bench -p dkn-compute`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dkn_compute::utils::DriaMessage;
+use dkn_p2p::EnvelopeFormat;
+use libsecp256k1::{PublicKey, SecretKey};
+
+/// Payload sizes to benchmark, from a heartbeat to a large result.
+const PAYLOAD_SIZES: [usize; 3] = [256, 4 << 10, 64 << 10];
+
+const FORMATS: [EnvelopeFormat; 2] = [EnvelopeFormat::Json, EnvelopeFormat::Cbor];
+
+fn messages() -> (PublicKey, Vec<(usize, DriaMessage)>) {
+    let sk = SecretKey::parse_slice(&[0x42; 32]).unwrap();
+    let pk = PublicKey::from_secret_key(&sk);
+
+    let messages = PAYLOAD_SIZES
+        .into_iter()
+        .map(|size| {
+            let payload = serde_json::json!({ "result": "x".repeat(size) }).to_string();
+            (size, DriaMessage::new_signed(payload, "results", &sk))
+        })
+        .collect();
+
+    (pk, messages)
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let (_, messages) = messages();
+    let mut group = c.benchmark_group("encode");
+    for (size, message) in &messages {
+        group.throughput(Throughput::Bytes(*size as u64));
+        for format in FORMATS {
+            group.bench_with_input(
+                BenchmarkId::new(format.to_string(), size),
+                message,
+                |b, m| b.iter(|| m.encode(format).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let (_, messages) = messages();
+    let mut group = c.benchmark_group("decode");
+    for (size, message) in &messages {
+        group.throughput(Throughput::Bytes(*size as u64));
+        for format in FORMATS {
+            let bytes = message.encode(format).unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format.to_string(), size),
+                &bytes,
+                |b, m| b.iter(|| DriaMessage::decode(m).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let (pk, messages) = messages();
+    let mut group = c.benchmark_group("decode_verify");
+    for (size, message) in &messages {
+        group.throughput(Throughput::Bytes(*size as u64));
+        for format in FORMATS {
+            let bytes = message.encode(format).unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format.to_string(), size),
+                &bytes,
+                |b, m| {
+                    b.iter(|| {
+                        let message = DriaMessage::decode(m).unwrap();
+                        assert!(message.is_signed(&pk).unwrap());
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode, bench_verify);
+criterion_main!(benches);
@@ -0,0 +1,169 @@
+use dkn_compute::{
+    handlers::{WorkflowHandler, WorkflowPayload},
+    payloads::{TaskErrorPayload, TaskRequestPayload, TaskStats},
+    workers::workflow::WorkflowsWorker,
+};
+use dkn_workflows::DriaWorkflowsConfig;
+use eyre::{Context, Result};
+use libsecp256k1::SecretKey;
+use std::{env, io::Read};
+use tokio::sync::mpsc;
+use tokio_util::either::Either;
+
+/// Runs workflow tasks locally, without connecting to the peer-to-peer network.
+///
+/// The input is a `TaskRequestPayload<WorkflowPayload>` JSON file, or a JSONL file with one task per line;
+/// if the path is `-` the input is read from `stdin`. Each task goes through the same model selection &
+/// execution path of the compute node, and the resulting `TaskResponsePayload` or `TaskErrorPayload` is
+/// written to `stdout` as a single JSON line. Logs are written to `stderr`.
+///
+/// A task that can not be parsed or executed is reported, and the rest of the tasks are still run;
+/// the exit code is non-zero if any task has failed.
+///
+/// Deadline & filter checks are skipped, so that tasks can be re-run at any time.
+///
+/// Only `DKN_MODELS` is required; results are signed with `DKN_WALLET_SECRET_KEY` if it is given,
+/// and with a random key otherwise.
+///
+/// ## Usage
+///
+/// ```sh
+/// cargo run --bin dkn-local -- ./task.json
+/// cargo run --bin dkn-local -- ./tasks.jsonl
+/// cat ./task.json | cargo run --bin dkn-local -- -
+/// ```
+#[tokio::main]
+async fn main() -> Result<()> {
+    let dotenv_result = dotenvy::dotenv();
+
+    env_logger::builder()
+        .format_timestamp(Some(env_logger::TimestampPrecision::Millis))
+        .filter(None, log::LevelFilter::Off)
+        .filter_module("dkn_compute", log::LevelFilter::Info)
+        .filter_module("dkn_local", log::LevelFilter::Info)
+        .filter_module("dkn_workflows", log::LevelFilter::Info)
+        .parse_default_env() // reads RUST_LOG variable
+        .init();
+
+    if let Err(e) = dotenv_result {
+        log::warn!("Could not load .env file: {}", e);
+    }
+
+    let Some(path) = env::args().nth(1) else {
+        return Err(eyre::eyre!(
+            "Usage: dkn-local <task.json | tasks.jsonl | ->"
+        ));
+    };
+
+    // read the input, either from a file or stdin
+    let input = if path == "-" {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .wrap_err("could not read stdin")?;
+        input
+    } else {
+        std::fs::read_to_string(&path).wrap_err(format!("could not read {}", path))?
+    };
+
+    // a single JSON object is read as is, otherwise each non-empty line is a task
+    let tasks = match serde_json::from_str::<TaskRequestPayload<WorkflowPayload>>(&input) {
+        Ok(task) => vec![Ok(task)],
+        Err(_) => input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str::<TaskRequestPayload<WorkflowPayload>>(line)
+                    .wrap_err(format!("could not parse task at line {}", i + 1))
+            })
+            .collect::<Vec<_>>(),
+    };
+    log::info!("Read {} tasks from {}", tasks.len(), path);
+
+    // create configurations & check required services
+    let mut workflows_config =
+        DriaWorkflowsConfig::new_from_csv(&env::var("DKN_MODELS").unwrap_or_default());
+    if workflows_config.models.is_empty() {
+        return Err(eyre::eyre!("No models were provided, make sure to run with at least one model provided within DKN_MODELS."));
+    }
+    workflows_config.check_services().await?;
+    let secret_key = read_secret_key()?;
+
+    // tasks are executed one by one, so that the outputs are reproducible
+    let (publish_tx, mut publish_rx) = mpsc::channel(1);
+    let num_tasks = tasks.len();
+    let mut num_failed = 0;
+    for task in tasks {
+        let task = match task {
+            Ok(task) => task,
+            Err(err) => {
+                log::error!("{:#}", err);
+                num_failed += 1;
+                continue;
+            }
+        };
+        let task_id = task.task_id.clone();
+        let stats = TaskStats::new().record_received_at();
+
+        let input = match WorkflowHandler::prepare_input(&workflows_config, task, stats) {
+            Ok(input) => input,
+            Err(err) => {
+                log::error!("Could not prepare task {}: {:?}", task_id, err);
+                let error_payload = TaskErrorPayload {
+                    task_id,
+                    error: format!("{:#}", err),
+                    model: String::default(),
+                    stats: TaskStats::default(),
+                };
+                println!("{}", serde_json::json!(error_payload));
+                num_failed += 1;
+                continue;
+            }
+        };
+
+        log::info!("Executing task {}", task_id);
+        WorkflowsWorker::execute((input, &publish_tx)).await;
+        let Some(output) = publish_rx.recv().await else {
+            return Err(eyre::eyre!("Publish channel closed unexpectedly."));
+        };
+
+        match WorkflowHandler::prepare_payload(output, &secret_key) {
+            Ok(Either::Left(payload)) => println!("{}", serde_json::json!(payload)),
+            Ok(Either::Right(error_payload)) => {
+                println!("{}", serde_json::json!(error_payload));
+                num_failed += 1;
+            }
+            Err(err) => {
+                log::error!(
+                    "Could not prepare the result of task {}: {:?}",
+                    task_id,
+                    err
+                );
+                num_failed += 1;
+            }
+        }
+    }
+
+    if num_failed > 0 {
+        return Err(eyre::eyre!("{} of {} tasks failed", num_failed, num_tasks));
+    }
+
+    Ok(())
+}
+
+/// Reads the secret key that signs the results, without requiring the rest of the node configuration.
+///
+/// A random key is used if `DKN_WALLET_SECRET_KEY` is empty, missing or all-zeros.
+fn read_secret_key() -> Result<SecretKey> {
+    let secret_env = env::var("DKN_WALLET_SECRET_KEY").unwrap_or_default();
+    let secret_dec = hex::decode(secret_env.trim().trim_start_matches("0x"))
+        .wrap_err("DKN_WALLET_SECRET_KEY should be 32-bytes hex encoded")?;
+
+    if secret_dec.iter().all(|b| b == &0) {
+        log::warn!("No secret key provided, results are signed with a random key.");
+        Ok(SecretKey::random(&mut rand::thread_rng()))
+    } else {
+        SecretKey::parse_slice(&secret_dec).wrap_err("could not parse DKN_WALLET_SECRET_KEY")
+    }
+}
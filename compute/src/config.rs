@@ -1,12 +1,18 @@
-use dkn_p2p::{libp2p::Multiaddr, DriaNetworkType};
+use dkn_p2p::{
+    libp2p::{Multiaddr, PeerId},
+    DriaNetworkType, DriaP2PConfig, EnvelopeFormat,
+};
+use dkn_utils::split_csv_line;
 use dkn_workflows::DriaWorkflowsConfig;
 use eyre::{eyre, Result};
 use libsecp256k1::{PublicKey, SecretKey};
-use std::{env, str::FromStr};
+use std::{env, str::FromStr, time::Duration};
 
 use crate::utils::{
     address_in_use,
+    compression::Compression,
     crypto::{secret_to_keypair, to_address},
+    AdminKeys, AdminKeysSource, DriaNodesSource,
 };
 
 const DEFAULT_WORKFLOW_BATCH_SIZE: usize = 5;
@@ -20,8 +26,12 @@ pub struct DriaComputeNodeConfig {
     pub public_key: PublicKey,
     /// Wallet address, derived from the public key.
     pub address: [u8; 20],
-    /// Admin public key, used for message authenticity.
-    pub admin_public_key: PublicKey,
+    /// Admin public keys, used for message authenticity.
+    ///
+    /// These are the initial trusted keys, the node may rotate them later with a signed list.
+    pub admin_keys: AdminKeys,
+    /// Where the admin keys are refreshed from, disabled unless a URL is given.
+    pub admin_keys_source: AdminKeysSource,
     /// P2P listen address, e.g. `/ip4/0.0.0.0/tcp/4001`.
     pub p2p_listen_addr: Multiaddr,
     /// Workflow configurations, e.g. models and providers.
@@ -33,6 +43,38 @@ pub struct DriaComputeNodeConfig {
     /// A higher value will help execute more tasks concurrently,
     /// at the risk of hitting rate-limits.
     pub batch_size: usize,
+    /// Where the available nodes are refreshed from, with an optional cache for when the API is unreachable.
+    pub nodes_source: DriaNodesSource,
+    /// Peer-to-peer configurations, e.g. mesh size and message limits.
+    pub p2p: DriaP2PConfig,
+    /// Maximum age of the Gossipsub messages, disabled if `None`.
+    ///
+    /// Enabling this changes the Gossipsub protocol, so it should only be enabled
+    /// along with the rest of the network.
+    pub message_freshness: Option<Duration>,
+    /// Compression of the large payloads that are published, disabled if `None`.
+    ///
+    /// Compressed payloads are read by every node regardless, so this only affects the published messages;
+    /// nodes older than this feature can not read them though.
+    pub compression: Option<Compression>,
+    /// Whether the received messages must have an envelope signature, see [`crate::utils::DriaMessage::is_envelope_signed_by_any`].
+    ///
+    /// Enabled by default; if disabled for older RPCs that sign the payload only, the envelope signature is still
+    /// required from the admins that are seen signing envelopes, see [`crate::utils::EnvelopeSigners`].
+    pub require_envelope_signature: bool,
+    /// Whether the results that do not fit in a single message are published in chunks.
+    ///
+    /// Disabled by default, as older RPCs can not reassemble them; such results are reported as errors instead.
+    pub chunked_results: bool,
+    /// Wire format of the published messages.
+    ///
+    /// CBOR changes the Gossipsub protocol, so it should only be enabled
+    /// along with the rest of the network.
+    pub envelope: EnvelopeFormat,
+    /// Peers that are allowed to request the specs of the node, e.g. monitors, in addition to the RPCs.
+    ///
+    /// Unlike the RPCs, these peers can not make any other request.
+    pub monitor_peer_ids: Vec<PeerId>,
 }
 
 #[allow(clippy::new_without_default)]
@@ -69,18 +111,25 @@ impl DriaComputeNodeConfig {
             hex::encode(public_key.serialize_compressed())
         );
 
-        let admin_public_key = match env::var("DKN_ADMIN_PUBLIC_KEY") {
-            Ok(admin_public_key) => {
-                let pubkey_dec = hex::decode(admin_public_key.trim_start_matches("0x"))
-                    .expect("Admin public key should be 33-bytes hex encoded.");
-                PublicKey::parse_slice(&pubkey_dec, None)
-                    .expect("Admin public key should be parseable.")
-            }
+        // multiple admin keys can be given as comma-separated values
+        let admin_public_keys = match env::var("DKN_ADMIN_PUBLIC_KEY") {
+            Ok(admin_public_keys) => split_csv_line(&admin_public_keys)
+                .into_iter()
+                .map(|admin_public_key| {
+                    let pubkey_dec = hex::decode(admin_public_key.trim_start_matches("0x"))
+                        .expect("Admin public key should be 33-bytes hex encoded.");
+                    PublicKey::parse_slice(&pubkey_dec, None)
+                        .expect("Admin public key should be parseable.")
+                })
+                .collect::<Vec<_>>(),
             Err(err) => {
                 log::error!("No admin public key provided: {}", err);
                 panic!("Please provide an admin public key.");
             }
         };
+        if admin_public_keys.is_empty() {
+            panic!("Please provide an admin public key.");
+        }
 
         let address = to_address(&public_key);
         log::info!("Node Address:     0x{}", hex::encode(address));
@@ -91,10 +140,12 @@ impl DriaComputeNodeConfig {
             secret_to_keypair(&secret_key).public().to_peer_id()
         );
 
-        log::info!(
-            "Admin Public Key: 0x{}",
-            hex::encode(admin_public_key.serialize_compressed())
-        );
+        for admin_public_key in &admin_public_keys {
+            log::info!(
+                "Admin Public Key: 0x{}",
+                hex::encode(admin_public_key.serialize_compressed())
+            );
+        }
 
         // parse listen address
         let p2p_listen_addr_str = env::var("DKN_P2P_LISTEN_ADDR")
@@ -113,8 +164,50 @@ impl DriaComputeNodeConfig {
             .map(|s| s.parse::<usize>().unwrap_or(DEFAULT_WORKFLOW_BATCH_SIZE))
             .unwrap_or(DEFAULT_WORKFLOW_BATCH_SIZE);
 
+        // parse message freshness, disabled by default
+        let message_freshness = env::var("DKN_P2P_MESSAGE_FRESHNESS_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        // parse compression, disabled by default
+        let compression = env::var("DKN_COMPRESSION")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| Compression::from_name(&s).expect("could not parse the given compression."));
+
+        // parse envelope format, JSON by default
+        let envelope = env::var("DKN_P2P_ENVELOPE")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| EnvelopeFormat::from_name(&s).expect("could not parse the given envelope."))
+            .unwrap_or_default();
+
+        // parse whether the envelope signature is required, enabled by default
+        let require_envelope_signature = env::var("DKN_REQUIRE_ENVELOPE_SIGNATURE")
+            .map(|s| s.trim() != "false")
+            .unwrap_or(true);
+
+        // parse whether large results are chunked, disabled by default
+        let chunked_results = env::var("DKN_CHUNKED_RESULTS")
+            .map(|s| s.trim() == "true")
+            .unwrap_or_default();
+
+        // parse the peers that may request the specs, none by default
+        let monitor_peer_ids = env::var("DKN_MONITOR_PEER_IDS")
+            .map(|s| {
+                split_csv_line(&s)
+                    .into_iter()
+                    .map(|peer_id| {
+                        PeerId::from_str(&peer_id).expect("Monitor peer id should be parseable.")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
         Self {
-            admin_public_key,
+            admin_keys: AdminKeys::from(admin_public_keys),
+            admin_keys_source: AdminKeysSource::default().with_envs(),
             secret_key,
             public_key,
             address,
@@ -122,6 +215,14 @@ impl DriaComputeNodeConfig {
             p2p_listen_addr,
             network_type,
             batch_size,
+            nodes_source: DriaNodesSource::default().with_envs(),
+            p2p: DriaP2PConfig::default().with_envs(),
+            message_freshness,
+            compression,
+            require_envelope_signature,
+            chunked_results,
+            envelope,
+            monitor_peer_ids,
         }
     }
 
@@ -20,11 +20,11 @@ pub struct PingpongPayload {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PingpongResponse {
     /// UUID as given in the ping payload.
-    pub(crate) uuid: String,
+    pub uuid: String,
     /// Models available in the node.
-    pub(crate) models: Vec<(ModelProvider, Model)>,
+    pub models: Vec<(ModelProvider, Model)>,
     /// Number of tasks in the channel currently, `single` and `batch`.
-    pub(crate) pending_tasks: [usize; 2],
+    pub pending_tasks: [usize; 2],
 }
 
 impl PingpongHandler {
@@ -62,6 +62,12 @@ impl PingpongHandler {
             return Ok(MessageAcceptance::Ignore);
         }
 
+        // check if this ping was seen before
+        if node.is_replayed(&pingpong.uuid, ping_message, pingpong.deadline)? {
+            log::warn!("Ping (uuid: {}) is a replay, ignoring.", pingpong.uuid);
+            return Ok(MessageAcceptance::Ignore);
+        }
+
         log::info!("Received a ping for: {}", pingpong.uuid);
         // record ping moment
         node.last_pinged_at = Instant::now();
@@ -1,13 +1,13 @@
 use dkn_p2p::libp2p::gossipsub::MessageAcceptance;
 use dkn_utils::get_current_time_nanos;
-use dkn_workflows::{Entry, Executor, ModelProvider, Workflow};
+use dkn_workflows::{DriaWorkflowsConfig, Entry, Executor, ModelProvider, Workflow};
 use eyre::{Context, Result};
-use libsecp256k1::PublicKey;
+use libsecp256k1::{PublicKey, SecretKey};
 use serde::Deserialize;
 use tokio_util::either::Either;
 
 use crate::payloads::*;
-use crate::utils::DriaMessage;
+use crate::utils::{chunks, DriaMessage};
 use crate::workers::workflow::*;
 use crate::DriaComputeNode;
 
@@ -53,6 +53,12 @@ impl WorkflowHandler {
             return Ok(Either::Left(MessageAcceptance::Ignore));
         }
 
+        // check if this task was seen before
+        if node.is_replayed(&task.task_id, compute_message, task.deadline)? {
+            log::warn!("Task {} is a replay, ignoring.", task.task_id);
+            return Ok(Either::Left(MessageAcceptance::Ignore));
+        }
+
         // check task inclusion via the bloom filter
         if !task.filter.contains(&node.config.address)? {
             log::debug!("Task {} ignored due to filter.", task.task_id);
@@ -62,7 +68,20 @@ impl WorkflowHandler {
         }
 
         log::info!("Received a task with id: {}", task.task_id);
+        let input = Self::prepare_input(&node.config.workflows, task, stats)?;
+
+        Ok(Either::Right(input))
+    }
 
+    /// Prepares the worker input for a given task, without any deadline or filter checks.
+    ///
+    /// The model is chosen from the task's list w.r.t the given workflows config,
+    /// and the executor is prepared for the chosen model's provider.
+    pub fn prepare_input(
+        workflows: &DriaWorkflowsConfig,
+        task: TaskRequestPayload<WorkflowPayload>,
+        stats: TaskStats,
+    ) -> Result<WorkflowsWorkerInput> {
         // obtain public key from the payload
         // do this early to avoid unnecessary processing
         let task_public_key_bytes =
@@ -70,21 +89,14 @@ impl WorkflowHandler {
         let task_public_key = PublicKey::parse_slice(&task_public_key_bytes, None)?;
 
         // read model / provider from the task
-        let (model_provider, model) = node
-            .config
-            .workflows
-            .get_any_matching_model(task.input.model)?;
+        let (model_provider, model) = workflows.get_any_matching_model(task.input.model)?;
         let model_name = model.to_string(); // get model name, we will pass it in payload
         log::info!("Using model {} for task {}", model_name, task.task_id);
 
         // prepare workflow executor
         let (executor, batchable) = if model_provider == ModelProvider::Ollama {
             (
-                Executor::new_at(
-                    model,
-                    &node.config.workflows.ollama.host,
-                    node.config.workflows.ollama.port,
-                ),
+                Executor::new_at(model, &workflows.ollama.host, workflows.ollama.port),
                 false,
             )
         } else {
@@ -100,7 +112,7 @@ impl WorkflowHandler {
         // get workflow as well
         let workflow = task.input.workflow;
 
-        Ok(Either::Right(WorkflowsWorkerInput {
+        Ok(WorkflowsWorkerInput {
             entry,
             executor,
             workflow,
@@ -109,30 +121,30 @@ impl WorkflowHandler {
             public_key: task_public_key,
             stats,
             batchable,
-        }))
+        })
     }
 
-    /// Handles the result of a workflow task.
-    pub(crate) async fn handle_publish(
-        node: &mut DriaComputeNode,
+    /// Prepares the payload for the output of a workflow task.
+    ///
+    /// - If the task succeeded, the result is signed & encrypted within a `TaskResponsePayload`.
+    /// - If the task failed, the error is returned within a `TaskErrorPayload`.
+    pub fn prepare_payload(
         task: WorkflowsWorkerOutput,
-    ) -> Result<()> {
-        let message = match task.result {
+        signing_key: &SecretKey,
+    ) -> Result<Either<TaskResponsePayload, TaskErrorPayload>> {
+        match task.result {
             Ok(result) => {
                 // prepare signed and encrypted payload
                 let payload = TaskResponsePayload::new(
                     result,
                     &task.task_id,
                     &task.public_key,
-                    &node.config.secret_key,
+                    signing_key,
                     task.model_name,
                     task.stats.record_published_at(),
                 )?;
 
-                // convert payload to message
-                let payload_str = serde_json::json!(payload).to_string();
-                log::info!("Publishing result for task {}", task.task_id);
-                DriaMessage::new(payload_str, Self::RESPONSE_TOPIC)
+                Ok(Either::Left(payload))
             }
             Err(err) => {
                 // use pretty display string for error logging with causes
@@ -141,11 +153,49 @@ impl WorkflowHandler {
 
                 // prepare error payload
                 let error_payload = TaskErrorPayload {
-                    task_id: task.task_id.clone(),
+                    task_id: task.task_id,
                     error: err_string,
                     model: task.model_name,
                     stats: task.stats.record_published_at(),
                 };
+
+                Ok(Either::Right(error_payload))
+            }
+        }
+    }
+
+    /// Handles the result of a workflow task.
+    pub(crate) async fn handle_publish(
+        node: &mut DriaComputeNode,
+        task: WorkflowsWorkerOutput,
+    ) -> Result<()> {
+        let task_id = task.task_id.clone();
+        let message = match Self::prepare_payload(task, &node.config.secret_key)? {
+            Either::Left(payload) => {
+                let payload_str = serde_json::json!(payload).to_string();
+
+                // large results do not fit in a single message, so they are published in chunks if enabled;
+                // otherwise, publishing fails below and an error is published instead
+                if node.config.chunked_results
+                    && !chunks::fits_in_message(
+                        payload_str.len(),
+                        node.config.p2p.max_transmit_size,
+                    )
+                {
+                    if let Err(publish_err) = node
+                        .publish_chunked(&task_id, payload_str.as_bytes(), Self::RESPONSE_TOPIC)
+                        .await
+                    {
+                        Self::publish_error(node, task_id, publish_err).await?;
+                    }
+                    return Ok(());
+                }
+
+                // convert payload to message
+                log::info!("Publishing result for task {}", task_id);
+                DriaMessage::new(payload_str, Self::RESPONSE_TOPIC)
+            }
+            Either::Right(error_payload) => {
                 let error_payload_str = serde_json::json!(error_payload).to_string();
 
                 // prepare signed message
@@ -159,22 +209,31 @@ impl WorkflowHandler {
 
         // try publishing the result
         if let Err(publish_err) = node.publish(message).await {
-            let err_msg = format!("Could not publish task result: {:?}", publish_err);
-            log::error!("{}", err_msg);
-
-            let payload = serde_json::json!({
-                "taskId": task.task_id,
-                "error": err_msg,
-            });
-            let message = DriaMessage::new_signed(
-                payload.to_string(),
-                Self::RESPONSE_TOPIC,
-                &node.config.secret_key,
-            );
-
-            node.publish(message).await?;
+            Self::publish_error(node, task_id, publish_err).await?;
         };
 
         Ok(())
     }
+
+    /// Publishes a signed error for a task result that could not be published.
+    async fn publish_error(
+        node: &mut DriaComputeNode,
+        task_id: String,
+        publish_err: eyre::Report,
+    ) -> Result<()> {
+        let err_msg = format!("Could not publish task result: {:?}", publish_err);
+        log::error!("{}", err_msg);
+
+        let payload = serde_json::json!({
+            "taskId": task_id,
+            "error": err_msg,
+        });
+        let message = DriaMessage::new_signed(
+            payload.to_string(),
+            Self::RESPONSE_TOPIC,
+            &node.config.secret_key,
+        );
+
+        node.publish(message).await
+    }
 }
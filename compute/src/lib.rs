@@ -15,7 +15,7 @@ pub mod workers;
 /// This value is attached within the published messages.
 pub const DRIA_COMPUTE_NODE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-pub use utils::refresh_dria_nodes;
+pub use utils::{refresh_dria_nodes, DriaNodesSource};
 
 pub use config::DriaComputeNodeConfig;
 pub use node::DriaComputeNode;
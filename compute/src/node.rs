@@ -1,15 +1,17 @@
 use dkn_p2p::{
     libp2p::{
+        autonat::NatStatus,
         gossipsub::{Message, MessageAcceptance, MessageId},
         request_response::ResponseChannel,
         PeerId,
     },
-    DriaNodes, DriaP2PClient, DriaP2PCommander, DriaP2PProtocol,
+    DriaNodes, DriaP2PClient, DriaP2PCommander, DriaP2PEvent, DriaP2PProtocol, EnvelopeFormat,
+    ReservedPeerKind,
 };
 use eyre::Result;
 use std::collections::HashSet;
 use tokio::{
-    sync::mpsc,
+    sync::{broadcast, mpsc},
     time::{Duration, Instant},
 };
 use tokio_util::{either::Either, sync::CancellationToken};
@@ -18,7 +20,13 @@ use crate::{
     config::*,
     handlers::*,
     responders::{IsResponder, SpecResponder, WorkflowResponder},
-    utils::{crypto::secret_to_keypair, refresh_dria_nodes, DriaMessage, SpecCollector},
+    utils::{
+        chunks,
+        compression::{Compression, COMPRESSION_MIN_SIZE},
+        crypto::secret_to_keypair,
+        load_admin_keys, refresh_admin_keys, refresh_dria_nodes, DriaMessage, EnvelopeSigners,
+        ReplayCache, SpecCollector,
+    },
     workers::workflow::{WorkflowsWorker, WorkflowsWorkerInput, WorkflowsWorkerOutput},
     DRIA_COMPUTE_NODE_VERSION,
 };
@@ -29,6 +37,11 @@ const DIAGNOSTIC_REFRESH_INTERVAL_SECS: u64 = 30;
 const AVAILABLE_NODES_REFRESH_INTERVAL_SECS: u64 = 30 * 60; // 30 minutes
 /// Number of seconds such that if the last ping is older than this, the node is considered unreachable.
 const PING_LIVENESS_SECS: u64 = 150;
+/// Number of seconds such that if a message is older than this, it is rejected.
+const MESSAGE_MAX_AGE_SECS: u64 = 5 * 60;
+/// Number of seconds such that if a message is further in the future than this, it is rejected.
+/// This allows for some clock skew between the nodes.
+const MESSAGE_MAX_SKEW_SECS: u64 = 60;
 /// Buffer size for message publishes.
 const PUBLISH_CHANNEL_BUFSIZE: usize = 1024;
 
@@ -45,6 +58,8 @@ pub struct DriaComputeNode {
     message_rx: mpsc::Receiver<(PeerId, MessageId, Message)>,
     /// Request-response request receiver.
     request_rx: mpsc::Receiver<(PeerId, Vec<u8>, ResponseChannel<Vec<u8>>)>,
+    /// Peer-to-peer client events, e.g. liveness of the RPC connections.
+    p2p_events: broadcast::Receiver<DriaP2PEvent>,
     /// Publish receiver to receive messages to be published,
     publish_rx: mpsc::Receiver<WorkflowsWorkerOutput>,
     /// Workflow transmitter to send batchable tasks.
@@ -61,6 +76,12 @@ pub struct DriaComputeNode {
     completed_tasks_batch: usize,
     /// Spec collector for the node.
     spec_collector: SpecCollector,
+    /// Seen signed messages, used to drop replayed ones.
+    replay_cache: ReplayCache,
+    /// Admins that sign the envelopes of their messages, whose messages must be signed so.
+    envelope_signers: EnvelopeSigners,
+    /// Dropped replayed messages count
+    replayed_messages: usize,
 }
 
 impl DriaComputeNode {
@@ -68,27 +89,69 @@ impl DriaComputeNode {
     ///
     /// Returns the node instance and p2p client together. P2p MUST be run in a separate task before this node is used at all.
     pub async fn new(
-        config: DriaComputeNodeConfig,
+        mut config: DriaComputeNodeConfig,
     ) -> Result<(
         DriaComputeNode,
         DriaP2PClient,
         Option<WorkflowsWorker>,
         Option<WorkflowsWorker>,
     )> {
-        // create the keypair from secret key
-        let keypair = secret_to_keypair(&config.secret_key);
+        // get admin keys, the last applied (or configured) ones are used if this fails
+        if let Err(e) = load_admin_keys(&mut config.admin_keys, &config.admin_keys_source) {
+            log::warn!("Could not load admin keys: {:?}", e);
+        };
+        if let Err(e) = refresh_admin_keys(&mut config.admin_keys, &config.admin_keys_source).await
+        {
+            log::warn!("Could not refresh admin keys: {:?}", e);
+        };
 
-        // get available nodes (bootstrap, relay, rpc) for p2p
+        // get available nodes (bootstrap, relay, rpc) for p2p, verified with the admin keys
         let mut available_nodes = DriaNodes::new(config.network_type)
             .with_statics()
             .with_envs();
-        if let Err(e) = refresh_dria_nodes(&mut available_nodes).await {
+        if let Err(e) = refresh_dria_nodes(
+            &mut available_nodes,
+            &config.nodes_source,
+            Some(&config.admin_keys),
+        )
+        .await
+        {
             log::error!("Error populating available nodes: {:?}", e);
         };
 
+        Self::new_with_nodes(config, available_nodes)
+    }
+
+    /// Creates a new `DriaComputeNode` with the given nodes, without refreshing them or the admin keys from Dria.
+    ///
+    /// The configured admin keys are used as is. This is useful for local networks & tests,
+    /// see [`DriaComputeNode::new`] for the returned values.
+    pub fn new_with_nodes(
+        config: DriaComputeNodeConfig,
+        available_nodes: DriaNodes,
+    ) -> Result<(
+        DriaComputeNode,
+        DriaP2PClient,
+        Option<WorkflowsWorker>,
+        Option<WorkflowsWorker>,
+    )> {
+        // create the keypair from secret key
+        let keypair = secret_to_keypair(&config.secret_key);
+
         // we are using the major.minor version as the P2P version
         // so that patch versions do not interfere with the protocol
-        let protocol = DriaP2PProtocol::new_major_minor(config.network_type.protocol_name());
+        let mut protocol = DriaP2PProtocol::new_major_minor(config.network_type.protocol_name());
+        if let Some(max_age) = config.message_freshness {
+            log::info!("Using message freshness of {} seconds", max_age.as_secs());
+            protocol = protocol.with_message_freshness(max_age);
+        }
+        if config.envelope != EnvelopeFormat::Json {
+            log::info!("Using {} message envelope", config.envelope);
+            protocol = protocol.with_envelope(config.envelope);
+        }
+        if let Some(compression) = config.compression {
+            log::info!("Using {} compression for large payloads", compression);
+        }
         log::info!("Using identity: {}", protocol);
 
         // create p2p client
@@ -97,6 +160,7 @@ impl DriaComputeNode {
             config.p2p_listen_addr.clone(),
             &available_nodes,
             protocol,
+            config.p2p.clone(),
         )?;
 
         // create workflow workers, all workers use the same publish channel
@@ -124,6 +188,7 @@ impl DriaComputeNode {
         Ok((
             DriaComputeNode {
                 config,
+                p2p_events: p2p_commander.events(),
                 p2p: p2p_commander,
                 dria_nodes: available_nodes,
                 publish_rx,
@@ -137,6 +202,9 @@ impl DriaComputeNode {
                 completed_tasks_batch: 0,
                 spec_collector: SpecCollector::new(model_names),
                 last_pinged_at: Instant::now(),
+                replay_cache: ReplayCache::default(),
+                envelope_signers: EnvelopeSigners::default(),
+                replayed_messages: 0,
             },
             p2p_client,
             workflows_batch_worker,
@@ -183,18 +251,80 @@ impl DriaComputeNode {
 
     /// Publishes a given message to the network w.r.t the topic of it.
     ///
-    /// Internally, identity is attached to the the message which is then JSON serialized to bytes
-    /// and then published to the network as is.
+    /// Internally, identity is attached to the the message and the envelope is signed,
+    /// which is then serialized to bytes w.r.t the envelope format of the protocol and published to the network as is.
+    ///
+    /// If compression is enabled, payloads of at least [`COMPRESSION_MIN_SIZE`] are compressed.
     pub async fn publish(&mut self, mut message: DriaMessage) -> Result<()> {
-        // attach protocol name to the message
-        message = message.with_identity(self.p2p.protocol().name.clone());
+        if let Some(compression) = self.config.compression {
+            if message.payload.len() >= COMPRESSION_MIN_SIZE {
+                message = message.with_compression(compression)?;
+            }
+        }
+
+        // attach protocol name to the message, and sign the entire envelope
+        message = message
+            .with_identity(self.p2p.protocol().name.clone())
+            .with_envelope_signature(&self.config.secret_key)?;
 
-        let message_bytes = serde_json::to_vec(&message)?;
+        let message_bytes = message.encode(self.p2p.protocol().envelope)?;
         let message_id = self.p2p.publish(&message.topic, message_bytes).await?;
         log::info!("Published message ({}) to {}", message_id, message.topic);
         Ok(())
     }
 
+    /// Publishes a payload that is too large for a single message as signed chunks,
+    /// preceded by a signed announcement that references them, see [`TaskResultAnnouncement`](crate::payloads::TaskResultAnnouncement).
+    ///
+    /// The chunks are sized w.r.t the maximum transmit size of the client.
+    pub async fn publish_chunked(
+        &mut self,
+        task_id: &str,
+        payload: &[u8],
+        topic: &str,
+    ) -> Result<()> {
+        let chunk_size = chunks::chunk_size(self.config.p2p.max_transmit_size);
+        let (announcement, chunks) = chunks::split_payload(task_id, payload, chunk_size)?;
+        log::info!(
+            "Publishing {} bytes for task {} in {} chunks (transfer {})",
+            announcement.size,
+            task_id,
+            announcement.chunks,
+            announcement.transfer_id
+        );
+
+        let announcement_str = serde_json::to_string(&announcement)?;
+        let message = DriaMessage::new_signed(announcement_str, topic, &self.config.secret_key);
+        self.publish(message).await?;
+
+        for chunk in chunks {
+            let chunk_str = serde_json::to_string(&chunk)?;
+            let message = DriaMessage::new_signed(chunk_str, topic, &self.config.secret_key);
+            self.publish(message).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if a signed message with the given `id` (e.g. ping uuid or task id) was seen before,
+    /// and records it until its `deadline` otherwise.
+    ///
+    /// Returns `true` if the message is a replay, in which case it should be dropped.
+    pub(crate) fn is_replayed(
+        &mut self,
+        id: &str,
+        message: &DriaMessage,
+        deadline: u128,
+    ) -> Result<bool> {
+        let signature = message.signature()?;
+        if self.replay_cache.insert(id, signature, deadline) {
+            Ok(false)
+        } else {
+            self.replayed_messages += 1;
+            Ok(true)
+        }
+    }
+
     /// Returns the list of connected peers, `mesh` and `all`.
     #[inline(always)]
     pub async fn peers(&self) -> Result<(Vec<PeerId>, Vec<PeerId>)> {
@@ -232,7 +362,7 @@ impl DriaComputeNode {
                 // parse the raw gossipsub message to a prepared DKN message
                 // the received message is expected to use IdentHash for the topic, so we can see the name of the topic immediately.
                 log::debug!("Parsing {} message.", gossipsub_message.topic.as_str());
-                let message: DriaMessage = match serde_json::from_slice(&gossipsub_message.data) {
+                let message = match DriaMessage::decode(&gossipsub_message.data) {
                     Ok(message) => message,
                     Err(e) => {
                         log::error!("Error parsing message: {:?}", e);
@@ -253,8 +383,15 @@ impl DriaComputeNode {
                     message
                 );
 
-                // check signature
-                match message.is_signed(&self.config.admin_public_key) {
+                // check signature, which must cover the entire envelope if required,
+                // or if the signer is known to sign envelopes otherwise
+                let is_signed = if self.config.require_envelope_signature {
+                    message.is_envelope_signed_by_any(self.config.admin_keys.valid_keys())
+                } else {
+                    self.envelope_signers
+                        .is_signed_by_any(&message, self.config.admin_keys.valid_keys())
+                };
+                match is_signed {
                     Ok(true) => { /* message is signed correctly, nothing to do here */ }
                     Ok(false) => {
                         log::warn!("Message has wrong signature!");
@@ -266,6 +403,19 @@ impl DriaComputeNode {
                     }
                 }
 
+                // check the timestamp, so that very old (or future) messages are not handled
+                if !message.is_timestamp_within(
+                    Duration::from_secs(MESSAGE_MAX_AGE_SECS),
+                    Duration::from_secs(MESSAGE_MAX_SKEW_SECS),
+                ) {
+                    log::warn!(
+                        "Received {} message with timestamp {} out of the allowed window.",
+                        message.topic,
+                        message.timestamp
+                    );
+                    return MessageAcceptance::Ignore;
+                }
+
                 // handle the DKN message with respect to the topic
                 let handler_result = match message.topic.as_str() {
                     WorkflowHandler::LISTEN_TOPIC => {
@@ -339,12 +489,14 @@ impl DriaComputeNode {
     /// Handles a request-response request received from the network.
     ///
     /// Internally, the data is expected to be some JSON serialized data that is expected to be parsed and handled.
+    /// The data may be compressed as well, in which case the response is compressed the same way.
     async fn handle_request(
         &mut self,
         (peer_id, data, channel): (PeerId, Vec<u8>, ResponseChannel<Vec<u8>>),
     ) -> Result<()> {
-        // ensure that message is from the known RPCs
-        if !self.dria_nodes.rpc_peerids.contains(&peer_id) {
+        // ensure that message is from the known RPCs, or from the monitors for spec requests
+        let is_rpc = self.dria_nodes.rpc_peerids.contains(&peer_id);
+        if !is_rpc && !self.config.monitor_peer_ids.contains(&peer_id) {
             log::warn!("Received request from unauthorized source: {}", peer_id);
             log::debug!("Allowed sources: {:#?}", self.dria_nodes.rpc_peerids);
             return Err(eyre::eyre!(
@@ -353,6 +505,13 @@ impl DriaComputeNode {
             ));
         }
 
+        // decompress the request if needed, the response is compressed the same way
+        let compression = Compression::detect(&data);
+        let data = match compression {
+            Some(compression) => compression.decompress(&data)?,
+            None => data,
+        };
+
         // respond w.r.t data
         let response_data = if let Ok(req) = SpecResponder::try_parse_request(&data) {
             log::info!(
@@ -363,6 +522,12 @@ impl DriaComputeNode {
 
             let response = SpecResponder::respond(req, self.spec_collector.collect().await);
             serde_json::to_vec(&response)?
+        } else if !is_rpc {
+            log::warn!("Received non-spec request from monitor: {}", peer_id);
+            return Err(eyre::eyre!(
+                "Received unauthorized request from {}",
+                peer_id
+            ));
         } else if let Ok(req) = WorkflowResponder::try_parse_request(&data) {
             log::info!("Received a task request with id: {}", req.task_id);
             return Err(eyre::eyre!(
@@ -376,6 +541,11 @@ impl DriaComputeNode {
             ));
         };
 
+        let response_data = match compression {
+            Some(compression) => compression.compress(&response_data)?,
+            None => response_data,
+        };
+
         log::info!("Responding to peer {}", peer_id);
         self.p2p.respond(response_data, channel).await
     }
@@ -457,6 +627,17 @@ impl DriaComputeNode {
                         break;
                     };
                 },
+                // an event is received from the p2p client
+                p2p_event = self.p2p_events.recv() => match p2p_event {
+                    Ok(event) => self.handle_p2p_event(event).await,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        log::warn!("Missed {} p2p events.", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        log::error!("p2p_events channel closed unexpectedly.");
+                        break;
+                    }
+                },
                 // check if the cancellation token is cancelled
                 // this is expected to be cancelled by the main thread with signal handling
                 _ = cancellation.cancelled() => break,
@@ -519,6 +700,23 @@ impl DriaComputeNode {
             ));
         }
 
+        // lowest & highest peer scores are printed as well in debug
+        if log::log_enabled!(log::Level::Debug) {
+            match self.p2p.peer_scores().await {
+                Ok(scores) => {
+                    if let (Some((_, min)), Some((_, max))) = (scores.first(), scores.last()) {
+                        diagnostics.push(format!("Peer Scores (min/max): {:.2} / {:.2}", min, max));
+                    }
+                }
+                Err(e) => log::error!("Error getting peer scores: {:?}", e),
+            }
+        }
+
+        // print dropped replays, if any
+        if self.replayed_messages > 0 {
+            diagnostics.push(format!("Replayed Messages: {}", self.replayed_messages));
+        }
+
         // print version
         diagnostics.push(format!("Version: v{}", DRIA_COMPUTE_NODE_VERSION));
 
@@ -526,29 +724,67 @@ impl DriaComputeNode {
 
         if self.last_pinged_at < Instant::now() - Duration::from_secs(PING_LIVENESS_SECS) {
             log::error!(
-                "Node has not received any pings for at least {} seconds & it may be unreachable!\nPlease restart your node!",
+                "Node has not received any pings for at least {} seconds & it may be unreachable!\nPlease check your connection if this persists.",
                 PING_LIVENESS_SECS
             );
         }
     }
 
-    /// Updates the local list of available nodes by refreshing it.
-    /// Dials the RPC nodes again for better connectivity.
+    /// Handles an event of the p2p client.
+    ///
+    /// When the connection to all RPC nodes is lost, the available nodes are refreshed
+    /// in case the RPC nodes have changed; the known ones are redialled by the client regardless.
+    async fn handle_p2p_event(&mut self, event: DriaP2PEvent) {
+        match event {
+            DriaP2PEvent::LivenessLost {
+                kind: ReservedPeerKind::Rpc,
+            } => {
+                log::warn!("Lost connection to all RPC nodes, refreshing available nodes.");
+                self.handle_available_nodes_refresh().await;
+            }
+            DriaP2PEvent::LivenessRestored {
+                kind: ReservedPeerKind::Rpc,
+            } => {
+                log::info!("Connection to RPC nodes is restored.");
+            }
+            DriaP2PEvent::NatStatusChanged {
+                new: NatStatus::Private,
+                ..
+            } => {
+                log::warn!("Node is behind a NAT, it is reachable through relays only.");
+            }
+            event => log::debug!("P2P event: {:?}", event),
+        }
+    }
+
+    /// Updates the local list of available nodes and admin keys by refreshing them.
+    /// Reserves the RPC nodes so that they are kept connected.
     async fn handle_available_nodes_refresh(&mut self) {
         log::info!("Refreshing available Dria nodes.");
 
-        // refresh available nodes
-        if let Err(e) = refresh_dria_nodes(&mut self.dria_nodes).await {
+        // refresh admin keys
+        if let Err(e) =
+            refresh_admin_keys(&mut self.config.admin_keys, &self.config.admin_keys_source).await
+        {
+            log::warn!("Could not refresh admin keys: {:?}", e);
+        };
+
+        // refresh available nodes, verified with the admin keys
+        if let Err(e) = refresh_dria_nodes(
+            &mut self.dria_nodes,
+            &self.config.nodes_source,
+            Some(&self.config.admin_keys),
+        )
+        .await
+        {
             log::error!("Error refreshing available nodes: {:?}", e);
         };
 
-        // dial all rpc nodes
-        for rpc_addr in self.dria_nodes.rpc_nodes.iter() {
-            log::info!("Dialling RPC node: {}", rpc_addr);
-            if let Err(e) = self.p2p.dial(rpc_addr.clone()).await {
-                log::warn!("Error dialling RPC node: {:?}", e);
-            };
-        }
+        // reserve all rpc nodes, the new ones are dialled & all of them are redialled when disconnected
+        let rpc_addrs = self.dria_nodes.rpc_nodes.iter().cloned().collect();
+        if let Err(e) = self.p2p.reserve(ReservedPeerKind::Rpc, rpc_addrs).await {
+            log::warn!("Error reserving RPC nodes: {:?}", e);
+        };
 
         log::info!("Finished refreshing!");
     }
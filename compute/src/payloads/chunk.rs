@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// Announcement of a task result that is too large for a single message,
+/// which is published as a sequence of [`TaskResultChunk`] messages instead.
+///
+/// Once all chunks are received, their concatenation is the JSON of the [`TaskResponsePayload`](super::TaskResponsePayload)
+/// that would otherwise be the payload of a single message, and its SHA256 digest must match `digest`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskResultAnnouncement {
+    /// The unique identifier of the task.
+    pub task_id: String,
+    /// The unique identifier of the transfer, referenced by the chunks.
+    pub transfer_id: String,
+    /// Number of chunks.
+    pub chunks: u32,
+    /// Total size of the payload in bytes.
+    pub size: usize,
+    /// SHA256 digest of the payload, hexadecimally encoded.
+    pub digest: String,
+}
+
+/// A chunk of a large task result, see [`TaskResultAnnouncement`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskResultChunk {
+    /// The unique identifier of the transfer.
+    pub transfer_id: String,
+    /// Index of the chunk, starting from 0.
+    pub index: u32,
+    /// Bytes of the chunk, base64 encoded.
+    pub data: String,
+}
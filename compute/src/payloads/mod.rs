@@ -1,3 +1,6 @@
+mod chunk;
+pub use chunk::{TaskResultAnnouncement, TaskResultChunk};
+
 mod error;
 pub use error::TaskErrorPayload;
 
@@ -5,7 +8,7 @@ mod request;
 pub use request::TaskRequestPayload;
 
 mod response;
-pub use response::TaskResponsePayload;
+pub use response::{TaskResponsePayload, VerifiedTaskResponse};
 
 mod stats;
 pub use stats::TaskStats;
@@ -1,5 +1,8 @@
-use crate::utils::crypto::{encrypt_bytes, sha256hash, sign_bytes_recoverable};
-use eyre::Result;
+use crate::utils::crypto::{
+    decrypt_bytes, encrypt_bytes, sha256hash, sign_bytes_recoverable, to_address,
+    verify_bytes_recoverable,
+};
+use eyre::{Context, Result};
 use libsecp256k1::{PublicKey, SecretKey};
 use serde::{Deserialize, Serialize};
 
@@ -55,6 +58,47 @@ impl TaskResponsePayload {
             stats,
         })
     }
+
+    /// Verifies the payload of a computation result, to be used by the requester.
+    ///
+    /// - Decrypt `ciphertext` with `task_secret_key`
+    /// - Verify the signature over `task_id || result`
+    /// - Recover the public key & address of the signer
+    ///
+    /// The caller is expected to check the returned signer against the expected node, if any.
+    pub fn verify(&self, task_secret_key: &SecretKey) -> Result<VerifiedTaskResponse> {
+        let result = decrypt_bytes(&self.ciphertext, task_secret_key)
+            .wrap_err("could not decrypt result")?;
+
+        // create the message `task_id || result`
+        let mut preimage = Vec::new();
+        preimage.extend_from_slice(self.task_id.as_ref());
+        preimage.extend_from_slice(&result);
+
+        let public_key = verify_bytes_recoverable(&sha256hash(preimage), &self.signature)
+            .wrap_err("could not verify signature")?;
+        let address = to_address(&public_key);
+
+        Ok(VerifiedTaskResponse {
+            task_id: self.task_id.clone(),
+            result,
+            public_key,
+            address,
+        })
+    }
+}
+
+/// A computation result that has been decrypted & verified, see [`TaskResponsePayload::verify`].
+#[derive(Debug, Clone)]
+pub struct VerifiedTaskResponse {
+    /// The unique identifier of the task.
+    pub task_id: String,
+    /// Decrypted result.
+    pub result: Vec<u8>,
+    /// Public key of the compute node that signed the result.
+    pub public_key: PublicKey,
+    /// Ethereum address of the compute node that signed the result.
+    pub address: [u8; 20],
 }
 
 #[cfg(test)]
@@ -109,4 +153,43 @@ mod tests {
         let recovered_public_key = recover(&message, &signature, &recid).expect("to recover");
         assert_eq!(signer_pk, recovered_public_key, "public key mismatch");
     }
+
+    #[test]
+    fn test_task_response_payload_verify() {
+        const RESULT: &[u8; 44] = b"hey im an LLM and I came up with this output";
+        const MODEL: &str = "gpt-4-turbo";
+
+        let signer_sk = SecretKey::random(&mut thread_rng());
+        let signer_pk = PublicKey::from_secret_key(&signer_sk);
+        let task_sk = SecretKey::random(&mut thread_rng());
+        let task_pk = PublicKey::from_secret_key(&task_sk);
+        let task_id = uuid::Uuid::new_v4().to_string();
+
+        let payload = TaskResponsePayload::new(
+            RESULT,
+            &task_id,
+            &task_pk,
+            &signer_sk,
+            MODEL.to_string(),
+            Default::default(),
+        )
+        .expect("to create payload");
+
+        let verified = payload.verify(&task_sk).expect("to verify");
+        assert_eq!(verified.task_id, task_id);
+        assert_eq!(verified.result, RESULT);
+        assert_eq!(verified.public_key, signer_pk);
+        assert_eq!(verified.address, to_address(&signer_pk));
+
+        // wrong task key can not decrypt
+        let other_sk = SecretKey::random(&mut thread_rng());
+        assert!(payload.verify(&other_sk).is_err());
+
+        // a different task id must not recover the signer
+        let mut tampered = payload.clone();
+        tampered.task_id = uuid::Uuid::new_v4().to_string();
+        assert!(tampered
+            .verify(&task_sk)
+            .map_or(true, |v| v.public_key != signer_pk));
+    }
 }
@@ -1,7 +1,7 @@
 use eyre::Context;
 use serde::{de::DeserializeOwned, Serialize};
 
-mod specs;
+pub mod specs;
 pub use specs::SpecResponder;
 
 mod workflow;
@@ -3,17 +3,18 @@ use crate::utils::Specs;
 use super::IsResponder;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Request {
     /// UUID of the specs request, prevents replay attacks.
     pub request_id: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Response {
-    request_id: String,
+    /// UUID of the specs request that this is a response to.
+    pub request_id: String,
     #[serde(flatten)]
-    specs: Specs,
+    pub specs: Specs,
 }
 
 pub struct SpecResponder;
@@ -0,0 +1,405 @@
+use dkn_utils::get_current_time_nanos;
+use eyre::{eyre, Context, Result};
+use libsecp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::crypto::{sha256hash, verify_bytes_recoverable};
+
+/// A trusted admin public key, with an optional validity window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminKey {
+    /// Public key of the admin.
+    pub public_key: PublicKey,
+    /// Timestamp (nanoseconds) from which the key is valid, if any.
+    pub valid_from: Option<u128>,
+    /// Timestamp (nanoseconds) until which the key is valid, if any.
+    pub valid_until: Option<u128>,
+}
+
+impl AdminKey {
+    /// Creates a key that is valid at all times.
+    pub fn new(public_key: PublicKey) -> Self {
+        Self {
+            public_key,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    /// Returns `true` if the key is valid at the given time (nanoseconds).
+    pub fn is_valid_at(&self, time: u128) -> bool {
+        self.valid_from.map_or(true, |from| from <= time)
+            && self.valid_until.map_or(true, |until| time < until)
+    }
+}
+
+/// Default file of the last applied list of admin keys, see [`AdminKeysSource::state_path`].
+const DEFAULT_ADMIN_KEYS_STATE_PATH: &str = "admin-keys.json";
+
+/// A set of trusted admin keys, a message signed by any of the currently valid keys is accepted.
+///
+/// The set can be rotated by an admin-signed list served by Dria, see [`refresh_admin_keys`].
+#[derive(Debug, Clone, Default)]
+pub struct AdminKeys {
+    keys: Vec<AdminKey>,
+    /// Issue time (nanoseconds) of the signed list that these keys come from, `0` for the configured keys.
+    issued_at: u128,
+}
+
+impl AdminKeys {
+    /// Creates a new set of admin keys.
+    pub fn new(keys: Vec<AdminKey>) -> Self {
+        Self { keys, issued_at: 0 }
+    }
+
+    /// Returns the issue time (nanoseconds) of the signed list that these keys come from.
+    pub fn issued_at(&self) -> u128 {
+        self.issued_at
+    }
+
+    /// Returns all keys, including the ones that are not valid at the moment.
+    pub fn keys(&self) -> &[AdminKey] {
+        &self.keys
+    }
+
+    /// Returns the public keys that are valid at the current time.
+    pub fn valid_keys(&self) -> Vec<&PublicKey> {
+        let now = get_current_time_nanos();
+        self.keys
+            .iter()
+            .filter(|key| key.is_valid_at(now))
+            .map(|key| &key.public_key)
+            .collect()
+    }
+
+    /// Parses and verifies a signed list of admin keys.
+    ///
+    /// The `data` is a JSON object with the `keys` array and their `issuedAt` time in nanoseconds,
+    /// and the `signature` is a 65-byte recoverable signature over the SHA256 of `data`, which must
+    /// belong to one of the currently valid keys.
+    ///
+    /// Lists that are not issued after the current one are rejected, so that a key rotation
+    /// can not be rolled back (or re-applied) by replaying a list.
+    pub fn try_from_signed(&self, data: &str, signature: &str) -> Result<Self> {
+        let signer = verify_bytes_recoverable(&sha256hash(data), signature)
+            .wrap_err("could not verify admin keys signature")?;
+        if !self.valid_keys().contains(&&signer) {
+            return Err(eyre!(
+                "admin keys are signed by an untrusted key: 0x{}",
+                hex::encode(signer.serialize_compressed())
+            ));
+        }
+
+        let admin_keys = Self::try_from_data(data)?;
+        if admin_keys.issued_at <= self.issued_at {
+            return Err(eyre!(
+                "admin keys are issued at {}, not after the current ones at {}",
+                admin_keys.issued_at,
+                self.issued_at
+            ));
+        }
+
+        Ok(admin_keys)
+    }
+
+    /// Parses a list of admin keys without verifying it, see [`AdminKeys::try_from_signed`].
+    fn try_from_data(data: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct AdminKeyList {
+            issued_at: u128,
+            keys: Vec<AdminKeyEntry>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct AdminKeyEntry {
+            public_key: String,
+            valid_from: Option<u128>,
+            valid_until: Option<u128>,
+        }
+
+        let list: AdminKeyList =
+            serde_json::from_str(data).wrap_err("could not parse admin keys")?;
+        let keys = list
+            .keys
+            .into_iter()
+            .map(|entry| {
+                let public_key_bytes = hex::decode(entry.public_key.trim_start_matches("0x"))?;
+                Ok(AdminKey {
+                    public_key: PublicKey::parse_slice(&public_key_bytes, None)?,
+                    valid_from: entry.valid_from,
+                    valid_until: entry.valid_until,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            keys,
+            issued_at: list.issued_at,
+        })
+    }
+}
+
+impl From<Vec<PublicKey>> for AdminKeys {
+    fn from(public_keys: Vec<PublicKey>) -> Self {
+        Self::new(public_keys.into_iter().map(AdminKey::new).collect())
+    }
+}
+
+/// Where the admin keys are refreshed from, see [`refresh_admin_keys`].
+#[derive(Debug, Clone)]
+pub struct AdminKeysSource {
+    /// URL of the signed admin keys API, the keys are not refreshed if `None`.
+    pub url: Option<String>,
+    /// File of the last applied list, so that the keys are restored and an older list
+    /// can not be replayed after a restart, see [`load_admin_keys`].
+    ///
+    /// Disabled if `None`.
+    pub state_path: Option<PathBuf>,
+}
+
+impl Default for AdminKeysSource {
+    fn default() -> Self {
+        Self {
+            url: None,
+            state_path: Some(PathBuf::from(DEFAULT_ADMIN_KEYS_STATE_PATH)),
+        }
+    }
+}
+
+impl AdminKeysSource {
+    /// Overrides the source with the environment variables.
+    ///
+    /// The environment variables are:
+    /// - `DKN_ADMIN_KEYS_URL`: URL of the signed admin keys API, refreshing is disabled if empty
+    /// - `DKN_ADMIN_KEYS_STATE`: file of the last applied list, disabled if empty
+    pub fn with_envs(mut self) -> Self {
+        if let Ok(url) = env::var("DKN_ADMIN_KEYS_URL") {
+            self.url = Some(url.trim().to_string()).filter(|url| !url.is_empty());
+        }
+        if let Ok(path) = env::var("DKN_ADMIN_KEYS_STATE") {
+            self.state_path = Some(path.trim())
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from);
+        }
+
+        self
+    }
+
+    /// Sets the URL of the signed admin keys API.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Sets the file of the last applied list, see [`AdminKeysSource::state_path`].
+    pub fn with_state(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_path = Some(path.into());
+        self
+    }
+}
+
+/// A signed list of admin keys, as served by the API and kept in the state file.
+#[derive(Debug, Serialize, Deserialize)]
+struct SignedAdminKeys {
+    /// Stringified JSON object of the admin keys and their issue time.
+    data: String,
+    /// Signature over the SHA256 of `data`, hexadecimally encoded.
+    signature: String,
+}
+
+/// Restores the last applied list of admin keys from the state file, if it is newer than the given keys.
+///
+/// The list is verified before it is saved, so it is not verified again.
+/// Nothing is restored if refreshing is disabled, i.e. the source has no URL.
+pub fn load_admin_keys(admin_keys: &mut AdminKeys, source: &AdminKeysSource) -> Result<()> {
+    let (Some(_), Some(state_path)) = (&source.url, &source.state_path) else {
+        return Ok(());
+    };
+
+    let data = match std::fs::read(state_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).wrap_err("could not read admin keys state"),
+    };
+    let state: SignedAdminKeys =
+        serde_json::from_slice(&data).wrap_err("could not parse admin keys state")?;
+    let restored = AdminKeys::try_from_data(&state.data)?;
+
+    if restored.issued_at > admin_keys.issued_at && !restored.valid_keys().is_empty() {
+        *admin_keys = restored;
+    }
+    Ok(())
+}
+
+/// Refresh the admin keys using the API of the source, does nothing if it has no URL.
+///
+/// The new list replaces the existing one only if it is signed by a currently valid key,
+/// it is issued after the existing one, and it has at least one valid key in it.
+/// The applied list is saved to the state file of the source, see [`load_admin_keys`].
+pub async fn refresh_admin_keys(
+    admin_keys: &mut AdminKeys,
+    source: &AdminKeysSource,
+) -> Result<()> {
+    let Some(url) = &source.url else {
+        return Ok(());
+    };
+
+    // make the request
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let response_body = response.json::<SignedAdminKeys>().await?;
+
+    // the same list is served until the keys are rotated, there is nothing to do for it
+    if AdminKeys::try_from_data(&response_body.data)?.issued_at == admin_keys.issued_at {
+        log::debug!("Admin keys are up to date.");
+        return Ok(());
+    }
+
+    let new_keys = admin_keys.try_from_signed(&response_body.data, &response_body.signature)?;
+    if new_keys.valid_keys().is_empty() {
+        return Err(eyre!("refreshed admin keys have no valid keys"));
+    }
+
+    if let Some(state_path) = &source.state_path {
+        save_state(state_path, &response_body)?;
+    }
+    *admin_keys = new_keys;
+    Ok(())
+}
+
+/// Saves the signed list to the state file atomically, by renaming a synced temporary file.
+fn save_state(path: &Path, signed: &SignedAdminKeys) -> Result<()> {
+    let data = serde_json::to_vec_pretty(signed).wrap_err("could not serialize admin keys")?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).wrap_err("could not create admin keys state directory")?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp_path).wrap_err("could not write admin keys state")?;
+    file.write_all(&data)
+        .and_then(|_| file.sync_all())
+        .wrap_err("could not write admin keys state")?;
+    std::fs::rename(&tmp_path, path).wrap_err("could not write admin keys state")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::sign_bytes_recoverable;
+    use libsecp256k1::SecretKey;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_admin_key_validity() {
+        let pk = PublicKey::from_secret_key(&SecretKey::random(&mut thread_rng()));
+
+        let key = AdminKey::new(pk);
+        assert!(key.is_valid_at(0));
+        assert!(key.is_valid_at(u128::MAX));
+
+        let key = AdminKey {
+            public_key: pk,
+            valid_from: Some(100),
+            valid_until: Some(200),
+        };
+        assert!(!key.is_valid_at(99));
+        assert!(key.is_valid_at(100));
+        assert!(key.is_valid_at(199));
+        assert!(!key.is_valid_at(200));
+    }
+
+    #[test]
+    fn test_admin_keys_signed_list() {
+        let old_sk = SecretKey::random(&mut thread_rng());
+        let old_pk = PublicKey::from_secret_key(&old_sk);
+        let new_sk = SecretKey::random(&mut thread_rng());
+        let new_pk = PublicKey::from_secret_key(&new_sk);
+        let admin_keys = AdminKeys::from(vec![old_pk]);
+
+        // old key expires, new key is valid from now on
+        let now = get_current_time_nanos();
+        let data = serde_json::json!({
+            "issuedAt": now,
+            "keys": [
+                {
+                    "publicKey": hex::encode(old_pk.serialize_compressed()),
+                    "validUntil": now + 1_000_000_000,
+                },
+                {
+                    "publicKey": hex::encode(new_pk.serialize_compressed()),
+                    "validFrom": now,
+                },
+            ],
+        })
+        .to_string();
+
+        // signed by a trusted key
+        let signature = sign_bytes_recoverable(&sha256hash(&data), &old_sk);
+        let refreshed = admin_keys
+            .try_from_signed(&data, &signature)
+            .expect("should verify");
+        assert_eq!(refreshed.keys().len(), 2);
+        assert_eq!(refreshed.valid_keys(), vec![&old_pk, &new_pk]);
+        assert_eq!(refreshed.issued_at(), now);
+
+        // an older list can not be replayed after a newer one
+        let old_data = serde_json::json!({
+            "issuedAt": now - 1,
+            "keys": [{ "publicKey": hex::encode(old_pk.serialize_compressed()) }],
+        })
+        .to_string();
+        let old_signature = sign_bytes_recoverable(&sha256hash(&old_data), &old_sk);
+        assert!(admin_keys
+            .try_from_signed(&old_data, &old_signature)
+            .is_ok());
+        assert!(refreshed
+            .try_from_signed(&old_data, &old_signature)
+            .is_err());
+
+        // nor can the same list be applied again
+        assert!(refreshed.try_from_signed(&data, &signature).is_err());
+
+        // signed by an untrusted key
+        let signature = sign_bytes_recoverable(&sha256hash(&data), &new_sk);
+        assert!(admin_keys.try_from_signed(&data, &signature).is_err());
+    }
+
+    #[test]
+    fn test_admin_keys_state() {
+        let path =
+            std::env::temp_dir().join(format!("dkn-admin-keys-{}.json", get_current_time_nanos()));
+        let sk = SecretKey::random(&mut thread_rng());
+        let pk = PublicKey::from_secret_key(&sk);
+        let other_pk = PublicKey::from_secret_key(&SecretKey::random(&mut thread_rng()));
+        let configured = AdminKeys::from(vec![pk]);
+
+        let data = serde_json::json!({
+            "issuedAt": 42,
+            "keys": [{ "publicKey": hex::encode(other_pk.serialize_compressed()) }],
+        })
+        .to_string();
+        let signature = sign_bytes_recoverable(&sha256hash(&data), &sk);
+        save_state(&path, &SignedAdminKeys { data, signature }).unwrap();
+
+        // nothing is restored if refreshing is disabled
+        let source = AdminKeysSource::default().with_state(&path);
+        let mut admin_keys = configured.clone();
+        load_admin_keys(&mut admin_keys, &source).unwrap();
+        assert_eq!(admin_keys.issued_at(), 0);
+
+        // the applied list is restored, along with its issue time
+        let source = source.with_url("http://127.0.0.1:1/admin-keys");
+        let mut admin_keys = configured.clone();
+        load_admin_keys(&mut admin_keys, &source).unwrap();
+        assert_eq!(admin_keys.issued_at(), 42);
+        assert_eq!(admin_keys.valid_keys(), vec![&other_pk]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,522 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use dkn_p2p::libp2p::PeerId;
+use dkn_utils::get_current_time_nanos;
+use eyre::{eyre, Context, Result};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::payloads::{TaskResultAnnouncement, TaskResultChunk};
+use crate::utils::compression::MAX_DECOMPRESSED_SIZE;
+use crate::utils::crypto::sha256hash;
+
+/// Maximum size of a chunked payload, larger ones are neither sent nor reassembled.
+pub const MAX_CHUNKED_SIZE: usize = MAX_DECOMPRESSED_SIZE;
+
+/// Maximum number of chunks of a payload, larger ones are neither sent nor reassembled.
+pub const MAX_CHUNKS: u32 = 1024;
+
+/// Bytes reserved for the envelope of a chunk message, i.e. the topic, signatures and field names.
+const CHUNK_ENVELOPE_SIZE: usize = 2 << 10; // 2 KB
+
+/// Returns the number of payload bytes that fit in a single chunk message,
+/// w.r.t the maximum size of a Gossipsub message.
+///
+/// The chunk bytes are base64 encoded within the chunk, and the signed chunk is base64 encoded
+/// again within the message, so a chunk takes up `16 / 9` of its size in the worst case.
+pub fn chunk_size(max_transmit_size: usize) -> usize {
+    (max_transmit_size.saturating_sub(CHUNK_ENVELOPE_SIZE) * 9 / 16).max(1)
+}
+
+/// Returns `true` if a payload of the given size fits in a single message without chunking,
+/// assuming the JSON envelope where the payload is base64 encoded.
+pub fn fits_in_message(payload_len: usize, max_transmit_size: usize) -> bool {
+    payload_len.div_ceil(3) * 4 + CHUNK_ENVELOPE_SIZE <= max_transmit_size
+}
+
+/// Splits the payload into chunks of at most `chunk_size` bytes, along with
+/// the announcement that references them under a new transfer id.
+pub fn split_payload(
+    task_id: &str,
+    payload: &[u8],
+    chunk_size: usize,
+) -> Result<(TaskResultAnnouncement, Vec<TaskResultChunk>)> {
+    if payload.is_empty() {
+        return Err(eyre!("payload is empty"));
+    }
+    if payload.len() > MAX_CHUNKED_SIZE {
+        return Err(eyre!(
+            "payload of {} bytes exceeds {} bytes",
+            payload.len(),
+            MAX_CHUNKED_SIZE
+        ));
+    }
+
+    let chunk_size = chunk_size.max(1);
+    if payload.len().div_ceil(chunk_size) > MAX_CHUNKS as usize {
+        return Err(eyre!(
+            "payload of {} bytes exceeds {} chunks of {} bytes",
+            payload.len(),
+            MAX_CHUNKS,
+            chunk_size
+        ));
+    }
+
+    let transfer_id = uuid::Uuid::new_v4().to_string();
+    let chunks = payload
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(index, data)| TaskResultChunk {
+            transfer_id: transfer_id.clone(),
+            index: index as u32,
+            data: BASE64_STANDARD.encode(data),
+        })
+        .collect::<Vec<_>>();
+
+    let announcement = TaskResultAnnouncement {
+        task_id: task_id.to_string(),
+        transfer_id,
+        chunks: chunks.len() as u32,
+        size: payload.len(),
+        digest: hex::encode(sha256hash(payload)),
+    };
+
+    Ok((announcement, chunks))
+}
+
+/// A transfer that is being reassembled.
+#[derive(Debug, Default)]
+struct PendingTransfer {
+    /// The announcement, if received; chunks may arrive before it.
+    announcement: Option<TaskResultAnnouncement>,
+    /// Received chunks by their index.
+    chunks: HashMap<u32, Vec<u8>>,
+    /// Total size of the received chunks.
+    size: usize,
+    /// Time of the first received message in nanoseconds.
+    started_at: u128,
+}
+
+/// Reassembles the chunked task results, see [`TaskResultAnnouncement`].
+///
+/// Gossipsub does not preserve the order of the messages, so the announcement & chunks
+/// are accepted in any order, and a transfer is completed once all of them are received.
+/// Incomplete transfers are dropped after a timeout, or when there are too many of them.
+///
+/// Transfers are kept per signer of the messages, so that a chunk can only be a part of
+/// a transfer that is announced by the same signer.
+#[derive(Debug)]
+pub struct ChunkAssembler {
+    /// Pending transfers by their signer & transfer id.
+    transfers: HashMap<(PeerId, String), PendingTransfer>,
+    /// Maximum number of pending transfers.
+    capacity: usize,
+    /// Maximum number of pending transfers of a single signer.
+    capacity_per_signer: usize,
+    /// Time after which an incomplete transfer is dropped.
+    timeout: Duration,
+}
+
+impl Default for ChunkAssembler {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY, Self::DEFAULT_TIMEOUT)
+    }
+}
+
+impl ChunkAssembler {
+    /// Default maximum number of pending transfers.
+    pub const DEFAULT_CAPACITY: usize = 16;
+    /// Default maximum number of pending transfers of a single signer.
+    pub const DEFAULT_CAPACITY_PER_SIGNER: usize = 2;
+    /// Default timeout of a transfer.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+    /// Creates a new assembler with the given capacity & timeout.
+    pub fn new(capacity: usize, timeout: Duration) -> Self {
+        Self {
+            transfers: HashMap::new(),
+            capacity,
+            capacity_per_signer: Self::DEFAULT_CAPACITY_PER_SIGNER.min(capacity),
+            timeout,
+        }
+    }
+
+    /// Number of pending transfers.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.transfers.len()
+    }
+
+    /// Returns `true` if there are no pending transfers.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+
+    /// Records the announcement of a transfer by the given signer.
+    ///
+    /// Returns the announcement & the reassembled payload if all chunks were already received.
+    pub fn announce(
+        &mut self,
+        signer: PeerId,
+        announcement: TaskResultAnnouncement,
+    ) -> Result<Option<(TaskResultAnnouncement, Vec<u8>)>> {
+        if announcement.size > MAX_CHUNKED_SIZE {
+            return Err(eyre!(
+                "transfer {} of {} bytes exceeds {} bytes",
+                announcement.transfer_id,
+                announcement.size,
+                MAX_CHUNKED_SIZE
+            ));
+        }
+        // chunks are not empty, so there can not be more of them than bytes
+        if announcement.chunks == 0
+            || announcement.chunks > MAX_CHUNKS
+            || announcement.chunks as usize > announcement.size
+        {
+            return Err(eyre!(
+                "transfer {} of {} bytes has {} chunks",
+                announcement.transfer_id,
+                announcement.size,
+                announcement.chunks
+            ));
+        }
+
+        let key = (signer, announcement.transfer_id.clone());
+        let transfer = self.entry(&key);
+        if transfer.announcement.is_some() {
+            return Ok(None);
+        }
+        if let Some(index) = transfer
+            .chunks
+            .keys()
+            .copied()
+            .find(|index| *index >= announcement.chunks)
+        {
+            self.transfers.remove(&key);
+            return Err(eyre!(
+                "transfer {} has chunk {} out of {}",
+                key.1,
+                index,
+                announcement.chunks
+            ));
+        }
+        if transfer.size > announcement.size {
+            self.transfers.remove(&key);
+            return Err(eyre!(
+                "transfer {} exceeds its {} bytes",
+                key.1,
+                announcement.size
+            ));
+        }
+        transfer.announcement = Some(announcement);
+
+        self.try_complete(&key)
+    }
+
+    /// Records a chunk of a transfer by the given signer.
+    ///
+    /// Returns the announcement & the reassembled payload if this was the last missing piece.
+    pub fn insert(
+        &mut self,
+        signer: PeerId,
+        chunk: TaskResultChunk,
+    ) -> Result<Option<(TaskResultAnnouncement, Vec<u8>)>> {
+        let data = BASE64_STANDARD
+            .decode(&chunk.data)
+            .wrap_err("could not decode chunk")?;
+        if data.is_empty() {
+            return Err(eyre!(
+                "chunk {} of transfer {} is empty",
+                chunk.index,
+                chunk.transfer_id
+            ));
+        }
+        if chunk.index >= MAX_CHUNKS {
+            return Err(eyre!(
+                "chunk {} exceeds {} chunks for transfer {}",
+                chunk.index,
+                MAX_CHUNKS,
+                chunk.transfer_id
+            ));
+        }
+
+        let key = (signer, chunk.transfer_id);
+        let transfer = self.entry(&key);
+        let max_size = match &transfer.announcement {
+            Some(announcement) if chunk.index >= announcement.chunks => {
+                return Err(eyre!(
+                    "chunk {} out of {} for transfer {}",
+                    chunk.index,
+                    announcement.chunks,
+                    key.1
+                ));
+            }
+            Some(announcement) => announcement.size,
+            None => MAX_CHUNKED_SIZE,
+        };
+        if transfer.chunks.contains_key(&chunk.index) {
+            return Ok(None);
+        }
+        if transfer.size + data.len() > max_size {
+            self.transfers.remove(&key);
+            return Err(eyre!("transfer {} exceeds {} bytes", key.1, max_size));
+        }
+        transfer.size += data.len();
+        transfer.chunks.insert(chunk.index, data);
+
+        self.try_complete(&key)
+    }
+
+    /// Removes the transfers that have timed out.
+    pub fn prune(&mut self) {
+        let now = get_current_time_nanos();
+        let timeout = self.timeout.as_nanos();
+        self.transfers
+            .retain(|_, transfer| now.saturating_sub(transfer.started_at) < timeout);
+    }
+
+    /// Returns the pending transfer with the given key, creating it if needed.
+    ///
+    /// If the signer has too many pending transfers, its oldest one is dropped to make room;
+    /// otherwise if there are too many pending transfers overall, the oldest one is dropped.
+    fn entry(&mut self, key: &(PeerId, String)) -> &mut PendingTransfer {
+        if !self.transfers.contains_key(key) {
+            self.prune();
+
+            let signer_transfers = self
+                .transfers
+                .keys()
+                .filter(|(signer, _)| *signer == key.0)
+                .count();
+            let oldest = if signer_transfers >= self.capacity_per_signer {
+                self.oldest(|signer| *signer == key.0)
+            } else if self.transfers.len() >= self.capacity {
+                self.oldest(|_| true)
+            } else {
+                None
+            };
+            if let Some(oldest) = oldest {
+                log::warn!("Dropping incomplete transfer {} of {}", oldest.1, oldest.0);
+                self.transfers.remove(&oldest);
+            }
+        }
+
+        self.transfers
+            .entry(key.clone())
+            .or_insert_with(|| PendingTransfer {
+                started_at: get_current_time_nanos(),
+                ..Default::default()
+            })
+    }
+
+    /// Returns the key of the oldest pending transfer among the signers that match the filter.
+    fn oldest(&self, filter: impl Fn(&PeerId) -> bool) -> Option<(PeerId, String)> {
+        self.transfers
+            .iter()
+            .filter(|((signer, _), _)| filter(signer))
+            .min_by_key(|(_, transfer)| transfer.started_at)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Reassembles the transfer if it is complete, verifying its size & digest.
+    fn try_complete(
+        &mut self,
+        key: &(PeerId, String),
+    ) -> Result<Option<(TaskResultAnnouncement, Vec<u8>)>> {
+        let is_complete = self.transfers.get(key).is_some_and(|transfer| {
+            transfer
+                .announcement
+                .as_ref()
+                .is_some_and(|a| transfer.chunks.len() == a.chunks as usize)
+        });
+        if !is_complete {
+            return Ok(None);
+        }
+
+        let Some(PendingTransfer {
+            announcement: Some(announcement),
+            mut chunks,
+            ..
+        }) = self.transfers.remove(key)
+        else {
+            return Ok(None);
+        };
+        let transfer_id = &key.1;
+
+        let mut payload = Vec::with_capacity(announcement.size);
+        for index in 0..announcement.chunks {
+            payload.extend(chunks.remove(&index).unwrap_or_default());
+        }
+
+        if payload.len() != announcement.size {
+            return Err(eyre!(
+                "transfer {} has {} bytes instead of {}",
+                transfer_id,
+                payload.len(),
+                announcement.size
+            ));
+        }
+        if hex::encode(sha256hash(&payload)) != announcement.digest {
+            return Err(eyre!("transfer {} has a digest mismatch", transfer_id));
+        }
+
+        Ok(Some((announcement, payload)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_transfer() {
+        let payload = "hello world ".repeat(1000).into_bytes();
+        let (announcement, mut chunks) = split_payload("task-1", &payload, 1000).unwrap();
+        assert_eq!(announcement.chunks, 12);
+        assert_eq!(chunks.len(), 12);
+
+        // chunks arrive in reverse, with a duplicate, and the announcement in the middle
+        chunks.reverse();
+        let signer = PeerId::random();
+        let mut assembler = ChunkAssembler::default();
+        for chunk in chunks.drain(..6) {
+            assert!(assembler.insert(signer, chunk.clone()).unwrap().is_none());
+            assert!(assembler.insert(signer, chunk).unwrap().is_none());
+        }
+        assert!(assembler
+            .announce(signer, announcement.clone())
+            .unwrap()
+            .is_none());
+        let last = chunks.pop().unwrap();
+        for chunk in chunks {
+            assert!(assembler.insert(signer, chunk).unwrap().is_none());
+        }
+
+        let (received, reassembled) = assembler
+            .insert(signer, last)
+            .unwrap()
+            .expect("should complete");
+        assert_eq!(received, announcement);
+        assert_eq!(reassembled, payload);
+        assert!(assembler.is_empty());
+    }
+
+    #[test]
+    fn test_chunked_transfer_tampered() {
+        let payload = "hello world ".repeat(100).into_bytes();
+        let (announcement, mut chunks) = split_payload("task-1", &payload, 100).unwrap();
+        chunks[3].data = BASE64_STANDARD.encode(vec![0u8; 100]);
+
+        let signer = PeerId::random();
+        let mut assembler = ChunkAssembler::default();
+        assert!(assembler
+            .announce(signer, announcement.clone())
+            .unwrap()
+            .is_none());
+        let last = chunks.pop().unwrap();
+        for chunk in chunks {
+            assert!(assembler.insert(signer, chunk).unwrap().is_none());
+        }
+        assert!(assembler.insert(signer, last).is_err());
+
+        // chunks out of range are rejected
+        let (announcement, _) = split_payload("task-2", &payload, 100).unwrap();
+        assert!(assembler
+            .announce(signer, announcement.clone())
+            .unwrap()
+            .is_none());
+        let chunk = TaskResultChunk {
+            transfer_id: announcement.transfer_id.clone(),
+            index: announcement.chunks,
+            data: BASE64_STANDARD.encode([0u8; 10]),
+        };
+        assert!(assembler.insert(signer, chunk).is_err());
+
+        // empty chunks are rejected
+        let chunk = TaskResultChunk {
+            transfer_id: announcement.transfer_id,
+            index: 0,
+            data: String::new(),
+        };
+        assert!(assembler.insert(signer, chunk).is_err());
+    }
+
+    #[test]
+    fn test_chunked_transfer_limits() {
+        let payload = "hello world ".repeat(100).into_bytes();
+        let (announcement, chunks) = split_payload("task-1", &payload, 100).unwrap();
+        let (signer, other) = (PeerId::random(), PeerId::random());
+
+        // chunks of another signer are not a part of the transfer
+        let mut assembler = ChunkAssembler::default();
+        assert!(assembler
+            .announce(signer, announcement.clone())
+            .unwrap()
+            .is_none());
+        for chunk in chunks.clone() {
+            assert!(assembler.insert(other, chunk).unwrap().is_none());
+        }
+        assert_eq!(assembler.len(), 2);
+
+        // the number of chunks is bounded by the size
+        for (chunks, size) in [(0, 10), (MAX_CHUNKS + 1, MAX_CHUNKED_SIZE), (11, 10)] {
+            let announcement = TaskResultAnnouncement {
+                transfer_id: "bogus".to_string(),
+                chunks,
+                size,
+                ..announcement.clone()
+            };
+            assert!(assembler.announce(signer, announcement).is_err());
+        }
+        let chunk = TaskResultChunk {
+            index: MAX_CHUNKS,
+            ..chunks[0].clone()
+        };
+        assert!(assembler.insert(signer, chunk).is_err());
+
+        // a signer can not take up all the pending transfers
+        let mut assembler = ChunkAssembler::default();
+        assert!(assembler
+            .announce(other, announcement.clone())
+            .unwrap()
+            .is_none());
+        for i in 0..ChunkAssembler::DEFAULT_CAPACITY {
+            let (announcement, _) = split_payload(&format!("task-{}", i), &payload, 100).unwrap();
+            assert!(assembler.announce(signer, announcement).unwrap().is_none());
+        }
+        assert_eq!(
+            assembler.len(),
+            ChunkAssembler::DEFAULT_CAPACITY_PER_SIGNER + 1
+        );
+        let completed = chunks
+            .into_iter()
+            .filter_map(|chunk| assembler.insert(other, chunk).unwrap())
+            .count();
+        assert_eq!(completed, 1, "transfer of the other signer is kept");
+    }
+
+    #[test]
+    fn test_chunk_size() {
+        assert!(fits_in_message(1024, 256 << 10));
+        assert!(!fits_in_message(256 << 10, 256 << 10));
+
+        let max_transmit_size = 256 << 10;
+        let payload = vec![0xffu8; 3 * max_transmit_size];
+        let (_, chunks) = split_payload("task-1", &payload, chunk_size(max_transmit_size)).unwrap();
+        assert!(
+            split_payload("task-1", &payload, 1).is_err(),
+            "too many chunks"
+        );
+
+        // a signed & encoded chunk fits in a message
+        let sk = libsecp256k1::SecretKey::random(&mut rand::thread_rng());
+        for chunk in chunks {
+            let message = crate::utils::DriaMessage::new_signed(
+                serde_json::to_string(&chunk).unwrap(),
+                "results",
+                &sk,
+            );
+            assert!(serde_json::to_vec(&message).unwrap().len() <= max_transmit_size);
+        }
+    }
+}
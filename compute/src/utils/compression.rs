@@ -0,0 +1,142 @@
+use eyre::{eyre, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Maximum size of a decompressed payload, larger ones are rejected to avoid decompression bombs.
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 << 20; // 16 MB
+
+/// Payloads smaller than this are sent uncompressed, as compressing them saves little.
+pub const COMPRESSION_MIN_SIZE: usize = 16 << 10; // 16 KB
+
+/// Magic bytes of a zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Stream identifier of the snappy framing format.
+const SNAPPY_MAGIC: [u8; 10] = [0xff, 0x06, 0x00, 0x00, b's', b'N', b'a', b'P', b'p', b'Y'];
+
+/// Compression level of zstd, the default one.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression algorithm of a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// [Zstandard](https://facebook.github.io/zstd/) frame.
+    Zstd,
+    /// [Snappy](https://github.com/google/snappy) framing format.
+    Snappy,
+}
+
+impl Compression {
+    /// Parses the compression from its name, e.g. `zstd` or `snappy`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "zstd" => Some(Self::Zstd),
+            "snappy" => Some(Self::Snappy),
+            _ => None,
+        }
+    }
+
+    /// Detects the compression of the data from its magic bytes, `None` if it is not compressed.
+    ///
+    /// JSON data can not start with these bytes, so this is safe to use for request-response messages.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else if data.starts_with(&SNAPPY_MAGIC) {
+            Some(Self::Snappy)
+        } else {
+            None
+        }
+    }
+
+    /// Compresses the data.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).wrap_err("could not compress"),
+            Self::Snappy => {
+                let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+                encoder.write_all(data).wrap_err("could not compress")?;
+                encoder
+                    .into_inner()
+                    .map_err(|e| eyre!("could not compress: {}", e.error()))
+            }
+        }
+    }
+
+    /// Decompresses the data, failing if the decompressed data is larger than [`MAX_DECOMPRESSED_SIZE`].
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Zstd => read_limited(zstd::stream::read::Decoder::new(data)?),
+            Self::Snappy => read_limited(snap::read::FrameDecoder::new(data)),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Zstd => write!(f, "zstd"),
+            Self::Snappy => write!(f, "snappy"),
+        }
+    }
+}
+
+/// Reads the decompressed data up to [`MAX_DECOMPRESSED_SIZE`], without reading the rest.
+fn read_limited(reader: impl Read) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    reader
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .wrap_err("could not decompress")?;
+
+    if decompressed.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(eyre!(
+            "decompressed data exceeds {} bytes",
+            MAX_DECOMPRESSED_SIZE
+        ));
+    }
+
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        let data = serde_json::json!({ "result": "hello world ".repeat(1000) })
+            .to_string()
+            .into_bytes();
+        assert_eq!(Compression::detect(&data), None);
+
+        for compression in [Compression::Zstd, Compression::Snappy] {
+            let compressed = compression.compress(&data).expect("should compress");
+            assert!(compressed.len() < data.len());
+            assert_eq!(Compression::detect(&compressed), Some(compression));
+            assert_eq!(
+                Compression::from_name(&compression.to_string()),
+                Some(compression)
+            );
+
+            let decompressed = compression
+                .decompress(&compressed)
+                .expect("should decompress");
+            assert_eq!(decompressed, data);
+
+            // garbage is not decompressed
+            assert!(compression.decompress(b"not compressed").is_err());
+        }
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        let data = vec![0u8; MAX_DECOMPRESSED_SIZE + 1];
+
+        for compression in [Compression::Zstd, Compression::Snappy] {
+            let compressed = compression.compress(&data).expect("should compress");
+            assert!(compression.decompress(&compressed).is_err());
+        }
+    }
+}
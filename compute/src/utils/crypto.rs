@@ -1,7 +1,7 @@
 use dkn_p2p::libp2p_identity;
 use ecies::PublicKey;
-use eyre::{Context, Result};
-use libsecp256k1::{Message, SecretKey};
+use eyre::{eyre, Context, Result};
+use libsecp256k1::{Message, RecoveryId, SecretKey, Signature};
 use sha2::{Digest, Sha256};
 use sha3::Keccak256;
 
@@ -52,6 +52,43 @@ pub fn encrypt_bytes(data: impl AsRef<[u8]>, public_key: &PublicKey) -> Result<S
         .map(hex::encode)
 }
 
+/// Shorthand to decrypt a hexadecimal encoded ciphertext with a given secret key.
+/// Returns the plaintext bytes.
+#[inline]
+pub fn decrypt_bytes(ciphertext_hex: impl AsRef<[u8]>, secret_key: &SecretKey) -> Result<Vec<u8>> {
+    let ciphertext = hex::decode(ciphertext_hex).wrap_err("could not decode ciphertext hex")?;
+    ecies::decrypt(&secret_key.serialize(), &ciphertext).wrap_err("could not decrypt data")
+}
+
+/// Verifies a 65-byte hex-string signature (as created by [`sign_bytes_recoverable`]) over the given digest,
+/// and recovers the public key of the signer.
+///
+/// Returns an error if the signature can not be parsed, or if it does not verify against the recovered key.
+pub fn verify_bytes_recoverable(message: &[u8; 32], signature_hex: &str) -> Result<PublicKey> {
+    let signature_bytes = hex::decode(signature_hex).wrap_err("could not decode signature hex")?;
+    if signature_bytes.len() != 65 {
+        return Err(eyre!(
+            "expected 65-byte signature, got {} bytes",
+            signature_bytes.len()
+        ));
+    }
+
+    let signature = Signature::parse_standard_slice(&signature_bytes[..64])
+        .wrap_err("could not parse signature bytes")?;
+    let recid = RecoveryId::parse(signature_bytes[64]).wrap_err("could not parse recovery id")?;
+
+    // recover the signer and verify the signature with respect to it, the latter
+    // is required because `recover` alone does not reject malleable (high-s) signatures
+    let message = Message::parse(message);
+    let public_key = libsecp256k1::recover(&message, &signature, &recid)
+        .wrap_err("could not recover public key")?;
+    if !libsecp256k1::verify(&message, &signature, &public_key) {
+        return Err(eyre!("could not verify signature"));
+    }
+
+    Ok(public_key)
+}
+
 /// Converts a `libsecp256k1::SecretKey` to a `libp2p_identity::secp256k1::Keypair`.
 /// To do this, we serialize the secret key and create a new keypair from it.
 #[inline]
@@ -63,6 +100,15 @@ pub fn secret_to_keypair(secret_key: &SecretKey) -> libp2p_identity::Keypair {
     libp2p_identity::secp256k1::Keypair::from(secret_key).into()
 }
 
+/// Converts a `libsecp256k1::PublicKey` to the `PeerId` of its owner.
+#[inline]
+pub fn public_to_peer_id(public_key: &PublicKey) -> dkn_p2p::libp2p::PeerId {
+    let public_key =
+        libp2p_identity::secp256k1::PublicKey::try_from_bytes(&public_key.serialize_compressed())
+            .expect("Failed to create public key");
+    libp2p_identity::PublicKey::from(public_key).to_peer_id()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,6 +173,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sign_verify_recoverable() {
+        let secret_key =
+            SecretKey::parse_slice(DUMMY_SECRET_KEY).expect("to parse private key slice");
+        let digest = sha256hash(MESSAGE);
+
+        let signature = sign_bytes_recoverable(&digest, &secret_key);
+        let public_key = verify_bytes_recoverable(&digest, &signature).expect("to verify");
+        assert_eq!(public_key, PublicKey::from_secret_key(&secret_key));
+
+        // a different digest should recover a different key
+        let other_digest = sha256hash(b"goodbye world");
+        let other_key = verify_bytes_recoverable(&other_digest, &signature);
+        assert!(other_key.map_or(true, |pk| pk != public_key));
+
+        // the signer is the owner of the peer id of the secret key
+        assert_eq!(
+            public_to_peer_id(&public_key),
+            secret_to_keypair(&secret_key).public().to_peer_id()
+        );
+    }
+
     #[test]
     #[ignore = "run only with profiler if wanted"]
     fn test_memory_usage() {
@@ -0,0 +1,100 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::compression::Compression;
+use crate::utils::{DriaMessage, EnvelopeSignature};
+
+/// The binary form of a [`DriaMessage`], encoded with CBOR.
+///
+/// The payload is kept as raw bytes instead of a base64 string as in JSON, so is the envelope signature
+/// instead of a hex string, and the field names are shortened. The conversions to & from [`DriaMessage`] are lossless,
+/// so that the signatures remain valid in either form.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CborMessage {
+    #[serde(rename = "p", with = "serde_bytes")]
+    pub payload: Vec<u8>,
+    #[serde(rename = "t")]
+    pub topic: String,
+    #[serde(rename = "v")]
+    pub version: String,
+    #[serde(rename = "i", default)]
+    pub identity: String,
+    #[serde(rename = "ts")]
+    pub timestamp: u128,
+    #[serde(rename = "c", default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    #[serde(rename = "s", default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<CborSignature>,
+}
+
+/// The envelope signature with raw bytes, see [`EnvelopeSignature`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CborSignature {
+    #[serde(rename = "v")]
+    pub version: u8,
+    /// 65-byte signature & recovery id.
+    #[serde(rename = "s", with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl CborMessage {
+    /// Encodes the message into CBOR bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(self, &mut bytes).wrap_err("could not encode CBOR message")?;
+        Ok(bytes)
+    }
+
+    /// Decodes a message from CBOR bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ciborium::from_reader(bytes).wrap_err("could not decode CBOR message")
+    }
+}
+
+impl TryFrom<&DriaMessage> for CborMessage {
+    type Error = eyre::Report;
+
+    fn try_from(message: &DriaMessage) -> Result<Self> {
+        Ok(Self {
+            // payload is kept as is, i.e. compressed if it was
+            payload: BASE64_STANDARD
+                .decode(&message.payload)
+                .wrap_err("could not decode payload")?,
+            topic: message.topic.clone(),
+            version: message.version.clone(),
+            identity: message.identity.clone(),
+            timestamp: message.timestamp,
+            compression: message.compression,
+            signature: message
+                .signature
+                .as_ref()
+                .map(|signature| {
+                    Ok::<_, eyre::Report>(CborSignature {
+                        version: signature.version,
+                        signature: hex::decode(&signature.signature)
+                            .wrap_err("could not decode signature")?,
+                    })
+                })
+                .transpose()?,
+        })
+    }
+}
+
+impl From<CborMessage> for DriaMessage {
+    fn from(message: CborMessage) -> Self {
+        Self {
+            payload: BASE64_STANDARD.encode(message.payload),
+            topic: message.topic,
+            version: message.version,
+            identity: message.identity,
+            timestamp: message.timestamp,
+            compression: message.compression,
+            signature: message.signature.map(|signature| EnvelopeSignature {
+                version: signature.version,
+                signature: hex::encode(signature.signature),
+            }),
+            decoded_payload: Default::default(),
+        }
+    }
+}
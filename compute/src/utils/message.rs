@@ -1,18 +1,24 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
 use core::fmt;
+use dkn_p2p::EnvelopeFormat;
 use dkn_utils::get_current_time_nanos;
 use ecies::PublicKey;
-use eyre::{Context, Result};
+use eyre::{eyre, Context, Result};
 use libsecp256k1::{verify, Message, SecretKey, Signature};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::Duration;
 
-use crate::utils::crypto::{sha256hash, sign_bytes_recoverable};
+use crate::utils::compression::Compression;
+use crate::utils::crypto::{sha256hash, sign_bytes_recoverable, verify_bytes_recoverable};
+use crate::utils::envelope::CborMessage;
 use crate::DRIA_COMPUTE_NODE_VERSION;
 
 /// A message within Dria Knowledge Network.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DriaMessage {
-    /// Base64 encoded payload, stores the main result.
+    /// Base64 encoded payload, stores the main result; compressed if [`DriaMessage::compression`] is set.
     pub payload: String,
     /// The topic of the message, derived from `TopicHash`
     ///
@@ -29,6 +35,46 @@ pub struct DriaMessage {
     ///
     /// NOTE: This can be obtained via `DataTransform` in GossipSub
     pub timestamp: u128,
+    /// Compression of the payload, `None` if it is not compressed.
+    ///
+    /// Every node reads compressed payloads, but only the ones that opt in send them, see [`DriaMessage::with_compression`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Signature over all the fields above, see [`DriaMessage::with_envelope_signature`].
+    ///
+    /// This is optional for backward compatibility, older messages are signed within the payload only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<EnvelopeSignature>,
+    /// The decoded & decompressed payload, so that it is decompressed once per message.
+    #[serde(skip)]
+    pub(crate) decoded_payload: DecodedPayload,
+}
+
+/// A cache of the decoded payload, see [`DriaMessage::decode_payload`].
+///
+/// Clones start with an empty cache, as their payload may be changed afterwards.
+#[derive(Default)]
+pub(crate) struct DecodedPayload(OnceLock<Vec<u8>>);
+
+impl Clone for DecodedPayload {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for DecodedPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DecodedPayload")
+    }
+}
+
+/// A signature over the canonical encoding of the message envelope.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnvelopeSignature {
+    /// Version of the signature scheme, describes the canonical encoding.
+    pub version: u8,
+    /// 65-byte signature & recovery id, hexadecimally encoded.
+    pub signature: String,
 }
 
 /// 65-byte signature as hex characters take up 130 characters.
@@ -38,23 +84,39 @@ pub struct DriaMessage {
 /// and therefore use 128 characters: SIGNATURE_SIZE - 2.
 const SIGNATURE_SIZE_HEX: usize = 130;
 
+/// Version of the envelope signature scheme for uncompressed messages.
+///
+/// SHA256 of `scheme || payload || topic || version || identity || timestamp`, where `scheme` is
+/// this version as a single byte, strings are prefixed with their length as 8-byte big-endian,
+/// and the timestamp is 16-byte big-endian. The compression is not covered, so it is only accepted for uncompressed messages.
+const ENVELOPE_SIGNATURE_VERSION: u8 = 1;
+
+/// Version of the envelope signature scheme for compressed messages.
+///
+/// Same as [`ENVELOPE_SIGNATURE_VERSION`], with the name of the compression appended as a string.
+/// Peers that can not read compressed payloads do not know this version either, so it is only used when needed.
+const COMPRESSED_ENVELOPE_SIGNATURE_VERSION: u8 = 2;
+
 impl DriaMessage {
     /// Creates a new message with current timestamp and version equal to the crate version.
     ///
     /// - `data` is given as bytes, it is encoded into base64 to make up the `payload` within.
     /// - `topic` is the name of the [gossipsub topic](https://docs.libp2p.io/concepts/pubsub/overview/).
-    pub(crate) fn new(data: impl AsRef<[u8]>, topic: &str) -> Self {
+    pub fn new(data: impl AsRef<[u8]>, topic: &str) -> Self {
         Self {
             payload: BASE64_STANDARD.encode(data),
             topic: topic.to_string(),
             version: DRIA_COMPUTE_NODE_VERSION.to_string(),
             identity: String::default(),
             timestamp: get_current_time_nanos(),
+            compression: None,
+            signature: None,
+            decoded_payload: DecodedPayload::default(),
         }
     }
 
     /// Creates a new Message by signing the SHA256 of the payload, and prepending the signature.
-    pub(crate) fn new_signed(data: impl AsRef<[u8]>, topic: &str, signing_key: &SecretKey) -> Self {
+    pub fn new_signed(data: impl AsRef<[u8]>, topic: &str, signing_key: &SecretKey) -> Self {
         // sign the SHA256 hash of the data
         let signature_bytes = sign_bytes_recoverable(&sha256hash(data.as_ref()), signing_key);
 
@@ -73,10 +135,104 @@ impl DriaMessage {
         self
     }
 
-    /// Decodes the base64 payload into bytes.
-    #[inline(always)]
-    pub(crate) fn decode_payload(&self) -> Result<Vec<u8>, base64::DecodeError> {
-        BASE64_STANDARD.decode(&self.payload)
+    /// Compresses the payload with the given compression.
+    ///
+    /// The compression is covered by the envelope signature, so like the identity,
+    /// this must be called before [`DriaMessage::with_envelope_signature`].
+    pub(crate) fn with_compression(mut self, compression: Compression) -> Result<Self> {
+        if self.compression.is_some() {
+            return Err(eyre!("payload is already compressed"));
+        }
+
+        let compressed = compression.compress(self.decode_payload()?)?;
+        self.payload = BASE64_STANDARD.encode(compressed);
+        self.compression = Some(compression);
+        Ok(self)
+    }
+
+    /// Signs the entire envelope of the message, i.e. all fields other than the signature.
+    ///
+    /// This must be called last, as any change to the message afterwards will invalidate the signature.
+    pub(crate) fn with_envelope_signature(mut self, signing_key: &SecretKey) -> Result<Self> {
+        let version = match self.compression {
+            Some(_) => COMPRESSED_ENVELOPE_SIGNATURE_VERSION,
+            None => ENVELOPE_SIGNATURE_VERSION,
+        };
+        let digest = self.envelope_digest(version)?;
+        self.signature = Some(EnvelopeSignature {
+            version,
+            signature: sign_bytes_recoverable(&digest, signing_key),
+        });
+        Ok(self)
+    }
+
+    /// Computes the digest of the canonical encoding of the envelope, w.r.t the given scheme version.
+    fn envelope_digest(&self, version: u8) -> Result<[u8; 32]> {
+        let compression = match version {
+            ENVELOPE_SIGNATURE_VERSION if self.compression.is_some() => {
+                return Err(eyre!(
+                    "envelope signature version {} does not cover the compression",
+                    version
+                ))
+            }
+            ENVELOPE_SIGNATURE_VERSION => None,
+            COMPRESSED_ENVELOPE_SIGNATURE_VERSION => {
+                Some(self.compression.map(|c| c.to_string()).unwrap_or_default())
+            }
+            _ => return Err(eyre!("unsupported envelope signature version: {}", version)),
+        };
+
+        let mut preimage = vec![version];
+        for field in [
+            self.payload.as_bytes(),
+            self.topic.as_bytes(),
+            self.version.as_bytes(),
+            self.identity.as_bytes(),
+        ] {
+            preimage.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            preimage.extend_from_slice(field);
+        }
+        preimage.extend_from_slice(&self.timestamp.to_be_bytes());
+        if let Some(compression) = compression {
+            preimage.extend_from_slice(&(compression.len() as u64).to_be_bytes());
+            preimage.extend_from_slice(compression.as_bytes());
+        }
+
+        Ok(sha256hash(preimage))
+    }
+
+    /// Encodes the message into bytes w.r.t the given wire format.
+    pub fn encode(&self, format: EnvelopeFormat) -> Result<Vec<u8>> {
+        match format {
+            EnvelopeFormat::Json => serde_json::to_vec(self).wrap_err("could not encode message"),
+            EnvelopeFormat::Cbor => CborMessage::try_from(self)?.to_bytes(),
+        }
+    }
+
+    /// Decodes a message from bytes, where the wire format is detected from the first byte:
+    /// JSON messages are objects that start with `{`, and CBOR messages are maps.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => serde_json::from_slice(data).wrap_err("could not decode message"),
+            _ => CborMessage::from_bytes(data).map(Into::into),
+        }
+    }
+
+    /// Decodes the base64 payload into bytes, decompressing it if needed.
+    ///
+    /// The result is cached, so the payload is decompressed once even if it is parsed many times.
+    pub(crate) fn decode_payload(&self) -> Result<&[u8]> {
+        if let Some(decoded) = self.decoded_payload.0.get() {
+            return Ok(decoded);
+        }
+
+        let payload = BASE64_STANDARD.decode(&self.payload)?;
+        let decoded = match self.compression {
+            Some(compression) => compression.decompress(&payload)?,
+            None => payload,
+        };
+
+        Ok(self.decoded_payload.0.get_or_init(|| decoded))
     }
 
     /// Decodes and parses the base64 payload into JSON for the provided type `T`.
@@ -85,45 +241,159 @@ impl DriaMessage {
 
         let body = if signed {
             // skips the 65 byte hex signature
-            &payload[SIGNATURE_SIZE_HEX..]
+            payload
+                .get(SIGNATURE_SIZE_HEX..)
+                .ok_or_else(|| eyre!("payload is too short for a signature"))?
         } else {
-            &payload[..]
+            payload
         };
 
         let parsed = serde_json::from_slice::<T>(body)?;
         Ok(parsed)
     }
 
-    /// Checks if the payload is signed by the given public key.
-    pub(crate) fn is_signed(&self, public_key: &PublicKey) -> Result<bool> {
-        // decode base64 payload
-        let data = self.decode_payload()?;
+    /// Returns the hex-encoded signature of a signed message, i.e. the first 130 characters of the decoded payload.
+    pub(crate) fn signature(&self) -> Result<String> {
+        let payload = self.decode_payload()?;
+        let signature = payload
+            .get(..SIGNATURE_SIZE_HEX)
+            .ok_or_else(|| eyre!("payload is too short for a signature"))?;
 
-        // parse signature from the following bytes:
-        //    32   +   32  +     1      +  ...
-        // (  x   ||   y   ||  rec_id  || data
-        let (signature_hex_bytes, body) =
-            (&data[..SIGNATURE_SIZE_HEX - 2], &data[SIGNATURE_SIZE_HEX..]);
-        let signature_bytes =
-            hex::decode(signature_hex_bytes).wrap_err("could not decode signature hex")?;
+        String::from_utf8(signature.to_vec()).wrap_err("could not read signature")
+    }
+
+    /// Checks if the timestamp of the message is at most `max_age` in the past,
+    /// and at most `max_skew` in the future w.r.t the local time.
+    pub(crate) fn is_timestamp_within(&self, max_age: Duration, max_skew: Duration) -> bool {
+        let current_time = get_current_time_nanos();
+
+        // the timestamp is given by the sender, so it may overflow
+        self.timestamp.saturating_add(max_age.as_nanos()) >= current_time
+            && self.timestamp <= current_time.saturating_add(max_skew.as_nanos())
+    }
+
+    /// Checks if the message is signed by the given public key, see [`DriaMessage::is_signed_by_any`].
+    pub fn is_signed(&self, public_key: &PublicKey) -> Result<bool> {
+        self.is_signed_by_any([public_key])
+    }
+
+    /// Checks if the message is signed by any of the given public keys over its entire envelope.
+    ///
+    /// Unlike [`DriaMessage::is_signed_by_any`], messages that are signed within the payload only are not accepted,
+    /// as their topic, timestamp & identity can be changed by any peer that relays them.
+    pub fn is_envelope_signed_by_any<'a>(
+        &self,
+        public_keys: impl IntoIterator<Item = &'a PublicKey>,
+    ) -> Result<bool> {
+        if self.signature.is_none() {
+            return Ok(false);
+        }
+
+        self.is_signed_by_any(public_keys)
+    }
+
+    /// Checks if the message is signed by any of the given public keys.
+    ///
+    /// If the message has an envelope signature, it is verified against the entire envelope;
+    /// otherwise, the signature within the payload is verified against the payload body only.
+    pub fn is_signed_by_any<'a>(
+        &self,
+        public_keys: impl IntoIterator<Item = &'a PublicKey>,
+    ) -> Result<bool> {
+        let (signature, digest) = self.signed_digest()?;
+        let signature_hex_bytes = signature
+            .get(..SIGNATURE_SIZE_HEX - 2)
+            .ok_or_else(|| eyre!("signature is too short"))?;
 
         // now obtain the signature itself
+        let signature_bytes =
+            hex::decode(signature_hex_bytes).wrap_err("could not decode signature hex")?;
         let signature = Signature::parse_standard_slice(&signature_bytes)
             .wrap_err("could not parse signature bytes")?;
 
-        // verify signature w.r.t the body and the given public key
-        let digest = Message::parse(&sha256hash(body));
-        Ok(verify(&digest, &signature, public_key))
+        // verify signature w.r.t the digest and the given public keys
+        let digest = Message::parse(&digest);
+        Ok(public_keys
+            .into_iter()
+            .any(|public_key| verify(&digest, &signature, public_key)))
+    }
+
+    /// Recovers the public key that has signed the message, w.r.t the same signature as [`DriaMessage::is_signed_by_any`].
+    pub fn signer(&self) -> Result<PublicKey> {
+        let (signature, digest) = self.signed_digest()?;
+        let signature = std::str::from_utf8(signature).wrap_err("could not read signature")?;
+
+        verify_bytes_recoverable(&digest, signature)
+    }
+
+    /// Returns the hex-encoded signature of the message along with the digest that it signs.
+    ///
+    /// If the message has an envelope signature, the digest is of the entire envelope;
+    /// otherwise, the signature is within the payload and the digest is of the payload body only.
+    fn signed_digest(&self) -> Result<(&[u8], [u8; 32])> {
+        match &self.signature {
+            Some(envelope) => Ok((
+                envelope.signature.as_bytes(),
+                self.envelope_digest(envelope.version)?,
+            )),
+            None => {
+                // decode base64 payload
+                let data = self.decode_payload()?;
+
+                // parse signature from the following bytes:
+                //    32   +   32  +     1      +  ...
+                // (  x   ||   y   ||  rec_id  || data
+                let (Some(signature), Some(body)) = (
+                    data.get(..SIGNATURE_SIZE_HEX),
+                    data.get(SIGNATURE_SIZE_HEX..),
+                ) else {
+                    return Err(eyre!("payload is too short for a signature"));
+                };
+
+                Ok((signature, sha256hash(body)))
+            }
+        }
+    }
+}
+
+/// The signers that are known to sign the envelopes of their messages.
+///
+/// Once a signer is seen with an envelope signature, its messages that are signed within the payload only
+/// are rejected, so that a relaying peer can not strip the envelope signature to change the topic or timestamp.
+#[derive(Debug, Default)]
+pub struct EnvelopeSigners(HashSet<[u8; 33]>);
+
+impl EnvelopeSigners {
+    /// Checks if the message is signed by any of the given public keys, see [`DriaMessage::is_signed_by_any`];
+    /// a message without an envelope signature is not accepted if its signer is known to sign envelopes.
+    pub fn is_signed_by_any<'a>(
+        &mut self,
+        message: &DriaMessage,
+        public_keys: impl IntoIterator<Item = &'a PublicKey>,
+    ) -> Result<bool> {
+        let signer = message.signer()?;
+        if !public_keys
+            .into_iter()
+            .any(|public_key| public_key == &signer)
+        {
+            return Ok(false);
+        }
+
+        let signer = signer.serialize_compressed();
+        if message.signature.is_some() {
+            self.0.insert(signer);
+            Ok(true)
+        } else {
+            Ok(!self.0.contains(&signer))
+        }
     }
 }
 
 impl fmt::Display for DriaMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let payload_decoded = self
-            .decode_payload()
-            .unwrap_or(self.payload.as_bytes().to_vec());
+        let payload_decoded = self.decode_payload().unwrap_or(self.payload.as_bytes());
 
-        let payload_str = String::from_utf8(payload_decoded).unwrap_or(self.payload.clone());
+        let payload_str = String::from_utf8_lossy(payload_decoded);
         write!(
             f,
             "{} message at {}\n{}",
@@ -133,10 +403,10 @@ impl fmt::Display for DriaMessage {
 }
 
 impl TryFrom<&dkn_p2p::libp2p::gossipsub::Message> for DriaMessage {
-    type Error = serde_json::Error;
+    type Error = eyre::Report;
 
     fn try_from(value: &dkn_p2p::libp2p::gossipsub::Message) -> Result<Self, Self::Error> {
-        serde_json::from_slice(&value.data)
+        Self::decode(&value.data)
     }
 }
 
@@ -215,7 +485,221 @@ mod tests {
 
         assert!(message.is_signed(&pk).expect("Should check signature"));
 
+        // should be signed by any of the keys, if the signer is among them
+        let other_pk = PublicKey::from_secret_key(&SecretKey::random(&mut rng));
+        assert!(message
+            .is_signed_by_any([&other_pk, &pk])
+            .expect("Should check signature"));
+        assert!(!message
+            .is_signed_by_any([&other_pk])
+            .expect("Should check signature"));
+        assert_eq!(message.signer().expect("Should recover signer"), pk);
+
         let parsed_body = message.parse_payload(true).expect("Should decode");
         assert_eq!(body, parsed_body);
+
+        let signature = message.signature().expect("Should have signature");
+        assert_eq!(signature.len(), SIGNATURE_SIZE_HEX);
+    }
+
+    #[test]
+    fn test_envelope_signed_message() {
+        let mut rng = thread_rng();
+        let sk = SecretKey::random(&mut rng);
+        let pk = PublicKey::from_secret_key(&sk);
+
+        let body_str = serde_json::to_string(&TestStruct::default()).unwrap();
+        let message = DriaMessage::new_signed(body_str, TOPIC, &sk)
+            .with_identity("dria".to_string())
+            .with_envelope_signature(&sk)
+            .expect("Should sign");
+        assert!(message.is_signed(&pk).expect("Should check signature"));
+        assert_eq!(message.signer().expect("Should recover signer"), pk);
+
+        // should survive serialization
+        let message_bytes = serde_json::to_vec(&message).expect("Should serialize");
+        let message: DriaMessage =
+            serde_json::from_slice(&message_bytes).expect("Should deserialize");
+        assert!(message.is_signed(&pk).expect("Should check signature"));
+
+        // payload is still readable by older nodes
+        let parsed_body: TestStruct = message.parse_payload(true).expect("Should decode");
+        assert_eq!(parsed_body, TestStruct::default());
+
+        // tampering with any field of the envelope is detected
+        let mut tampered = message.clone();
+        tampered.topic = "other-topic".to_string();
+        assert!(!tampered.is_signed(&pk).expect("Should check signature"));
+
+        let mut tampered = message.clone();
+        tampered.timestamp += 1;
+        assert!(!tampered.is_signed(&pk).expect("Should check signature"));
+
+        let mut tampered = message.clone();
+        tampered.version = "0.0.0".to_string();
+        assert!(!tampered.is_signed(&pk).expect("Should check signature"));
+
+        let mut tampered = message.clone();
+        tampered.identity = "other".to_string();
+        assert!(!tampered.is_signed(&pk).expect("Should check signature"));
+
+        // stripping the envelope signature falls back to the payload signature, which does not cover the envelope,
+        // so it is rejected if the envelope signature is required or the signer is known to sign envelopes
+        let mut stripped = message.clone();
+        stripped.signature = None;
+        stripped.topic = "other-topic".to_string();
+        assert!(stripped.is_signed(&pk).expect("Should check signature"));
+        assert!(!stripped
+            .is_envelope_signed_by_any([&pk])
+            .expect("Should check signature"));
+        assert!(message
+            .is_envelope_signed_by_any([&pk])
+            .expect("Should check signature"));
+
+        let mut signers = EnvelopeSigners::default();
+        assert!(signers
+            .is_signed_by_any(&stripped, [&pk])
+            .expect("Should check signature"));
+        assert!(signers
+            .is_signed_by_any(&message, [&pk])
+            .expect("Should check signature"));
+        assert!(!signers
+            .is_signed_by_any(&stripped, [&pk])
+            .expect("Should check signature"));
+        assert!(!signers
+            .is_signed_by_any(&tampered, [&pk])
+            .expect("Should check signature"));
+
+        // short payloads are not accepted, instead of panicking
+        let short = DriaMessage::new(b"too short", TOPIC);
+        assert!(short.is_signed(&pk).is_err());
+
+        // uncompressed messages are signed with the first scheme, so that all peers can verify them
+        assert_eq!(
+            message.signature.as_ref().map(|s| s.version),
+            Some(ENVELOPE_SIGNATURE_VERSION)
+        );
+
+        // unknown scheme versions are not accepted
+        let mut tampered = message.clone();
+        tampered.signature.as_mut().unwrap().version = 3;
+        assert!(tampered.is_signed(&pk).is_err());
+    }
+
+    #[test]
+    fn test_compressed_message() {
+        let mut rng = thread_rng();
+        let sk = SecretKey::random(&mut rng);
+        let pk = PublicKey::from_secret_key(&sk);
+
+        let body = TestStruct {
+            hello: "world".repeat(1000),
+        };
+        let body_str = serde_json::to_string(&body).unwrap();
+        let uncompressed = DriaMessage::new_signed(&body_str, TOPIC, &sk);
+
+        for compression in [Compression::Zstd, Compression::Snappy] {
+            let message = uncompressed
+                .clone()
+                .with_compression(compression)
+                .expect("Should compress")
+                .with_envelope_signature(&sk)
+                .expect("Should sign");
+            assert!(message.payload.len() < uncompressed.payload.len());
+            assert!(message.clone().with_compression(compression).is_err());
+
+            // should survive serialization, with the compression flag
+            let message_bytes = serde_json::to_vec(&message).expect("Should serialize");
+            let message: DriaMessage =
+                serde_json::from_slice(&message_bytes).expect("Should deserialize");
+            assert_eq!(message.compression, Some(compression));
+            assert!(message.is_signed(&pk).expect("Should check signature"));
+            assert_eq!(
+                message.signature().expect("Should have signature"),
+                uncompressed.signature().expect("Should have signature")
+            );
+
+            let parsed_body: TestStruct = message.parse_payload(true).expect("Should decode");
+            assert_eq!(parsed_body, body);
+
+            // the flag is signed, and a tampered one makes the payload unreadable
+            let mut tampered = message.clone();
+            tampered.compression = None;
+            assert!(!tampered.is_signed(&pk).expect("Should check signature"));
+            assert!(tampered.parse_payload::<TestStruct>(true).is_err());
+
+            // the first scheme does not cover the flag, so it is not accepted
+            assert_eq!(
+                message.signature.as_ref().map(|s| s.version),
+                Some(COMPRESSED_ENVELOPE_SIGNATURE_VERSION)
+            );
+            let mut previous = message.clone();
+            previous.signature.as_mut().unwrap().version = ENVELOPE_SIGNATURE_VERSION;
+            assert!(previous.is_signed(&pk).is_err());
+        }
+
+        // uncompressed messages do not have the flag at all, for older peers
+        let message_json = serde_json::to_value(&uncompressed).expect("Should serialize");
+        assert!(message_json.get("compression").is_none());
+    }
+
+    #[test]
+    fn test_cbor_message() {
+        let mut rng = thread_rng();
+        let sk = SecretKey::random(&mut rng);
+        let pk = PublicKey::from_secret_key(&sk);
+
+        let body_str = serde_json::to_string(&TestStruct::default()).unwrap();
+        let uncompressed = DriaMessage::new_signed(body_str, TOPIC, &sk);
+        let compressed = uncompressed
+            .clone()
+            .with_compression(Compression::Zstd)
+            .expect("Should compress");
+
+        for message in [uncompressed, compressed] {
+            let message = message
+                .with_identity("dria".to_string())
+                .with_envelope_signature(&sk)
+                .expect("Should sign");
+            let json = message.encode(EnvelopeFormat::Json).expect("Should encode");
+            let cbor = message.encode(EnvelopeFormat::Cbor).expect("Should encode");
+            assert!(cbor.len() < json.len());
+
+            // both are decoded to the same message, with valid signatures
+            for bytes in [json, cbor] {
+                let decoded = DriaMessage::decode(&bytes).expect("Should decode");
+                assert_eq!(decoded.payload, message.payload);
+                assert_eq!(decoded.signature, message.signature);
+                assert_eq!(decoded.compression, message.compression);
+                assert!(decoded.is_signed(&pk).expect("Should check signature"));
+
+                let parsed_body: TestStruct = decoded.parse_payload(true).expect("Should parse");
+                assert_eq!(parsed_body, TestStruct::default());
+            }
+        }
+
+        assert!(DriaMessage::decode(b"not a message").is_err());
+    }
+
+    #[test]
+    fn test_message_timestamp() {
+        let max_age = Duration::from_secs(60);
+        let max_skew = Duration::from_secs(10);
+
+        let mut message = DriaMessage::new(b"hello world", TOPIC);
+        assert!(message.is_timestamp_within(max_age, max_skew));
+
+        // too old
+        message.timestamp -= Duration::from_secs(120).as_nanos();
+        assert!(!message.is_timestamp_within(max_age, max_skew));
+
+        // too far in the future
+        message.timestamp += Duration::from_secs(180).as_nanos();
+        assert!(!message.is_timestamp_within(max_age, max_skew));
+
+        // does not overflow
+        message.timestamp = u128::MAX;
+        assert!(!message.is_timestamp_within(max_age, max_skew));
+        assert!(!message.is_timestamp_within(Duration::MAX, Duration::MAX));
     }
 }
@@ -1,12 +1,21 @@
+pub mod chunks;
+pub mod compression;
 pub mod crypto;
+pub mod envelope;
 pub mod filter;
 
+mod admins;
+pub use admins::*;
+
 mod message;
-pub use message::DriaMessage;
+pub use message::{DriaMessage, EnvelopeSignature, EnvelopeSigners};
 
 mod misc;
 pub use misc::*;
 
+mod replay;
+pub use replay::ReplayCache;
+
 mod nodes;
 pub use nodes::*;
 
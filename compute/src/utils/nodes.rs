@@ -1,69 +1,392 @@
 use dkn_p2p::{libp2p::PeerId, DriaNetworkType, DriaNodes};
-use dkn_utils::parse_vec;
-use eyre::Result;
+use dkn_utils::{get_current_time_nanos, parse_vec};
+use eyre::{eyre, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-/// Refresh available nodes using the API.
-pub async fn refresh_dria_nodes(nodes: &mut DriaNodes) -> Result<()> {
-    #[derive(serde::Deserialize, Debug)]
-    struct DriaNodesApiResponse {
-        pub bootstraps: Vec<String>,
-        pub relays: Vec<String>,
-        pub rpcs: Vec<String>,
-        #[serde(rename = "rpcAddrs")]
-        pub rpc_addrs: Vec<String>,
+use super::crypto::{sha256hash, verify_bytes_recoverable};
+use super::AdminKeys;
+
+/// Response of the available nodes API, which is also what the cache keeps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriaNodesApiResponse {
+    pub bootstraps: Vec<String>,
+    pub relays: Vec<String>,
+    pub rpcs: Vec<String>,
+    pub rpc_addrs: Vec<String>,
+    /// Time of signing in seconds since the epoch, which is covered by the signature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<u64>,
+    /// Admin signature over [`DriaNodesApiResponse::digest`], hexadecimally encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl DriaNodesApiResponse {
+    /// Signed responses older than this are rejected, so that a cached response can not be used forever.
+    pub const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+    /// Allowed clock difference for the signing time of a response.
+    const MAX_CLOCK_SKEW_SECS: u64 = 60;
+
+    /// Returns the SHA256 of the compact JSON of the response without its signature,
+    /// i.e. of `{"bootstraps":[..],"relays":[..],"rpcs":[..],"rpcAddrs":[..],"issuedAt":..}`.
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let unsigned = Self {
+            signature: None,
+            ..self.clone()
+        };
+        let data = serde_json::to_vec(&unsigned).wrap_err("could not serialize nodes")?;
+        Ok(sha256hash(data))
+    }
+
+    /// Verifies the admin signature of the response.
+    ///
+    /// Unsigned responses are accepted unless `require_signature` is set, but a signed one must be
+    /// signed by a currently valid admin key within [`DriaNodesApiResponse::MAX_AGE`].
+    pub fn verify(&self, admin_keys: &AdminKeys, require_signature: bool) -> Result<()> {
+        let Some(signature) = &self.signature else {
+            if require_signature {
+                return Err(eyre!("available nodes are not signed"));
+            }
+            log::debug!("Available nodes are not signed.");
+            return Ok(());
+        };
+
+        let signer = verify_bytes_recoverable(&self.digest()?, signature)
+            .wrap_err("could not verify available nodes signature")?;
+        if !admin_keys.valid_keys().contains(&&signer) {
+            return Err(eyre!(
+                "available nodes are signed by an untrusted key: 0x{}",
+                hex::encode(signer.serialize_compressed())
+            ));
+        }
+
+        let issued_at = self
+            .issued_at
+            .ok_or_else(|| eyre!("available nodes are signed without a signing time"))?;
+        let now = (get_current_time_nanos() / 1_000_000_000) as u64;
+        if issued_at > now.saturating_add(Self::MAX_CLOCK_SKEW_SECS) {
+            return Err(eyre!(
+                "available nodes are signed in the future: {}",
+                issued_at
+            ));
+        }
+        if now.saturating_sub(issued_at) > Self::MAX_AGE.as_secs() {
+            return Err(eyre!(
+                "available nodes are signed too long ago: {}",
+                issued_at
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the nodes within the response to the given nodes, ignoring the ones that can not be parsed.
+    fn extend(self, nodes: &mut DriaNodes) {
+        nodes
+            .bootstrap_nodes
+            .extend(parse_vec(self.bootstraps).unwrap_or_else(|e| {
+                log::error!("Failed to parse bootstrap nodes: {}", e);
+                vec![]
+            }));
+        nodes
+            .relay_nodes
+            .extend(parse_vec(self.relays).unwrap_or_else(|e| {
+                log::error!("Failed to parse relay nodes: {}", e);
+                vec![]
+            }));
+        nodes
+            .rpc_nodes
+            .extend(parse_vec(self.rpc_addrs).unwrap_or_else(|e| {
+                log::error!("Failed to parse rpc nodes: {}", e);
+                vec![]
+            }));
+        nodes
+            .rpc_peerids
+            .extend(parse_vec::<PeerId>(self.rpcs).unwrap_or_else(|e| {
+                log::error!("Failed to parse rpc peerids: {}", e);
+                vec![]
+            }));
+    }
+}
+
+/// Where the available nodes are refreshed from, see [`refresh_dria_nodes`].
+#[derive(Debug, Clone, Default)]
+pub struct DriaNodesSource {
+    /// URL of the available nodes API, the one of the network is used if `None`.
+    pub url: Option<String>,
+    /// File of the last successful response, which is used when the API is unreachable.
+    ///
+    /// Disabled if `None`.
+    pub cache_path: Option<PathBuf>,
+    /// Whether to reject the responses that are not signed by an admin, see [`DriaNodesApiResponse::verify`].
+    pub require_signature: bool,
+}
+
+impl DriaNodesSource {
+    /// Overrides the source with the environment variables, if they are not empty.
+    ///
+    /// The environment variables are:
+    /// - `DKN_NODES_URL`: URL of the available nodes API
+    /// - `DKN_NODES_CACHE`: file of the last successful response
+    /// - `DKN_NODES_REQUIRE_SIGNATURE`: `true` to reject unsigned responses
+    pub fn with_envs(mut self) -> Self {
+        if let Ok(url) = env::var("DKN_NODES_URL") {
+            self.url = Some(url.trim().to_string()).filter(|url| !url.is_empty());
+        }
+        if let Ok(path) = env::var("DKN_NODES_CACHE") {
+            self.cache_path = Some(path.trim())
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from);
+        }
+        if let Ok(require_signature) = env::var("DKN_NODES_REQUIRE_SIGNATURE") {
+            self.require_signature = require_signature.trim() == "true";
+        }
+
+        self
+    }
+
+    /// Sets the URL of the available nodes API.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Enables the cache at the given path, see [`DriaNodesSource::cache_path`].
+    pub fn with_cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_path = Some(path.into());
+        self
+    }
+
+    /// Rejects the responses that are not signed by an admin.
+    pub fn with_required_signature(mut self) -> Self {
+        self.require_signature = true;
+        self
     }
 
-    // url to be used is determined by the network type
-    let url = match nodes.network {
-        DriaNetworkType::Community => "https://dkn.dria.co/available-nodes",
-        DriaNetworkType::Pro => "https://dkn.dria.co/sdk/available-nodes",
-        DriaNetworkType::Test => "https://dkn.dria.co/test/available-nodes",
+    /// Returns the configured URL, or the one of the given network.
+    pub fn url(&self, network: DriaNetworkType) -> &str {
+        self.url.as_deref().unwrap_or(match network {
+            DriaNetworkType::Community => "https://dkn.dria.co/available-nodes",
+            DriaNetworkType::Pro => "https://dkn.dria.co/sdk/available-nodes",
+            DriaNetworkType::Test => "https://dkn.dria.co/test/available-nodes",
+        })
+    }
+}
+
+/// A response that is cached on disk, along with the network it belongs to.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedDriaNodes {
+    network: String,
+    response: DriaNodesApiResponse,
+}
+
+/// Refresh available nodes using the API.
+///
+/// The response is verified with the given admin keys, see [`DriaNodesApiResponse::verify`], and must be signed
+/// if the source requires so; verification is skipped if no keys are given, e.g. for the monitor.
+///
+/// If a cache is configured, a verified response is saved there, and the cached one is used
+/// when the API is unreachable or its response can not be verified.
+pub async fn refresh_dria_nodes(
+    nodes: &mut DriaNodes,
+    source: &DriaNodesSource,
+    admin_keys: Option<&AdminKeys>,
+) -> Result<()> {
+    let verify = |response: &DriaNodesApiResponse| match admin_keys {
+        Some(admin_keys) => response.verify(admin_keys, source.require_signature),
+        None => Ok(()),
     };
 
     // make the request
-    let response = reqwest::get(url).await?;
-    let response_body = response.json::<DriaNodesApiResponse>().await?;
-    nodes
-        .bootstrap_nodes
-        .extend(parse_vec(response_body.bootstraps).unwrap_or_else(|e| {
-            log::error!("Failed to parse bootstrap nodes: {}", e);
-            vec![]
-        }));
-    nodes
-        .relay_nodes
-        .extend(parse_vec(response_body.relays).unwrap_or_else(|e| {
-            log::error!("Failed to parse relay nodes: {}", e);
-            vec![]
-        }));
-    nodes
-        .rpc_nodes
-        .extend(parse_vec(response_body.rpc_addrs).unwrap_or_else(|e| {
-            log::error!("Failed to parse rpc nodes: {}", e);
-            vec![]
-        }));
-    nodes
-        .rpc_peerids
-        .extend(parse_vec::<PeerId>(response_body.rpcs).unwrap_or_else(|e| {
-            log::error!("Failed to parse rpc peerids: {}", e);
-            vec![]
-        }));
+    let url = source.url(nodes.network);
+    let fetched = async {
+        let response = reqwest::get(url).await?.error_for_status()?;
+        let response_body = response.json::<DriaNodesApiResponse>().await?;
+        verify(&response_body)?;
+        Ok::<_, eyre::Report>(response_body)
+    }
+    .await;
+
+    let response_body = match (fetched, &source.cache_path) {
+        (Ok(response_body), Some(cache_path)) => {
+            if let Err(e) = save_cache(cache_path, nodes.network, &response_body) {
+                log::warn!("Could not cache available nodes: {:?}", e);
+            }
+            response_body
+        }
+        (Ok(response_body), None) => response_body,
+        (Err(e), Some(cache_path)) => {
+            log::warn!(
+                "Could not fetch available nodes from {}, using the ones cached at {}: {:?}",
+                url,
+                cache_path.display(),
+                e
+            );
+            let response_body = load_cache(cache_path, nodes.network)?;
+            verify(&response_body).wrap_err("could not verify cached available nodes")?;
+            response_body
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    response_body.extend(nodes);
+    Ok(())
+}
+
+/// Saves the response to the cache atomically, by renaming a temporary file.
+fn save_cache(
+    path: &Path,
+    network: DriaNetworkType,
+    response: &DriaNodesApiResponse,
+) -> Result<()> {
+    let cached = CachedDriaNodes {
+        network: network.to_string(),
+        response: response.clone(),
+    };
+    let data = serde_json::to_vec_pretty(&cached).wrap_err("could not serialize nodes")?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).wrap_err("could not create nodes cache directory")?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, data).wrap_err("could not write nodes cache")?;
+    std::fs::rename(&tmp_path, path).wrap_err("could not write nodes cache")?;
 
     Ok(())
 }
 
+/// Loads the cached response, which must belong to the given network.
+fn load_cache(path: &Path, network: DriaNetworkType) -> Result<DriaNodesApiResponse> {
+    let data = std::fs::read(path).wrap_err("could not read nodes cache")?;
+    let cached: CachedDriaNodes =
+        serde_json::from_slice(&data).wrap_err("could not parse nodes cache")?;
+    if cached.network != network.to_string() {
+        return Err(eyre!(
+            "nodes cache belongs to {} network, not {}",
+            cached.network,
+            network
+        ));
+    }
+
+    Ok(cached.response)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::crypto::sign_bytes_recoverable;
+    use libsecp256k1::{PublicKey, SecretKey};
+    use rand::thread_rng;
+
+    const RPC_PEER_ID: &str = "16Uiu2HAmJqegPzwuGKWzmb5m3RdSUJ7NhEGWB5jNCd3ca9zdQ9dU";
+
+    fn response() -> DriaNodesApiResponse {
+        DriaNodesApiResponse {
+            bootstraps: vec![],
+            relays: vec![],
+            rpcs: vec![RPC_PEER_ID.to_string()],
+            rpc_addrs: vec![format!("/ip4/18.234.39.91/tcp/4001/p2p/{}", RPC_PEER_ID)],
+            issued_at: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_signed_dria_nodes() {
+        let admin_sk = SecretKey::random(&mut thread_rng());
+        let admin_keys = AdminKeys::from(vec![PublicKey::from_secret_key(&admin_sk)]);
+        let mut response = response();
+
+        // unsigned responses are accepted, unless a signature is required
+        assert!(response.verify(&admin_keys, false).is_ok());
+        assert!(response.verify(&admin_keys, true).is_err());
+
+        // signed by the admin
+        let now = (get_current_time_nanos() / 1_000_000_000) as u64;
+        response.issued_at = Some(now);
+        response.signature = Some(sign_bytes_recoverable(
+            &response.digest().unwrap(),
+            &admin_sk,
+        ));
+        assert!(response.verify(&admin_keys, true).is_ok());
+
+        // tampered
+        let mut tampered = response.clone();
+        tampered.rpc_addrs.push("/ip4/1.2.3.4/tcp/4001".to_string());
+        assert!(tampered.verify(&admin_keys, false).is_err());
+        let mut tampered = response.clone();
+        tampered.issued_at = Some(now + 1);
+        assert!(tampered.verify(&admin_keys, false).is_err());
+
+        // signed too long ago, in the future, or without a signing time
+        let sign = |issued_at| {
+            let mut response = response.clone();
+            response.issued_at = issued_at;
+            response.signature = Some(sign_bytes_recoverable(
+                &response.digest().unwrap(),
+                &admin_sk,
+            ));
+            response
+        };
+        let max_age = DriaNodesApiResponse::MAX_AGE.as_secs();
+        assert!(sign(Some(now - max_age + 60))
+            .verify(&admin_keys, true)
+            .is_ok());
+        assert!(sign(Some(now - max_age - 60))
+            .verify(&admin_keys, true)
+            .is_err());
+        assert!(sign(Some(now + 3600)).verify(&admin_keys, true).is_err());
+        assert!(sign(None).verify(&admin_keys, false).is_err());
+
+        // signed by someone else
+        let other_sk = SecretKey::random(&mut thread_rng());
+        response.signature = Some(sign_bytes_recoverable(
+            &response.digest().unwrap(),
+            &other_sk,
+        ));
+        assert!(response.verify(&admin_keys, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_dria_nodes() {
+        let path = std::env::temp_dir().join(format!("dkn-nodes-{}.json", PeerId::random()));
+        // nothing listens on port 1, so that the API is unreachable
+        let source = DriaNodesSource::default()
+            .with_url("http://127.0.0.1:1/available-nodes")
+            .with_cache(&path);
+
+        // without a cached response, the error is returned
+        let mut nodes = DriaNodes::new(DriaNetworkType::Pro);
+        assert!(refresh_dria_nodes(&mut nodes, &source, None).await.is_err());
+
+        // cached nodes are used as a fallback
+        save_cache(&path, DriaNetworkType::Pro, &response()).unwrap();
+        refresh_dria_nodes(&mut nodes, &source, None).await.unwrap();
+        assert_eq!(nodes.rpc_nodes.len(), 1);
+        assert_eq!(nodes.rpc_peerids.len(), 1);
+
+        // cache of another network is not used
+        let mut nodes = DriaNodes::new(DriaNetworkType::Test);
+        assert!(refresh_dria_nodes(&mut nodes, &source, None).await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
 
     #[tokio::test]
     #[ignore = "run this manually"]
     async fn test_refresh_dria_nodes() {
+        let source = DriaNodesSource::default();
+
         let mut nodes = DriaNodes::new(DriaNetworkType::Community);
-        refresh_dria_nodes(&mut nodes).await.unwrap();
+        refresh_dria_nodes(&mut nodes, &source, None).await.unwrap();
         println!("Community: {:#?}", nodes);
 
         let mut nodes = DriaNodes::new(DriaNetworkType::Pro);
-        refresh_dria_nodes(&mut nodes).await.unwrap();
+        refresh_dria_nodes(&mut nodes, &source, None).await.unwrap();
         println!("Pro: {:#?}", nodes);
     }
 }
@@ -0,0 +1,129 @@
+use dkn_utils::get_current_time_nanos;
+use std::collections::HashMap;
+
+/// A bounded cache of seen `(id, signature)` pairs, used to drop replayed messages.
+///
+/// The `id` is the unique identifier of the message (e.g. `uuid` of a ping, or `task_id` of a task),
+/// and each entry is kept until the deadline of its message; after that point the message
+/// is rejected by its deadline anyways.
+#[derive(Debug, Clone)]
+pub struct ReplayCache {
+    /// Seen `(id, signature)` pairs, mapped to their expiry time in nanoseconds.
+    entries: HashMap<(String, String), u128>,
+    /// Maximum number of entries to keep.
+    capacity: usize,
+}
+
+impl Default for ReplayCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl ReplayCache {
+    /// Default maximum number of entries.
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// Creates a new cache with the given capacity.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Number of entries in the cache.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no entries in the cache.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Inserts the `(id, signature)` pair to be kept until `expires_at` (in nanoseconds).
+    ///
+    /// Returns `false` if the pair was already seen, i.e. the message is a replay.
+    pub fn insert(
+        &mut self,
+        id: impl Into<String>,
+        signature: impl Into<String>,
+        expires_at: u128,
+    ) -> bool {
+        self.prune(get_current_time_nanos());
+
+        let key = (id.into(), signature.into());
+        if self.entries.contains_key(&key) {
+            return false;
+        }
+
+        // if we are still at capacity, evict the entry that expires the soonest
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, expiry)| **expiry)
+                .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(key, expires_at);
+        true
+    }
+
+    /// Removes the entries that have expired by the given time.
+    fn prune(&mut self, now: u128) {
+        self.entries.retain(|_, expiry| *expiry > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_NANOS: u128 = 3600 * 1_000_000_000;
+
+    #[test]
+    fn test_replay_cache() {
+        let mut cache = ReplayCache::default();
+        let deadline = get_current_time_nanos() + HOUR_NANOS;
+
+        assert!(cache.insert("uuid", "sig", deadline));
+        assert!(!cache.insert("uuid", "sig", deadline), "should be a replay");
+
+        // same id with a different signature, or vice versa, is not a replay
+        assert!(cache.insert("uuid", "other-sig", deadline));
+        assert!(cache.insert("other-uuid", "sig", deadline));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_replay_cache_expiry() {
+        let mut cache = ReplayCache::default();
+
+        // expired entries are pruned on the next insert
+        assert!(cache.insert("uuid", "sig", get_current_time_nanos() - 1));
+        assert!(cache.insert("uuid", "sig", get_current_time_nanos() + HOUR_NANOS));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_replay_cache_capacity() {
+        let mut cache = ReplayCache::new(2);
+        let now = get_current_time_nanos();
+
+        assert!(cache.insert("a", "sig", now + HOUR_NANOS));
+        assert!(cache.insert("b", "sig", now + 2 * HOUR_NANOS));
+        assert!(cache.insert("c", "sig", now + 3 * HOUR_NANOS));
+        assert_eq!(cache.len(), 2);
+
+        // the soonest-expiring entry is evicted
+        assert!(cache.insert("a", "sig", now + HOUR_NANOS));
+        assert!(!cache.insert("c", "sig", now + 3 * HOUR_NANOS));
+    }
+}
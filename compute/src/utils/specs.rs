@@ -6,21 +6,21 @@ use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Specs {
     /// Total memory in bytes
-    total_mem: u64,
+    pub total_mem: u64,
     /// Free memory in bytes
-    free_mem: u64,
+    pub free_mem: u64,
     /// Number of physical CPU cores.
-    num_cpus: Option<usize>,
+    pub num_cpus: Option<usize>,
     /// Global CPU usage, in percentage.
-    cpu_usage: f32,
+    pub cpu_usage: f32,
     /// Operating system name, e.g. `linux`, `macos`, `windows`.
-    os: String,
+    pub os: String,
     /// CPU architecture, e.g. `x86_64`, `aarch64`.
-    arch: String,
+    pub arch: String,
     /// Public IP lookup response.
-    lookup: Option<LookupResponse>,
+    pub lookup: Option<LookupResponse>,
     /// Used models.
-    models: Vec<String>,
+    pub models: Vec<String>,
     // GPU adapter infos, showing information about the available GPUs.
     // gpus: Vec<wgpu::AdapterInfo>,
 }
@@ -0,0 +1,351 @@
+//! An in-process Dria network on localhost: a fake RPC peer that signs messages with a test admin key,
+//! a mock Ollama backend, and a compute node that connects to both.
+#![allow(dead_code)]
+
+use axum::{http::StatusCode, routing::post, Json, Router};
+use dkn_compute::{
+    utils::{
+        crypto::{secret_to_keypair, to_address},
+        AdminKeys, DriaMessage,
+    },
+    DriaComputeNode, DriaComputeNodeConfig,
+};
+use dkn_p2p::{
+    libp2p::{
+        gossipsub::{Message, MessageAcceptance, MessageId},
+        multiaddr::Protocol,
+        Multiaddr, PeerId,
+    },
+    DriaNetworkType, DriaNodes, DriaP2PClient, DriaP2PCommander, DriaP2PConfig, DriaP2PProtocol,
+};
+use dkn_workflows::{DriaWorkflowsConfig, Model, OllamaConfig};
+use eyre::{eyre, Result};
+use libsecp256k1::{PublicKey, SecretKey};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+/// Default timeout for the waits within tests.
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval to poll the peers while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Network of the local nodes.
+const NETWORK: DriaNetworkType = DriaNetworkType::Test;
+
+/// The model served by the mock Ollama backend.
+pub const MOCK_MODEL: Model = Model::Phi3_5Mini;
+
+/// The response of the mock Ollama backend for all prompts.
+pub const MOCK_RESPONSE: &str = "Hello from the mock model!";
+
+/// Prompts containing this text make the mock Ollama backend fail.
+pub const MOCK_FAILURE: &str = "PLEASE_FAIL";
+
+/// Initializes the logger for tests, once.
+pub fn init_logger() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Off)
+        .filter_module("dkn_compute", log::LevelFilter::Debug)
+        .filter_module("dkn_p2p", log::LevelFilter::Info)
+        .is_test(true)
+        .try_init();
+}
+
+/// Returns a localhost TCP address with a port that is free at the moment.
+pub fn local_addr() -> Multiaddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("could not bind");
+    let port = listener.local_addr().expect("could not get address").port();
+
+    Multiaddr::empty()
+        .with(Protocol::Ip4([127, 0, 0, 1].into()))
+        .with(Protocol::Tcp(port))
+}
+
+/// A peer that acts like a Dria RPC: publishes admin-signed pings & tasks, and listens to pongs & results.
+pub struct FakeRpc {
+    pub peer_id: PeerId,
+    /// Listen address, along with the peer id.
+    pub addr: Multiaddr,
+    /// Admin secret key that signs the published messages.
+    pub admin_secret_key: SecretKey,
+    pub commander: DriaP2PCommander,
+    msg_rx: mpsc::Receiver<(PeerId, MessageId, Message)>,
+    handle: JoinHandle<()>,
+}
+
+impl FakeRpc {
+    /// Topics that the RPC is subscribed to.
+    const TOPICS: [&'static str; 4] = ["ping", "pong", "task", "results"];
+
+    /// Spawns the RPC with a random admin key, and subscribes to all topics.
+    pub async fn spawn() -> Result<Self> {
+        let keypair = dkn_p2p::libp2p_identity::Keypair::generate_secp256k1();
+        let peer_id = keypair.public().to_peer_id();
+        let listen_addr = local_addr();
+
+        // the compute node uses the major.minor version of the same network
+        let protocol = DriaP2PProtocol::new_major_minor(NETWORK.protocol_name());
+        let (client, commander, msg_rx, _) = DriaP2PClient::new(
+            keypair,
+            listen_addr.clone(),
+            &DriaNodes::new(NETWORK),
+            protocol,
+            DriaP2PConfig::default(),
+        )?;
+        let handle = tokio::spawn(async move { client.run().await });
+
+        for topic in Self::TOPICS {
+            commander.subscribe(topic).await?;
+        }
+
+        Ok(Self {
+            peer_id,
+            addr: listen_addr.with(Protocol::P2p(peer_id)),
+            admin_secret_key: SecretKey::random(&mut rand::thread_rng()),
+            commander,
+            msg_rx,
+            handle,
+        })
+    }
+
+    /// Public key of the admin.
+    pub fn admin_public_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(&self.admin_secret_key)
+    }
+
+    /// Returns the nodes with this RPC as the only bootstrap & RPC node.
+    pub fn nodes(&self) -> DriaNodes {
+        DriaNodes::new(NETWORK)
+            .with_bootstrap_nodes([self.addr.clone()])
+            .with_rpc_nodes([self.addr.clone()])
+            .with_rpc_peer_ids([self.peer_id])
+    }
+
+    /// Waits until the given peer is in the mesh of this RPC.
+    pub async fn wait_for_peer(&self, peer_id: &PeerId) -> Result<()> {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            loop {
+                let (mesh, _) = self.commander.peers().await?;
+                if mesh.contains(peer_id) {
+                    return Ok::<_, eyre::Report>(());
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .map_err(|_| eyre!("timed out waiting for peer {}", peer_id))?
+    }
+
+    /// Publishes the payload as an admin-signed message to the given topic.
+    pub async fn publish(&mut self, topic: &str, payload: &impl Serialize) -> Result<()> {
+        let message = DriaMessage::new_signed(
+            serde_json::to_string(payload)?,
+            topic,
+            &self.admin_secret_key,
+        );
+        self.commander
+            .publish(topic, serde_json::to_vec(&message)?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Waits for the next message on the given topic, messages on other topics are skipped.
+    pub async fn recv(&mut self, topic: &str, timeout: Duration) -> Result<DriaMessage> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let (peer_id, message_id, message) = self
+                    .msg_rx
+                    .recv()
+                    .await
+                    .ok_or_else(|| eyre!("message channel closed"))?;
+                self.commander
+                    .validate_message(&message_id, &peer_id, MessageAcceptance::Accept)
+                    .await?;
+
+                if message.topic.as_str() == topic {
+                    return DriaMessage::decode(&message.data);
+                }
+            }
+        })
+        .await
+        .map_err(|_| eyre!("timed out waiting for {} message", topic))?
+    }
+
+    /// Shuts down the client and waits for its task to finish.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.commander.shutdown().await?;
+        self.msg_rx.close();
+        self.handle.await?;
+
+        Ok(())
+    }
+}
+
+/// A mock Ollama backend, responding to chat & generation requests with [`MOCK_RESPONSE`].
+///
+/// Requests that contain [`MOCK_FAILURE`] fail with an internal server error.
+pub struct MockOllama {
+    pub host: String,
+    pub port: u16,
+    handle: JoinHandle<()>,
+}
+
+impl MockOllama {
+    pub async fn spawn() -> Result<Self> {
+        let app = Router::new()
+            .route(
+                "/api/chat",
+                post(|Json(request): Json<Value>| async move {
+                    Self::respond(&request, |model| {
+                        json!({
+                            "model": model,
+                            "created_at": "2024-01-01T00:00:00Z",
+                            "message": { "role": "assistant", "content": MOCK_RESPONSE },
+                            "done": true,
+                            "total_duration": 1,
+                            "load_duration": 1,
+                            "prompt_eval_count": 1,
+                            "prompt_eval_duration": 1,
+                            "eval_count": 1,
+                            "eval_duration": 1
+                        })
+                    })
+                }),
+            )
+            .route(
+                "/api/generate",
+                post(|Json(request): Json<Value>| async move {
+                    Self::respond(&request, |model| {
+                        json!({
+                            "model": model,
+                            "created_at": "2024-01-01T00:00:00Z",
+                            "response": MOCK_RESPONSE,
+                            "done": true,
+                            "context": [1],
+                            "total_duration": 1,
+                            "load_duration": 1,
+                            "prompt_eval_count": 1,
+                            "prompt_eval_duration": 1,
+                            "eval_count": 1,
+                            "eval_duration": 1
+                        })
+                    })
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .await
+                .expect("mock ollama should serve")
+        });
+
+        Ok(Self {
+            host: "http://127.0.0.1".to_string(),
+            port,
+            handle,
+        })
+    }
+
+    fn respond(request: &Value, response: impl Fn(&Value) -> Value) -> (StatusCode, Json<Value>) {
+        if request.to_string().contains(MOCK_FAILURE) {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "mock failure" })),
+            )
+        } else {
+            (StatusCode::OK, Json(response(&request["model"])))
+        }
+    }
+
+    /// Stops the backend.
+    pub fn shutdown(self) {
+        self.handle.abort();
+    }
+}
+
+/// A compute node running all of its tasks (p2p client, worker & main loop) in the background.
+pub struct TestNode {
+    pub peer_id: PeerId,
+    pub public_key: PublicKey,
+    pub address: [u8; 20],
+    cancellation: CancellationToken,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl TestNode {
+    /// Spawns a compute node that trusts the RPC, and serves [`MOCK_MODEL`] with the mock backend.
+    pub fn spawn(rpc: &FakeRpc, ollama: &MockOllama) -> Result<Self> {
+        let secret_key = SecretKey::random(&mut rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(&secret_key);
+        let address = to_address(&public_key);
+        let peer_id = secret_to_keypair(&secret_key).public().to_peer_id();
+
+        let mut ollama_config = OllamaConfig::default();
+        ollama_config.host = ollama.host.clone();
+        ollama_config.port = ollama.port;
+        let workflows =
+            DriaWorkflowsConfig::new(vec![MOCK_MODEL]).with_ollama_config(ollama_config);
+
+        let config = DriaComputeNodeConfig {
+            secret_key,
+            public_key,
+            address,
+            admin_keys: AdminKeys::from(vec![rpc.admin_public_key()]),
+            admin_keys_source: Default::default(),
+            p2p_listen_addr: local_addr(),
+            workflows,
+            network_type: NETWORK,
+            batch_size: 1,
+            nodes_source: Default::default(),
+            p2p: DriaP2PConfig::default(),
+            message_freshness: None,
+            compression: None,
+            require_envelope_signature: false,
+            chunked_results: false,
+            envelope: Default::default(),
+            monitor_peer_ids: vec![],
+        };
+
+        let (mut node, p2p, worker_batch, worker_single) =
+            DriaComputeNode::new_with_nodes(config, rpc.nodes())?;
+
+        let cancellation = CancellationToken::new();
+        let mut handles = vec![tokio::spawn(async move { p2p.run().await })];
+        assert!(worker_batch.is_none(), "mock model should not be batchable");
+        let mut worker_single = worker_single.expect("should have single worker");
+        handles.push(tokio::spawn(
+            async move { worker_single.run_series().await },
+        ));
+        let node_cancellation = cancellation.clone();
+        handles.push(tokio::spawn(async move {
+            node.run(node_cancellation)
+                .await
+                .expect("node should run without errors")
+        }));
+
+        Ok(Self {
+            peer_id,
+            public_key,
+            address,
+            cancellation,
+            handles,
+        })
+    }
+
+    /// Stops the node and waits for all of its tasks to finish.
+    pub async fn shutdown(self) -> Result<()> {
+        self.cancellation.cancel();
+        for handle in self.handles {
+            handle.await?;
+        }
+
+        Ok(())
+    }
+}
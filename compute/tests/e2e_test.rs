@@ -0,0 +1,157 @@
+use dkn_compute::{
+    handlers::PingpongResponse,
+    payloads::{TaskErrorPayload, TaskResponsePayload},
+    utils::filter::TaskFilter,
+};
+use dkn_utils::get_current_time_nanos;
+use dkn_workflows::ModelProvider;
+use eyre::Result;
+use fastbloom_rs::{FilterBuilder, Membership};
+use libsecp256k1::{PublicKey, SecretKey};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+mod common;
+use common::*;
+
+/// Time given to the node to handle a ping or a task.
+const DEADLINE: Duration = Duration::from_secs(60);
+
+/// Returns a deadline in nanoseconds, [`DEADLINE`] from now.
+fn deadline() -> u128 {
+    get_current_time_nanos() + DEADLINE.as_nanos()
+}
+
+/// Returns a filter that includes the given address only.
+fn filter_of(address: &[u8]) -> TaskFilter {
+    let mut bloom = FilterBuilder::new(128, 0.01).build_bloom_filter();
+    bloom.add(address);
+    TaskFilter::from(bloom)
+}
+
+/// Returns a task payload with a single-generation workflow for the given prompt.
+fn task_payload(task_id: &str, prompt: &str, filter: TaskFilter, public_key: &PublicKey) -> Value {
+    json!({
+        "taskId": task_id,
+        "deadline": deadline(),
+        "input": {
+            "workflow": {
+                "config": { "max_steps": 10, "max_time": 50, "tools": [""] },
+                "tasks": [
+                    {
+                        "id": "A",
+                        "name": "",
+                        "description": "",
+                        "operator": "generation",
+                        "messages": [{ "role": "user", "content": prompt }],
+                        "inputs": [],
+                        "outputs": [{ "type": "write", "key": "result", "value": "__result" }]
+                    },
+                    {
+                        "id": "__end",
+                        "name": "end",
+                        "description": "End of the task",
+                        "operator": "end",
+                        "messages": [{ "role": "user", "content": "End of the task" }],
+                        "inputs": [],
+                        "outputs": []
+                    }
+                ],
+                "steps": [{ "source": "A", "target": "__end" }],
+                "return_value": { "input": { "type": "read", "key": "result" } }
+            },
+            "model": [MOCK_MODEL.to_string()],
+            "prompt": null
+        },
+        "filter": filter,
+        "publicKey": hex::encode(public_key.serialize_compressed()),
+    })
+}
+
+/// Runs a compute node against a fake RPC and a mock Ollama backend, and goes through the entire flow:
+///
+/// 1. A ping is answered with a signed pong.
+/// 2. A task that excludes the node via its filter is not computed.
+/// 3. A task is computed, and its result is encrypted for the requester & signed by the node.
+/// 4. A failing task is answered with a signed error payload.
+///
+/// ## Run command
+///
+/// ```sh
+/// cargo test --package dkn-compute --test e2e_test
+/// ```
+#[tokio::test]
+async fn test_compute_node_e2e() -> Result<()> {
+    init_logger();
+
+    let mut rpc = FakeRpc::spawn().await?;
+    let ollama = MockOllama::spawn().await?;
+    let node = TestNode::spawn(&rpc, &ollama)?;
+    rpc.wait_for_peer(&node.peer_id).await?;
+
+    // (1) ping to pong
+    let uuid = uuid::Uuid::new_v4().to_string();
+    rpc.publish("ping", &json!({ "uuid": uuid, "deadline": deadline() }))
+        .await?;
+    let pong = rpc.recv("pong", WAIT_TIMEOUT).await?;
+    assert!(pong.is_signed(&node.public_key)?);
+    let pong = pong.parse_payload::<PingpongResponse>(true)?;
+    assert_eq!(pong.uuid, uuid);
+    assert_eq!(pong.models, vec![(ModelProvider::Ollama, MOCK_MODEL)]);
+
+    // the requester's key, results are encrypted for it
+    let task_secret_key = SecretKey::random(&mut rand::thread_rng());
+    let task_public_key = PublicKey::from_secret_key(&task_secret_key);
+
+    // (2) a task that excludes the node, followed by one that includes it;
+    // only the latter is expected to have a result
+    let excluded_task_id = uuid::Uuid::new_v4().to_string();
+    let excluded = task_payload(
+        &excluded_task_id,
+        "Hello?",
+        filter_of(b"someone else"),
+        &task_public_key,
+    );
+    rpc.publish("task", &excluded).await?;
+
+    // (3) task to encrypted & signed result
+    let task_id = uuid::Uuid::new_v4().to_string();
+    let task = task_payload(
+        &task_id,
+        "Hello?",
+        filter_of(&node.address),
+        &task_public_key,
+    );
+    rpc.publish("task", &task).await?;
+    let result = rpc.recv("results", WAIT_TIMEOUT).await?;
+    assert!(result.is_signed(&node.public_key)?);
+    let result = result.parse_payload::<TaskResponsePayload>(false)?;
+    assert_eq!(
+        result.task_id, task_id,
+        "excluded task should not be computed"
+    );
+    assert_eq!(result.model, MOCK_MODEL.to_string());
+    let verified = result.verify(&task_secret_key)?;
+    assert_eq!(verified.address, node.address);
+    assert_eq!(String::from_utf8(verified.result)?, MOCK_RESPONSE);
+
+    // (4) task to error
+    let failing_task_id = uuid::Uuid::new_v4().to_string();
+    let failing = task_payload(
+        &failing_task_id,
+        MOCK_FAILURE,
+        filter_of(&node.address),
+        &task_public_key,
+    );
+    rpc.publish("task", &failing).await?;
+    let error = rpc.recv("results", WAIT_TIMEOUT).await?;
+    assert!(error.is_signed(&node.public_key)?);
+    let error = error.parse_payload::<TaskErrorPayload>(true)?;
+    assert_eq!(error.task_id, failing_task_id);
+    assert_eq!(error.model, MOCK_MODEL.to_string());
+
+    node.shutdown().await?;
+    rpc.shutdown().await?;
+    ollama.shutdown();
+    Ok(())
+}
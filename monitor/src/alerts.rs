@@ -0,0 +1,334 @@
+use std::{collections::HashSet, time::Duration};
+
+use eyre::{eyre, Context, Result};
+use serde::Serialize;
+
+use crate::api::MonitorStats;
+
+/// Minimum number of outcomes for a model before its error rate is considered.
+const MIN_OUTCOMES_FOR_ERROR_RATE: usize = 10;
+/// Time limit of an action, so that a stuck webhook or command is not left running.
+const ACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Thresholds for the alerts, a rule is disabled if its threshold is not set.
+#[derive(Debug, Clone, Default)]
+pub struct AlertRules {
+    /// Alert if pending tasks are above this.
+    pub max_pending_tasks: Option<usize>,
+    /// Alert if the ratio of errors to all outcomes of a model is above this, within `[0, 1]`.
+    pub max_error_rate: Option<f64>,
+    /// Alert if no ping has been seen for this long.
+    pub max_ping_silence: Option<Duration>,
+    /// Alert if mesh peers are below this, once the peers are counted.
+    pub min_mesh_peers: Option<usize>,
+}
+
+/// An action to take when an alert fires.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertAction {
+    /// POST the alert as JSON to the given URL.
+    Webhook(String),
+    /// Run the given command with `sh -c`, with the alert as JSON in `DKN_ALERT`.
+    Command(String),
+}
+
+/// An alert that has fired.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    /// Name of the rule, e.g. `pending_tasks`.
+    pub rule: &'static str,
+    /// The subject of the alert, e.g. the model name for `error_rate`.
+    pub subject: Option<String>,
+    /// Observed value.
+    pub value: f64,
+    /// Threshold of the rule.
+    pub threshold: f64,
+    /// Human-readable description.
+    pub message: String,
+}
+
+impl Alert {
+    /// A key that identifies the alert, so that it does not fire again while it is active.
+    fn key(&self) -> String {
+        match &self.subject {
+            Some(subject) => format!("{}:{}", self.rule, subject),
+            None => self.rule.to_string(),
+        }
+    }
+}
+
+impl AlertRules {
+    /// Returns `true` if no rules are set.
+    pub fn is_empty(&self) -> bool {
+        self.max_pending_tasks.is_none()
+            && self.max_error_rate.is_none()
+            && self.max_ping_silence.is_none()
+            && self.min_mesh_peers.is_none()
+    }
+
+    /// Evaluates the rules against the statistics at the given time (nanoseconds).
+    pub fn evaluate(&self, stats: &MonitorStats, now: u128) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+
+        if let Some(max) = self.max_pending_tasks {
+            if stats.pending_tasks > max {
+                alerts.push(Alert {
+                    rule: "pending_tasks",
+                    subject: None,
+                    value: stats.pending_tasks as f64,
+                    threshold: max as f64,
+                    message: format!("{} pending tasks (max {})", stats.pending_tasks, max),
+                });
+            }
+        }
+
+        if let Some(max) = self.max_error_rate {
+            let mut models = stats.models.iter().collect::<Vec<_>>();
+            models.sort_by_key(|(model, _)| *model);
+            for (model, outcomes) in models {
+                let rate = outcomes.failure_rate();
+                if outcomes.results + outcomes.errors >= MIN_OUTCOMES_FOR_ERROR_RATE && rate > max {
+                    alerts.push(Alert {
+                        rule: "error_rate",
+                        subject: Some(model.clone()),
+                        value: rate,
+                        threshold: max,
+                        message: format!(
+                            "{:.1}% of tasks failed for {} (max {:.1}%)",
+                            rate * 100.0,
+                            model,
+                            max * 100.0
+                        ),
+                    });
+                }
+            }
+        }
+
+        if let Some(max) = self.max_ping_silence {
+            // if no pings were seen at all, count from the start
+            let last_ping_at = stats.last_ping_at.unwrap_or(stats.started_at);
+            let silence = now.saturating_sub(last_ping_at);
+            if silence > max.as_nanos() {
+                let silence_secs = (silence / 1_000_000_000) as u64;
+                alerts.push(Alert {
+                    rule: "ping_silence",
+                    subject: None,
+                    value: silence_secs as f64,
+                    threshold: max.as_secs() as f64,
+                    message: format!("no pings for {}s (max {}s)", silence_secs, max.as_secs()),
+                });
+            }
+        }
+
+        // peers are counted periodically, so skip the rule until the first count
+        if let Some(min) = self
+            .min_mesh_peers
+            .filter(|_| stats.peers_counted_at.is_some())
+        {
+            if stats.mesh_peers < min {
+                alerts.push(Alert {
+                    rule: "mesh_peers",
+                    subject: None,
+                    value: stats.mesh_peers as f64,
+                    threshold: min as f64,
+                    message: format!("{} mesh peers (min {})", stats.mesh_peers, min),
+                });
+            }
+        }
+
+        alerts
+    }
+}
+
+/// Evaluates the rules and notifies the actions when an alert fires.
+///
+/// An alert fires once when its rule is triggered, and can fire again only after it is resolved.
+#[derive(Debug)]
+pub struct Alerter {
+    rules: AlertRules,
+    actions: Vec<AlertAction>,
+    /// Keys of the currently active alerts.
+    active: HashSet<String>,
+    client: reqwest::Client,
+}
+
+impl Alerter {
+    pub fn new(rules: AlertRules, actions: Vec<AlertAction>) -> Self {
+        Self {
+            rules,
+            actions,
+            active: HashSet::new(),
+            client: reqwest::Client::builder()
+                .timeout(ACTION_TIMEOUT)
+                .build()
+                .expect("could not build client"),
+        }
+    }
+
+    /// Evaluates the rules, and notifies the actions for the newly fired alerts in the background.
+    ///
+    /// Returns the newly fired alerts.
+    pub fn check(&mut self, stats: &MonitorStats, now: u128) -> Vec<Alert> {
+        if self.rules.is_empty() {
+            return Vec::new();
+        }
+
+        let alerts = self.rules.evaluate(stats, now);
+
+        // resolve the alerts that are no longer triggered
+        let keys = alerts.iter().map(Alert::key).collect::<HashSet<_>>();
+        for key in self.active.difference(&keys) {
+            log::info!("Alert resolved: {}", key);
+        }
+        self.active.retain(|key| keys.contains(key));
+
+        let mut fired = Vec::new();
+        for alert in alerts {
+            if !self.active.insert(alert.key()) {
+                continue;
+            }
+
+            log::warn!("Alert: {}", alert.message);
+            for action in &self.actions {
+                let (client, action, alert) = (self.client.clone(), action.clone(), alert.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = notify(&client, &action, &alert).await {
+                        log::error!("Could not notify {:?}: {:?}", action, e);
+                    }
+                });
+            }
+            fired.push(alert);
+        }
+
+        fired
+    }
+}
+
+/// Notifies the action of the alert, within [`ACTION_TIMEOUT`].
+async fn notify(client: &reqwest::Client, action: &AlertAction, alert: &Alert) -> Result<()> {
+    match action {
+        AlertAction::Webhook(url) => {
+            client
+                .post(url)
+                .json(alert)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        AlertAction::Command(command) => {
+            let status = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("DKN_ALERT", serde_json::to_string(alert)?)
+                .kill_on_drop(true)
+                .status();
+            let status = tokio::time::timeout(ACTION_TIMEOUT, status)
+                .await
+                .wrap_err("command timed out")?
+                .wrap_err("could not run command")?;
+            if !status.success() {
+                return Err(eyre!("command exited with {}", status));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+    use dkn_utils::get_current_time_nanos;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_alert_rules() {
+        let rules = AlertRules {
+            max_pending_tasks: Some(10),
+            max_error_rate: Some(0.5),
+            max_ping_silence: Some(Duration::from_secs(60)),
+            min_mesh_peers: Some(3),
+        };
+
+        let mut stats = MonitorStats {
+            pending_tasks: 5,
+            ..Default::default()
+        };
+        let now = get_current_time_nanos();
+        stats.last_ping_at = Some(now);
+        assert!(
+            rules.evaluate(&stats, now).is_empty(),
+            "peers not counted yet"
+        );
+        stats.peers_counted_at = Some(now);
+        stats.mesh_peers = 5;
+        for _ in 0..6 {
+            stats.record_outcome("gpt-4o", true);
+        }
+        assert!(rules.evaluate(&stats, now).is_empty(), "too few outcomes");
+
+        for _ in 0..4 {
+            stats.record_outcome("gpt-4o", false);
+        }
+        stats.pending_tasks = 11;
+        stats.mesh_peers = 2;
+        let later = now + Duration::from_secs(61).as_nanos();
+        let rules_fired = rules
+            .evaluate(&stats, later)
+            .into_iter()
+            .map(|alert| alert.key())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules_fired,
+            vec![
+                "pending_tasks",
+                "error_rate:gpt-4o",
+                "ping_silence",
+                "mesh_peers"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alert_webhook() {
+        // a local stub that forwards the received alerts
+        let (alert_tx, mut alert_rx) = mpsc::channel(4);
+        let app = Router::new().route(
+            "/alert",
+            post(|Json(alert): Json<serde_json::Value>| async move {
+                alert_tx.send(alert).await.unwrap();
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let rules = AlertRules {
+            max_pending_tasks: Some(10),
+            ..Default::default()
+        };
+        let webhook = AlertAction::Webhook(format!("http://{}/alert", addr));
+        let mut alerter = Alerter::new(rules, vec![webhook]);
+        let mut stats = MonitorStats {
+            pending_tasks: 11,
+            ..Default::default()
+        };
+        let now = get_current_time_nanos();
+
+        // fires once
+        assert_eq!(alerter.check(&stats, now).len(), 1);
+        let alert = alert_rx.recv().await.unwrap();
+        assert_eq!(alert["rule"], "pending_tasks");
+        assert_eq!(alert["value"], 11.0);
+        assert!(alerter.check(&stats, now).is_empty());
+
+        // fires again after being resolved
+        stats.pending_tasks = 0;
+        assert!(alerter.check(&stats, now).is_empty());
+        stats.pending_tasks = 12;
+        assert_eq!(alerter.check(&stats, now).len(), 1);
+        assert_eq!(alert_rx.recv().await.unwrap()["value"], 12.0);
+    }
+}
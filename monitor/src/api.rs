@@ -0,0 +1,406 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{Html, IntoResponse},
+    routing::get,
+    Json, Router,
+};
+use dkn_p2p::DriaP2PEvent;
+use dkn_utils::get_current_time_nanos;
+use eyre::{Context, Result};
+use serde::Serialize;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    census::{ModelCensus, PeerCensus},
+    inventory::{FleetInventory, InventoryReport},
+    tracker::TaskCounters,
+};
+
+/// Monitor statistics that are shared with the HTTP server.
+pub type SharedStats = Arc<RwLock<MonitorStats>>;
+
+/// Maximum number of keys in the counters that are keyed by message fields, as those are chosen by the peers.
+const MAX_COUNTER_KEYS: usize = 256;
+/// Maximum length of a counter key, longer ones are truncated.
+const MAX_COUNTER_KEY_LEN: usize = 64;
+/// Key of the counts that are beyond [`MAX_COUNTER_KEYS`].
+const OTHER_COUNTER_KEY: &str = "other";
+
+/// Outcomes of tasks for a model.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelOutcomes {
+    pub results: usize,
+    pub errors: usize,
+}
+
+impl ModelOutcomes {
+    /// Ratio of errors to all outcomes, `0.0` if there are none.
+    pub fn failure_rate(&self) -> f64 {
+        let total = self.results + self.errors;
+        if total == 0 {
+            0.0
+        } else {
+            self.errors as f64 / total as f64
+        }
+    }
+}
+
+/// Counters of the peer-to-peer events of the monitor itself.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkCounters {
+    /// Number of connections established.
+    pub connections_established: usize,
+    /// Number of connections closed.
+    pub connections_closed: usize,
+    /// Number of outgoing connections that could not be established.
+    pub dial_failures: usize,
+    /// Number of peers blacklisted due to a protocol mismatch.
+    pub blacklisted_peers: usize,
+    /// Latest AutoNAT status of the monitor, if known.
+    pub nat_status: Option<String>,
+}
+
+/// A snapshot of the network as observed by the monitor.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonitorStats {
+    /// Timestamp (nanoseconds) at which the monitor had started.
+    pub started_at: u128,
+    /// Number of peers in the mesh of subscribed topics.
+    pub mesh_peers: usize,
+    /// Number of all known peers.
+    pub all_peers: usize,
+    /// Timestamp (nanoseconds) at which the peers were last counted, if ever.
+    pub peers_counted_at: Option<u128>,
+    /// Number of messages per topic.
+    pub messages: HashMap<String, usize>,
+    /// Number of messages per `DriaMessage.version`.
+    pub versions: HashMap<String, usize>,
+    /// Number of tasks that have not been responded to yet.
+    pub pending_tasks: usize,
+    /// Summary counters of the tasks.
+    pub tasks: TaskCounters,
+    /// Timestamp (nanoseconds) of the last ping, if any.
+    pub last_ping_at: Option<u128>,
+    /// Task outcomes per model.
+    pub models: HashMap<String, ModelOutcomes>,
+    /// Peer-to-peer events of the monitor.
+    pub network: NetworkCounters,
+    /// Census of the nodes, served separately.
+    #[serde(skip)]
+    pub census: PeerCensus,
+    /// Hardware inventory of the nodes, served separately.
+    #[serde(skip)]
+    pub inventory: FleetInventory,
+}
+
+impl Default for MonitorStats {
+    fn default() -> Self {
+        Self {
+            started_at: get_current_time_nanos(),
+            mesh_peers: 0,
+            all_peers: 0,
+            peers_counted_at: None,
+            messages: HashMap::new(),
+            versions: HashMap::new(),
+            pending_tasks: 0,
+            tasks: TaskCounters::default(),
+            last_ping_at: None,
+            models: HashMap::new(),
+            network: NetworkCounters::default(),
+            census: PeerCensus::default(),
+            inventory: FleetInventory::default(),
+        }
+    }
+}
+
+impl MonitorStats {
+    /// Records a message with the given topic & version.
+    pub fn record_message(&mut self, topic: &str, version: &str) {
+        *bounded_entry(&mut self.messages, topic) += 1;
+        *bounded_entry(&mut self.versions, version) += 1;
+    }
+
+    /// Records a peer-to-peer event of the monitor.
+    pub fn record_event(&mut self, event: &DriaP2PEvent) {
+        match event {
+            DriaP2PEvent::ConnectionEstablished { .. } => self.network.connections_established += 1,
+            DriaP2PEvent::ConnectionClosed { .. } => self.network.connections_closed += 1,
+            DriaP2PEvent::OutgoingConnectionError { .. } => self.network.dial_failures += 1,
+            DriaP2PEvent::PeerBlacklisted { .. } => self.network.blacklisted_peers += 1,
+            DriaP2PEvent::NatStatusChanged { new, .. } => {
+                self.network.nat_status = Some(format!("{:?}", new))
+            }
+            _ => {}
+        }
+    }
+
+    /// Records a task outcome for the given model.
+    pub fn record_outcome(&mut self, model: &str, is_error: bool) {
+        let outcomes = bounded_entry(&mut self.models, model);
+        if is_error {
+            outcomes.errors += 1;
+        } else {
+            outcomes.results += 1;
+        }
+    }
+}
+
+/// Returns the entry of the given key, or of [`OTHER_COUNTER_KEY`] if the map is full,
+/// so that the peers can not grow the counters without bound.
+fn bounded_entry<'a, V: Default>(map: &'a mut HashMap<String, V>, key: &str) -> &'a mut V {
+    let key = match key.char_indices().nth(MAX_COUNTER_KEY_LEN) {
+        Some((end, _)) => &key[..end],
+        None => key,
+    };
+    let key = if map.len() < MAX_COUNTER_KEYS || map.contains_key(key) {
+        key
+    } else {
+        OTHER_COUNTER_KEY
+    };
+
+    map.entry(key.to_string()).or_default()
+}
+
+/// The JSON response of `/api/stats`, with the rates derived from the counters.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsResponse<'a> {
+    #[serde(flatten)]
+    stats: &'a MonitorStats,
+    uptime_secs: u64,
+    /// Messages per minute for each topic, averaged over the uptime.
+    rates_per_minute: HashMap<&'a str, f64>,
+    /// Failure rate for each model.
+    failure_rates: HashMap<&'a str, f64>,
+    /// Number of nodes that have responded to a ping recently.
+    active_nodes: usize,
+    /// Number of active nodes & their pending tasks for each model.
+    census: HashMap<String, ModelCensus>,
+    /// Aggregated hardware of the nodes that have reported their specs.
+    inventory: InventoryReport,
+}
+
+/// Serves the JSON API at `/api/stats` and a simple dashboard at `/`, until the token is cancelled.
+///
+/// The census of active nodes is exported at `/api/census.json` and `/api/census.csv`,
+/// and the hardware inventory at `/api/inventory.json`.
+pub async fn serve(addr: SocketAddr, stats: SharedStats, token: CancellationToken) -> Result<()> {
+    let app = Router::new()
+        .route("/", get(|| async { Html(DASHBOARD_HTML) }))
+        .route("/api/stats", get(get_stats))
+        .route("/api/census.json", get(get_census_json))
+        .route("/api/census.csv", get(get_census_csv))
+        .route("/api/inventory.json", get(get_inventory_json))
+        .with_state(stats);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .wrap_err(format!("could not bind to {}", addr))?;
+    log::info!("Serving dashboard at http://{}", addr);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { token.cancelled().await })
+        .await
+        .wrap_err("could not serve dashboard")
+}
+
+async fn get_stats(State(stats): State<SharedStats>) -> Json<serde_json::Value> {
+    let stats = stats.read().await;
+
+    let uptime_secs =
+        (get_current_time_nanos().saturating_sub(stats.started_at) / 1_000_000_000) as u64;
+    let rates_per_minute = stats
+        .messages
+        .iter()
+        .map(|(topic, count)| {
+            (
+                topic.as_str(),
+                *count as f64 * 60.0 / uptime_secs.max(1) as f64,
+            )
+        })
+        .collect();
+    let failure_rates = stats
+        .models
+        .iter()
+        .map(|(model, outcomes)| (model.as_str(), outcomes.failure_rate()))
+        .collect();
+    let active_since = census_active_since();
+
+    Json(serde_json::json!(StatsResponse {
+        stats: &stats,
+        uptime_secs,
+        rates_per_minute,
+        failure_rates,
+        active_nodes: stats.census.active(active_since).len(),
+        census: stats.census.models(active_since),
+        inventory: stats.inventory.report(),
+    }))
+}
+
+async fn get_census_json(State(stats): State<SharedStats>) -> Json<serde_json::Value> {
+    Json(stats.read().await.census.to_json(census_active_since()))
+}
+
+async fn get_census_csv(State(stats): State<SharedStats>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/csv")],
+        stats.read().await.census.to_csv(census_active_since()),
+    )
+}
+
+async fn get_inventory_json(State(stats): State<SharedStats>) -> Json<serde_json::Value> {
+    Json(stats.read().await.inventory.to_json())
+}
+
+/// Nodes seen after this time are considered active.
+fn census_active_since() -> u128 {
+    get_current_time_nanos().saturating_sub(PeerCensus::ACTIVE_WINDOW.as_nanos())
+}
+
+/// A single-page dashboard that polls `/api/stats`.
+const DASHBOARD_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Dria Monitor</title>
+  <style>
+    body { font-family: monospace; margin: 2em; }
+    table { border-collapse: collapse; margin-bottom: 1.5em; }
+    td, th { border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left; }
+  </style>
+</head>
+<body>
+  <h1>Dria Monitor</h1>
+  <div id="stats">Loading...</div>
+  <script>
+    // the values are chosen by the peers, so they are only ever set as text
+    const el = (tag, text) => {
+      const e = document.createElement(tag);
+      if (text !== undefined) e.textContent = String(text);
+      return e;
+    };
+
+    const table = (title, head, rows, links = []) => {
+      const h = el("h3", title);
+      for (const [name, href] of links) {
+        const a = el("a", name);
+        a.href = href;
+        h.append(" ", a);
+      }
+
+      const t = el("table");
+      const tr = el("tr");
+      tr.append(...head.map((c) => el("th", c)));
+      t.append(tr);
+      for (const r of rows) {
+        const tr = el("tr");
+        tr.append(...r.map((c) => el("td", c)));
+        t.append(tr);
+      }
+
+      const section = document.createDocumentFragment();
+      section.append(h, t);
+      return section;
+    };
+
+    async function refresh() {
+      const s = await (await fetch("/api/stats")).json();
+      document.getElementById("stats").replaceChildren(
+        table("Network", ["Uptime (s)", "Mesh Peers", "All Peers", "Active Nodes", "Pending Tasks"],
+          [[s.uptimeSecs, s.meshPeers, s.allPeers, s.activeNodes, s.pendingTasks]]),
+        table("Census", ["Model", "Active Nodes", "Pending Tasks"],
+          Object.entries(s.census).map(([m, c]) => [m, c.nodes, c.pendingTasks]),
+          [["csv", "/api/census.csv"], ["json", "/api/census.json"]]),
+        table("Tasks", ["Seen", "Results", "Completed", "Expired", "Unmatched Results"],
+          [[s.tasks.tasks, s.tasks.results, s.tasks.completed, s.tasks.expired, s.tasks.unmatchedResults]]),
+        table("Inventory",
+          ["Nodes", "Total Memory (GiB)", "Free Memory (GiB)", "CPU Cores", "Avg. CPU Usage", "Platforms"],
+          [[s.inventory.nodes, (s.inventory.totalMem / 2 ** 30).toFixed(1), (s.inventory.freeMem / 2 ** 30).toFixed(1),
+            s.inventory.numCpus, s.inventory.avgCpuUsage.toFixed(1) + "%",
+            Object.entries(s.inventory.platforms).map(([p, c]) => `${p}: ${c}`).join(", ")]],
+          [["json", "/api/inventory.json"]]),
+        table("Topics", ["Topic", "Messages", "Per Minute"],
+          Object.entries(s.messages).map(([t, c]) => [t, c, s.ratesPerMinute[t].toFixed(2)])),
+        table("Models", ["Model", "Results", "Errors", "Failure Rate"],
+          Object.entries(s.models).map(([m, o]) =>
+            [m, o.results, o.errors, (100 * s.failureRates[m]).toFixed(1) + "%"])),
+        table("Versions", ["Version", "Messages"], Object.entries(s.versions)),
+      );
+    }
+
+    refresh();
+    setInterval(refresh, 5000);
+  </script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor_stats() {
+        let mut stats = MonitorStats::default();
+        stats.record_message("ping", "0.2.33");
+        stats.record_message("pong", "0.2.33");
+        stats.record_message("pong", "0.2.32");
+        assert_eq!(stats.messages["pong"], 2);
+        assert_eq!(stats.versions["0.2.33"], 2);
+
+        stats.record_outcome("gpt-4o", false);
+        stats.record_outcome("gpt-4o", false);
+        stats.record_outcome("gpt-4o", false);
+        stats.record_outcome("gpt-4o", true);
+        assert_eq!(stats.models["gpt-4o"].failure_rate(), 0.25);
+        assert_eq!(ModelOutcomes::default().failure_rate(), 0.0);
+
+        // keys chosen by the peers are bounded in number & length
+        for i in 0..2 * MAX_COUNTER_KEYS {
+            stats.record_message("pong", &format!("{}{}", i, "0".repeat(100)));
+        }
+        assert_eq!(stats.versions.len(), MAX_COUNTER_KEYS + 1);
+        assert!(stats
+            .versions
+            .keys()
+            .all(|key| key.len() <= MAX_COUNTER_KEY_LEN));
+        assert!(stats.versions[OTHER_COUNTER_KEY] > MAX_COUNTER_KEYS);
+    }
+
+    #[test]
+    fn test_monitor_network_stats() {
+        let mut stats = MonitorStats::default();
+        let peer_id = dkn_p2p::libp2p::PeerId::random();
+        stats.record_event(&DriaP2PEvent::ConnectionEstablished {
+            peer_id,
+            address: "/ip4/127.0.0.1/tcp/4001".parse().unwrap(),
+            num_established: 1,
+        });
+        stats.record_event(&DriaP2PEvent::ConnectionClosed {
+            peer_id,
+            num_established: 0,
+            cause: None,
+        });
+        stats.record_event(&DriaP2PEvent::PeerBlacklisted {
+            peer_id,
+            protocol: "dria/0.0".to_string(),
+        });
+        stats.record_event(&DriaP2PEvent::NatStatusChanged {
+            old: dkn_p2p::libp2p::autonat::NatStatus::Unknown,
+            new: dkn_p2p::libp2p::autonat::NatStatus::Private,
+        });
+
+        assert_eq!(stats.network.connections_established, 1);
+        assert_eq!(stats.network.connections_closed, 1);
+        assert_eq!(stats.network.blacklisted_peers, 1);
+        assert_eq!(stats.network.dial_failures, 0);
+        assert_eq!(stats.network.nat_status.as_deref(), Some("Private"));
+    }
+}
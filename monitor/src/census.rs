@@ -0,0 +1,197 @@
+use std::{collections::HashMap, time::Duration};
+
+use dkn_compute::handlers::PingpongResponse;
+use dkn_p2p::libp2p::PeerId;
+use serde::Serialize;
+
+/// The latest heartbeat of a peer, as observed from its `pong` messages.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerRecord {
+    pub peer_id: String,
+    /// Timestamp (nanoseconds) of the last `pong` from this peer.
+    pub last_seen: u128,
+    /// Version of the node, from `DriaMessage.version`.
+    pub version: String,
+    /// Advertised models.
+    pub models: Vec<String>,
+    /// Number of pending `single` tasks.
+    pub pending_single: usize,
+    /// Number of pending `batch` tasks.
+    pub pending_batch: usize,
+}
+
+/// Number of nodes serving a model, and how loaded they are.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCensus {
+    pub nodes: usize,
+    pub pending_tasks: usize,
+}
+
+/// A census of the nodes in the network, built from `pong` messages.
+#[derive(Debug, Clone, Default)]
+pub struct PeerCensus {
+    peers: HashMap<PeerId, PeerRecord>,
+}
+
+impl PeerCensus {
+    /// Peers that have not been seen within this window are not considered active.
+    pub const ACTIVE_WINDOW: Duration = Duration::from_secs(10 * 60);
+    /// Peers that have not been seen within this window are removed, see [`PeerCensus::prune`].
+    pub const RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Records a `pong` from the given peer.
+    pub fn record(
+        &mut self,
+        peer_id: PeerId,
+        version: &str,
+        response: &PingpongResponse,
+        seen_at: u128,
+    ) {
+        self.peers.insert(
+            peer_id,
+            PeerRecord {
+                peer_id: peer_id.to_string(),
+                last_seen: seen_at,
+                version: version.to_string(),
+                models: response
+                    .models
+                    .iter()
+                    .map(|(_, model)| model.to_string())
+                    .collect(),
+                pending_single: response.pending_tasks[0],
+                pending_batch: response.pending_tasks[1],
+            },
+        );
+    }
+
+    /// Removes the peers that were not seen after the given time.
+    pub fn prune(&mut self, since: u128) {
+        self.peers.retain(|_, record| record.last_seen >= since);
+    }
+
+    /// Number of peers in the census.
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Returns the peers that were seen after the given time, sorted by their peer id.
+    pub fn active(&self, since: u128) -> Vec<&PeerRecord> {
+        let mut peers = self
+            .peers
+            .values()
+            .filter(|record| record.last_seen >= since)
+            .collect::<Vec<_>>();
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        peers
+    }
+
+    /// Returns the number of active nodes & their pending tasks for each model.
+    pub fn models(&self, since: u128) -> HashMap<String, ModelCensus> {
+        let mut models: HashMap<String, ModelCensus> = HashMap::new();
+        for record in self.active(since) {
+            for model in &record.models {
+                let census = models.entry(model.clone()).or_default();
+                census.nodes += 1;
+                census.pending_tasks += record.pending_single + record.pending_batch;
+            }
+        }
+        models
+    }
+
+    /// Exports the peers seen after the given time as a JSON array.
+    pub fn to_json(&self, since: u128) -> serde_json::Value {
+        serde_json::json!(self.active(since))
+    }
+
+    /// Exports the peers seen after the given time as CSV, models are separated by `;`.
+    pub fn to_csv(&self, since: u128) -> String {
+        let mut csv =
+            String::from("peer_id,last_seen,version,models,pending_single,pending_batch\n");
+        for record in self.active(since) {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                record.peer_id,
+                record.last_seen,
+                escape_csv(&record.version),
+                escape_csv(&record.models.join(";")),
+                record.pending_single,
+                record.pending_batch
+            ));
+        }
+        csv
+    }
+}
+
+/// Quotes the field if it has a comma, quote or newline within.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pong(models: &[&str], pending_tasks: [usize; 2]) -> PingpongResponse {
+        serde_json::from_value(serde_json::json!({
+            "uuid": "uuid",
+            "models": models.iter().map(|m| ["ollama", m]).collect::<Vec<_>>(),
+            "pending_tasks": pending_tasks,
+        }))
+        .expect("should parse pong")
+    }
+
+    #[test]
+    fn test_census() {
+        let mut census = PeerCensus::default();
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+
+        census.record(peer_a, "0.2.33", &pong(&["llama3.1:latest"], [1, 0]), 100);
+        census.record(
+            peer_b,
+            "0.2.32",
+            &pong(&["llama3.1:latest", "gemma2:9b-instruct-q8_0"], [2, 3]),
+            50,
+        );
+        assert_eq!(census.len(), 2);
+
+        let models = census.models(0);
+        assert_eq!(
+            models["llama3.1:latest"],
+            ModelCensus {
+                nodes: 2,
+                pending_tasks: 6
+            }
+        );
+        assert_eq!(models["gemma2:9b-instruct-q8_0"].nodes, 1);
+
+        // only the recently seen peer is active
+        assert_eq!(census.active(75).len(), 1);
+        assert_eq!(census.models(75)["llama3.1:latest"].nodes, 1);
+
+        let csv = census.to_csv(75);
+        assert_eq!(
+            csv,
+            format!(
+                "peer_id,last_seen,version,models,pending_single,pending_batch\n{},100,0.2.33,llama3.1:latest,1,0\n",
+                peer_a
+            )
+        );
+        assert_eq!(census.to_json(0).as_array().unwrap().len(), 2);
+
+        // stale peers are removed
+        let mut pruned = census.clone();
+        pruned.prune(75);
+        assert_eq!(pruned.len(), 1);
+
+        // a newer pong replaces the older one
+        census.record(peer_b, "0.2.33", &pong(&[], [0, 0]), 200);
+        assert_eq!(census.len(), 2);
+        assert!(!census.models(0).contains_key("gemma2:9b-instruct-q8_0"));
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    fs::OpenOptions, io::Write, net::SocketAddr, os::unix::fs::OpenOptionsExt, path::PathBuf,
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use dkn_p2p::{
+    libp2p::Multiaddr,
+    libp2p_identity::{secp256k1, Keypair},
+    DriaNetworkType, DriaP2PProtocol, EnvelopeFormat,
+};
+use eyre::{Context, Result};
+
+use crate::alerts::{AlertAction, AlertRules};
+
+/// Monitors the tasks & heartbeats within Dria Knowledge Network.
+///
+/// Each option can also be given with its environment variable, e.g. within `.env`.
+#[derive(Parser, Debug)]
+#[command(name = "dkn-monitor", version)]
+pub struct Cli {
+    /// Network to monitor.
+    #[arg(long, env = "DKN_NETWORK", value_enum, default_value_t = Network::Pro)]
+    pub network: Network,
+
+    /// Address to listen on for peer-to-peer connections.
+    #[arg(
+        long,
+        env = "DKN_MONITOR_LISTEN_ADDR",
+        default_value = "/ip4/0.0.0.0/tcp/4069"
+    )]
+    pub listen_addr: Multiaddr,
+
+    /// File with a hex-encoded secp256k1 secret key, created if it does not exist.
+    ///
+    /// A random key is used if not given, i.e. the peer id changes on every run.
+    #[arg(long, env = "DKN_MONITOR_KEYPAIR")]
+    pub keypair: Option<PathBuf>,
+
+    /// Extra topics to watch, in addition to `task`, `results`, `ping` and `pong`.
+    #[arg(long, env = "DKN_MONITOR_TOPICS", value_delimiter = ',')]
+    pub topics: Vec<String>,
+
+    /// Interval in seconds to print pending tasks; peers are printed at twice this interval.
+    #[arg(long, env = "DKN_MONITOR_PRINT_INTERVAL", default_value_t = 20)]
+    pub print_interval: u64,
+
+    /// Maximum age of the messages in seconds, must be enabled if the network uses message freshness.
+    #[arg(long, env = "DKN_MONITOR_MESSAGE_FRESHNESS")]
+    pub message_freshness: Option<u64>,
+
+    /// Must be enabled if the network uses the CBOR envelope, i.e. the nodes use `DKN_P2P_ENVELOPE=cbor`.
+    #[arg(long, env = "DKN_MONITOR_CBOR")]
+    pub cbor: bool,
+
+    /// Output format of the observed messages.
+    #[arg(long, env = "DKN_MONITOR_OUTPUT", value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Path to the database, `:memory:` for an in-memory database.
+    #[arg(long, env = "DKN_MONITOR_DB_PATH", default_value = "dkn-monitor.db")]
+    pub db_path: String,
+
+    /// Data older than this many hours is removed from the database.
+    #[arg(long, env = "DKN_MONITOR_RETENTION_HOURS", default_value_t = 7 * 24)]
+    pub retention_hours: u64,
+
+    /// Address to serve the dashboard at, e.g. `127.0.0.1:8080`.
+    #[arg(long, env = "DKN_MONITOR_HTTP_ADDR")]
+    pub http_addr: Option<SocketAddr>,
+
+    /// Interval in seconds to request the specs of the peers, disabled by default.
+    ///
+    /// Nodes only respond to the RPCs, see the README.
+    #[arg(long, env = "DKN_MONITOR_SPEC_INTERVAL", default_value_t = 0)]
+    pub spec_interval: u64,
+
+    /// Alert if pending tasks are above this.
+    #[arg(long, env = "DKN_MONITOR_ALERT_MAX_PENDING_TASKS")]
+    pub alert_max_pending_tasks: Option<usize>,
+
+    /// Alert if the error rate of a model is above this percentage.
+    #[arg(long, env = "DKN_MONITOR_ALERT_MAX_ERROR_RATE")]
+    pub alert_max_error_rate: Option<f64>,
+
+    /// Alert if no pings are seen for this many seconds.
+    #[arg(long, env = "DKN_MONITOR_ALERT_MAX_PING_SILENCE")]
+    pub alert_max_ping_silence: Option<u64>,
+
+    /// Alert if mesh peers are below this.
+    #[arg(long, env = "DKN_MONITOR_ALERT_MIN_MESH_PEERS")]
+    pub alert_min_mesh_peers: Option<usize>,
+
+    /// URL to POST the alerts to as JSON.
+    #[arg(long, env = "DKN_MONITOR_ALERT_WEBHOOK")]
+    pub alert_webhook: Option<String>,
+
+    /// Command to run on alerts with `sh -c`, the alert is given as JSON in `DKN_ALERT`.
+    #[arg(long, env = "DKN_MONITOR_ALERT_COMMAND")]
+    pub alert_command: Option<String>,
+}
+
+/// Network to monitor, see [`DriaNetworkType`].
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Community,
+    Pro,
+    Test,
+}
+
+impl From<Network> for DriaNetworkType {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Community => DriaNetworkType::Community,
+            Network::Pro => DriaNetworkType::Pro,
+            Network::Test => DriaNetworkType::Test,
+        }
+    }
+}
+
+/// Output format of the observed messages.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable logs only.
+    Text,
+    /// One JSON line per observed message to `stdout`, logs are still written to `stderr`.
+    Json,
+}
+
+impl Cli {
+    /// Network type to monitor.
+    pub fn network(&self) -> DriaNetworkType {
+        self.network.into()
+    }
+
+    /// Interval to print pending tasks.
+    pub fn print_interval(&self) -> Duration {
+        Duration::from_secs(self.print_interval.max(1))
+    }
+
+    /// Retention of the database.
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention_hours * 60 * 60)
+    }
+
+    /// Protocol of the network, with message freshness & CBOR envelope if enabled.
+    pub fn protocol(&self) -> DriaP2PProtocol {
+        let mut protocol = DriaP2PProtocol::new_major_minor(self.network().protocol_name());
+        if let Some(secs) = self.message_freshness {
+            protocol = protocol.with_message_freshness(Duration::from_secs(secs));
+        }
+        if self.cbor {
+            protocol = protocol.with_envelope(EnvelopeFormat::Cbor);
+        }
+        protocol
+    }
+
+    /// Interval to request the specs of the peers, `None` if disabled.
+    pub fn spec_interval(&self) -> Option<Duration> {
+        (self.spec_interval != 0).then(|| Duration::from_secs(self.spec_interval))
+    }
+
+    /// Alert rules, with the error rate given as a ratio.
+    pub fn alert_rules(&self) -> AlertRules {
+        AlertRules {
+            max_pending_tasks: self.alert_max_pending_tasks,
+            max_error_rate: self.alert_max_error_rate.map(|rate| rate / 100.0),
+            max_ping_silence: self.alert_max_ping_silence.map(Duration::from_secs),
+            min_mesh_peers: self.alert_min_mesh_peers,
+        }
+    }
+
+    /// Actions to take on alerts.
+    pub fn alert_actions(&self) -> Vec<AlertAction> {
+        let webhook = self.alert_webhook.clone().map(AlertAction::Webhook);
+        let command = self.alert_command.clone().map(AlertAction::Command);
+        webhook.into_iter().chain(command).collect()
+    }
+
+    /// Reads the keypair from the given file, or creates a new one there that only the owner can access.
+    ///
+    /// Returns a random keypair if no file is given.
+    pub fn keypair(&self) -> Result<Keypair> {
+        let Some(path) = &self.keypair else {
+            return Ok(Keypair::generate_secp256k1());
+        };
+
+        if path.exists() {
+            let secret_hex = std::fs::read_to_string(path)
+                .wrap_err(format!("could not read {}", path.display()))?;
+            let secret_bytes = hex::decode(secret_hex.trim().trim_start_matches("0x"))
+                .wrap_err("could not decode secret key")?;
+            let secret_key = secp256k1::SecretKey::try_from_bytes(secret_bytes)
+                .wrap_err("could not parse secret key")?;
+
+            Ok(secp256k1::Keypair::from(secret_key).into())
+        } else {
+            let keypair = secp256k1::Keypair::generate();
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .and_then(|mut file| {
+                    file.write_all(hex::encode(keypair.secret().to_bytes()).as_bytes())
+                })
+                .wrap_err(format!("could not write {}", path.display()))?;
+            log::info!("Created a new keypair at {}", path.display());
+
+            Ok(keypair.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        let cli = Cli::try_parse_from([
+            "dkn-monitor",
+            "--network",
+            "community",
+            "--listen-addr",
+            "/ip4/127.0.0.1/tcp/4070",
+            "--topics",
+            "spec,heartbeat",
+            "--output",
+            "json",
+            "--spec-interval",
+            "600",
+        ])
+        .expect("should parse");
+
+        assert!(matches!(cli.network(), DriaNetworkType::Community));
+        assert_eq!(cli.listen_addr.to_string(), "/ip4/127.0.0.1/tcp/4070");
+        assert_eq!(cli.topics, vec!["spec", "heartbeat"]);
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert_eq!(cli.spec_interval(), Some(Duration::from_secs(600)));
+
+        // unknown networks are rejected instead of falling back to the default one
+        assert!(Cli::try_parse_from(["dkn-monitor", "--network", "prod"]).is_err());
+
+        let cli = Cli::try_parse_from(["dkn-monitor"]).expect("should parse");
+        assert_eq!(cli.spec_interval(), None);
+    }
+
+    #[test]
+    fn test_keypair_file() {
+        let path = std::env::temp_dir().join(format!("dkn-monitor-{}.key", std::process::id()));
+        let cli = Cli::try_parse_from(["dkn-monitor", "--keypair", path.to_str().unwrap()])
+            .expect("should parse");
+
+        // the keypair is created once and then re-used
+        let created = cli.keypair().expect("should create keypair");
+        let loaded = cli.keypair().expect("should load keypair");
+        assert_eq!(created.public(), loaded.public());
+
+        // the secret key is only readable by the owner
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
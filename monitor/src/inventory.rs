@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use dkn_compute::utils::Specs;
+use dkn_p2p::libp2p::PeerId;
+use serde::Serialize;
+
+/// The latest specs of a node, as returned by its `SpecResponder`.
+///
+/// The public IP lookup of the node is not kept.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSpecs {
+    pub peer_id: String,
+    /// Timestamp (nanoseconds) of the last response from this node.
+    pub reported_at: u128,
+    /// Total memory in bytes.
+    pub total_mem: u64,
+    /// Free memory in bytes.
+    pub free_mem: u64,
+    /// Number of physical CPU cores, if known.
+    pub num_cpus: Option<usize>,
+    /// Global CPU usage, in percentage.
+    pub cpu_usage: f32,
+    /// Operating system name, e.g. `linux`.
+    pub os: String,
+    /// CPU architecture, e.g. `x86_64`.
+    pub arch: String,
+    /// Models served by the node.
+    pub models: Vec<String>,
+}
+
+/// Aggregated hardware of the nodes in the inventory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryReport {
+    /// Number of nodes that have reported their specs.
+    pub nodes: usize,
+    /// Total memory of all nodes in bytes.
+    pub total_mem: u64,
+    /// Free memory of all nodes in bytes.
+    pub free_mem: u64,
+    /// Total number of physical CPU cores, of the nodes that report it.
+    pub num_cpus: usize,
+    /// Average CPU usage of the nodes, in percentage.
+    pub avg_cpu_usage: f32,
+    /// Number of nodes per `os/arch`, e.g. `linux/x86_64`.
+    pub platforms: BTreeMap<String, usize>,
+    /// Number of nodes per model.
+    pub models: BTreeMap<String, usize>,
+}
+
+/// An inventory of the hardware in the network, built from the spec responses of the nodes.
+#[derive(Debug, Clone, Default)]
+pub struct FleetInventory {
+    nodes: HashMap<PeerId, NodeSpecs>,
+}
+
+impl FleetInventory {
+    /// Nodes that have not reported within this window are removed, see [`FleetInventory::prune`].
+    pub const RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Records the specs reported by the given peer.
+    pub fn record(&mut self, peer_id: PeerId, specs: &Specs, reported_at: u128) {
+        self.nodes.insert(
+            peer_id,
+            NodeSpecs {
+                peer_id: peer_id.to_string(),
+                reported_at,
+                total_mem: specs.total_mem,
+                free_mem: specs.free_mem,
+                num_cpus: specs.num_cpus,
+                cpu_usage: specs.cpu_usage,
+                os: specs.os.clone(),
+                arch: specs.arch.clone(),
+                models: specs.models.clone(),
+            },
+        );
+    }
+
+    /// Removes the nodes that have not reported after the given time.
+    pub fn prune(&mut self, since: u128) {
+        self.nodes.retain(|_, specs| specs.reported_at >= since);
+    }
+
+    /// Returns the time (nanoseconds) of the last report of the given peer, if any.
+    pub fn reported_at(&self, peer_id: &PeerId) -> Option<u128> {
+        self.nodes.get(peer_id).map(|specs| specs.reported_at)
+    }
+
+    /// Returns all nodes, sorted by their peer id.
+    pub fn nodes(&self) -> Vec<&NodeSpecs> {
+        let mut nodes = self.nodes.values().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        nodes
+    }
+
+    /// Aggregates the specs of all nodes.
+    pub fn report(&self) -> InventoryReport {
+        let mut report = InventoryReport {
+            nodes: self.nodes.len(),
+            ..Default::default()
+        };
+
+        let mut cpu_usage_sum = 0.0;
+        for specs in self.nodes.values() {
+            // the specs are reported by the nodes, so do not let them overflow the sums
+            report.total_mem = report.total_mem.saturating_add(specs.total_mem);
+            report.free_mem = report.free_mem.saturating_add(specs.free_mem);
+            report.num_cpus = report
+                .num_cpus
+                .saturating_add(specs.num_cpus.unwrap_or_default());
+            cpu_usage_sum += specs.cpu_usage;
+
+            *report
+                .platforms
+                .entry(format!("{}/{}", specs.os, specs.arch))
+                .or_default() += 1;
+            for model in &specs.models {
+                *report.models.entry(model.clone()).or_default() += 1;
+            }
+        }
+        if report.nodes > 0 {
+            report.avg_cpu_usage = cpu_usage_sum / report.nodes as f32;
+        }
+
+        report
+    }
+
+    /// Exports the report along with the specs of each node as JSON.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "report": self.report(),
+            "nodes": self.nodes(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(total_mem: u64, num_cpus: Option<usize>, cpu_usage: f32, os: &str) -> Specs {
+        Specs {
+            total_mem,
+            free_mem: total_mem / 2,
+            num_cpus,
+            cpu_usage,
+            os: os.to_string(),
+            arch: "x86_64".to_string(),
+            lookup: None,
+            models: vec!["gpt-4o".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_inventory() {
+        let mut inventory = FleetInventory::default();
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+        inventory.record(a, &specs(16, Some(8), 10.0, "linux"), 100);
+        inventory.record(b, &specs(32, None, 30.0, "macos"), 200);
+        inventory.record(c, &specs(8, Some(4), 50.0, "linux"), 300);
+        inventory.record(a, &specs(16, Some(8), 20.0, "linux"), 400); // updated
+
+        let report = inventory.report();
+        assert_eq!(report.nodes, 3);
+        assert_eq!(report.total_mem, 56);
+        assert_eq!(report.free_mem, 28);
+        assert_eq!(report.num_cpus, 12);
+        assert_eq!(report.avg_cpu_usage, 100.0 / 3.0);
+        assert_eq!(report.platforms["linux/x86_64"], 2);
+        assert_eq!(report.platforms["macos/x86_64"], 1);
+        assert_eq!(report.models["gpt-4o"], 3);
+        assert_eq!(inventory.reported_at(&a), Some(400));
+
+        // `b` is the only one that has not reported since
+        inventory.prune(250);
+        assert_eq!(inventory.report().nodes, 2);
+        assert_eq!(inventory.reported_at(&b), None);
+
+        // bogus specs saturate instead of overflowing
+        inventory.record(b, &specs(u64::MAX, Some(usize::MAX), 0.0, "linux"), 500);
+        let report = inventory.report();
+        assert_eq!(report.total_mem, u64::MAX);
+        assert_eq!(report.num_cpus, usize::MAX);
+    }
+}
@@ -1,15 +1,30 @@
-use dkn_compute::refresh_dria_nodes;
-use dkn_p2p::{
-    libp2p_identity::Keypair, DriaNetworkType, DriaNodes, DriaP2PClient, DriaP2PProtocol,
-};
+use clap::Parser;
+use dkn_compute::{refresh_dria_nodes, DriaNodesSource};
+use dkn_p2p::{DriaNodes, DriaP2PClient, DriaP2PConfig};
 use tokio_util::sync::CancellationToken;
 
+mod alerts;
+
+mod api;
+
+mod cli;
+use cli::Cli;
+
+mod census;
+
+mod inventory;
+
 mod node;
-use node::DriaMonitorNode;
+use node::{DriaMonitorConfig, DriaMonitorNode};
+
+mod store;
+use store::MonitorStore;
+
+mod tracker;
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    dotenvy::dotenv().expect("could not load .env");
+    let dotenv_result = dotenvy::dotenv();
 
     env_logger::builder()
         .filter(None, log::LevelFilter::Off)
@@ -19,22 +34,28 @@ async fn main() -> eyre::Result<()> {
         .parse_default_env() // reads RUST_LOG variable
         .init();
 
-    let network = std::env::var("DKN_NETWORK")
-        .map(|s| DriaNetworkType::from(s.as_str()))
-        .unwrap_or(DriaNetworkType::Pro);
+    if let Err(e) = dotenv_result {
+        log::warn!("Could not load .env file: {}", e);
+    }
+
+    // parse arguments after `.env` is loaded, so that they can be given from there as well
+    let cli = Cli::parse();
+
+    let network = cli.network();
     let mut nodes = DriaNodes::new(network);
-    refresh_dria_nodes(&mut nodes).await?;
+    // the monitor does not have admin keys, so the nodes are not verified
+    refresh_dria_nodes(&mut nodes, &DriaNodesSource::default().with_envs(), None).await?;
 
     // setup p2p client
-    let listen_addr = "/ip4/0.0.0.0/tcp/4069".parse()?;
-    log::info!("Listen Address: {}", listen_addr);
-    let keypair = Keypair::generate_secp256k1();
+    log::info!("Listen Address: {}", cli.listen_addr);
+    let keypair = cli.keypair()?;
     log::info!("PeerID: {}", keypair.public().to_peer_id());
     let (client, commander, msg_rx, _) = DriaP2PClient::new(
         keypair,
-        listen_addr,
+        cli.listen_addr.clone(),
         &nodes,
-        DriaP2PProtocol::new_major_minor(network.protocol_name()),
+        cli.protocol(),
+        DriaP2PConfig::default().with_envs(),
     )?;
 
     // spawn p2p task
@@ -62,16 +83,44 @@ async fn main() -> eyre::Result<()> {
         network,
         network.protocol_name()
     );
-    let mut monitor = DriaMonitorNode::new(commander, msg_rx);
+    log::info!("Database: {}", cli.db_path);
+    let store = MonitorStore::open(&cli.db_path)?;
+    let stats = api::SharedStats::default();
+    let config = DriaMonitorConfig {
+        retention: cli.retention(),
+        print_interval: cli.print_interval(),
+        topics: cli.topics.clone(),
+        output: cli.output,
+        alert_rules: cli.alert_rules(),
+        alert_actions: cli.alert_actions(),
+        spec_interval: cli.spec_interval(),
+    };
+    let mut monitor = DriaMonitorNode::new(commander, msg_rx, store, stats.clone(), config);
+
+    // serve the dashboard, if an address is given
+    let api_handle = cli.http_addr.map(|addr| {
+        let api_token = token.clone();
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(addr, stats, api_token).await {
+                log::error!("Dashboard error: {:?}", e);
+            }
+        })
+    });
 
     // setup monitor
     monitor.setup().await?;
-    monitor.run(token).await;
+    monitor.run(token.clone()).await;
     monitor.shutdown().await?;
 
+    // the monitor may have stopped on its own, so stop the others as well
+    token.cancel();
+
     log::info!("Waiting for task handles...");
     p2p_handle.await?;
     sig_handle.await?;
+    if let Some(api_handle) = api_handle {
+        api_handle.await?;
+    }
 
     log::info!("Done!");
     Ok(())
@@ -1,85 +1,174 @@
-use std::collections::HashMap;
+use std::{collections::HashSet, time::Duration};
 
 use dkn_compute::{
-    handlers::{WorkflowHandler, WorkflowPayload},
-    payloads::{TaskRequestPayload, TaskResponsePayload},
-    utils::DriaMessage,
+    handlers::{PingpongHandler, PingpongResponse, WorkflowHandler, WorkflowPayload},
+    payloads::{
+        TaskErrorPayload, TaskRequestPayload, TaskResponsePayload, TaskResultAnnouncement,
+        TaskResultChunk,
+    },
+    responders::specs,
+    utils::{chunks::ChunkAssembler, crypto::public_to_peer_id, DriaMessage},
 };
 use dkn_p2p::{
     libp2p::{
         gossipsub::{Message, MessageId},
         PeerId,
     },
-    DriaP2PCommander,
+    DriaP2PCommander, DriaP2PEvent,
 };
-use eyre::Result;
-use tokio::sync::mpsc;
+use dkn_utils::get_current_time_nanos;
+use eyre::{eyre, Result};
+use tokio::sync::{broadcast, mpsc};
 use tokio_util::sync::CancellationToken;
 
-const TASK_PRINT_INTERVAL_SECS: u64 = 20;
-const PEER_PRINT_INTERVAL_SECS: u64 = 40;
+use crate::{
+    alerts::{AlertAction, AlertRules, Alerter},
+    api::SharedStats,
+    census::PeerCensus,
+    cli::OutputFormat,
+    inventory::FleetInventory,
+    store::MonitorStore,
+    tracker::TaskTracker,
+};
+
+/// Configurations of the monitor node.
+#[derive(Debug, Clone)]
+pub struct DriaMonitorConfig {
+    /// Data older than this is removed from the store.
+    pub retention: Duration,
+    /// Interval to print pending tasks, peers are printed at twice this interval.
+    pub print_interval: Duration,
+    /// Extra topics to subscribe to.
+    pub topics: Vec<String>,
+    /// Output format of the observed messages.
+    pub output: OutputFormat,
+    /// Rules to alert on.
+    pub alert_rules: AlertRules,
+    /// Actions to take when an alert fires.
+    pub alert_actions: Vec<AlertAction>,
+    /// Interval to request the specs of the peers, disabled if `None`.
+    pub spec_interval: Option<Duration>,
+}
+
+/// Maximum number of spec requests that are waiting for a response at once.
+const MAX_SPEC_REQUESTS: usize = 64;
+/// Buffer size for the spec responses.
+const SPEC_CHANNEL_BUFSIZE: usize = 64;
+
+/// A spec response (or failure) from a peer, along with the request id.
+type SpecResult = (PeerId, String, Result<Vec<u8>>);
 
 pub struct DriaMonitorNode {
     pub p2p: DriaP2PCommander,
     pub msg_rx: mpsc::Receiver<(PeerId, MessageId, Message)>,
+    /// Events of the p2p client, recorded in the statistics.
+    p2p_events: broadcast::Receiver<DriaP2PEvent>,
 
     // task monitoring
-    pub tasks: HashMap<String, TaskRequestPayload<WorkflowPayload>>,
-    pub results: HashMap<String, TaskResponsePayload>,
+    pub tracker: TaskTracker,
+    /// Reassembles the results that are published in chunks.
+    assembler: ChunkAssembler,
+
+    /// Persistent store of observed tasks & results.
+    pub store: MonitorStore,
+    /// Statistics served by the dashboard.
+    pub stats: SharedStats,
+    /// Alerts on the statistics.
+    pub alerter: Alerter,
+    pub config: DriaMonitorConfig,
+
+    // spec requests, the responses are received from the spawned requests
+    spec_tx: mpsc::Sender<SpecResult>,
+    spec_rx: mpsc::Receiver<SpecResult>,
+    /// Peers with a spec request waiting for a response.
+    spec_requests: HashSet<PeerId>,
 }
 
 impl DriaMonitorNode {
     pub fn new(
         p2p: DriaP2PCommander,
         msg_rx: mpsc::Receiver<(PeerId, MessageId, Message)>,
+        store: MonitorStore,
+        stats: SharedStats,
+        config: DriaMonitorConfig,
     ) -> Self {
+        let (spec_tx, spec_rx) = mpsc::channel(SPEC_CHANNEL_BUFSIZE);
+
         Self {
+            p2p_events: p2p.events(),
             p2p,
             msg_rx,
-            tasks: HashMap::new(),
-            results: HashMap::new(),
+            tracker: TaskTracker::default(),
+            assembler: ChunkAssembler::default(),
+            store,
+            stats,
+            alerter: Alerter::new(config.alert_rules.clone(), config.alert_actions.clone()),
+            config,
+            spec_tx,
+            spec_rx,
+            spec_requests: HashSet::new(),
         }
     }
 
     /// Setup the monitor node.
     ///
-    /// Subscribes to task topics.
+    /// Subscribes to task & heartbeat topics, and the extra topics.
     pub async fn setup(&self) -> Result<()> {
-        self.p2p.subscribe(WorkflowHandler::LISTEN_TOPIC).await?;
-        self.p2p.subscribe(WorkflowHandler::RESPONSE_TOPIC).await?;
+        for topic in self.topics() {
+            self.p2p.subscribe(topic).await?;
+        }
 
         Ok(())
     }
 
     /// Shutdown the monitor node.
     ///
-    /// Unsubscribes from task topics, closes channels.
+    /// Unsubscribes from all topics, closes channels.
     pub async fn shutdown(&mut self) -> Result<()> {
         log::info!("Shutting down monitor");
-        self.p2p.unsubscribe(WorkflowHandler::LISTEN_TOPIC).await?;
-        self.p2p
-            .unsubscribe(WorkflowHandler::RESPONSE_TOPIC)
-            .await?;
+        for topic in self.topics() {
+            self.p2p.unsubscribe(topic).await?;
+        }
 
         self.p2p.shutdown().await?;
         self.msg_rx.close();
 
         // print tasks one final time
-        self.handle_task_print();
+        self.handle_task_print().await;
 
         Ok(())
     }
 
+    /// Returns the topics to subscribe to, without duplicates.
+    fn topics(&self) -> Vec<&str> {
+        let mut topics = vec![
+            WorkflowHandler::LISTEN_TOPIC,
+            WorkflowHandler::RESPONSE_TOPIC,
+            PingpongHandler::LISTEN_TOPIC,
+            PingpongHandler::RESPONSE_TOPIC,
+        ];
+        for topic in &self.config.topics {
+            if !topics.contains(&topic.as_str()) {
+                topics.push(topic.as_str());
+            }
+        }
+        topics
+    }
+
     /// Run the monitor node.
     pub async fn run(&mut self, token: CancellationToken) {
-        let mut task_print_interval =
-            tokio::time::interval(tokio::time::Duration::from_secs(TASK_PRINT_INTERVAL_SECS));
-        let mut peer_print_interval =
-            tokio::time::interval(tokio::time::Duration::from_secs(PEER_PRINT_INTERVAL_SECS));
+        let mut task_print_interval = tokio::time::interval(self.config.print_interval);
+        let mut peer_print_interval = tokio::time::interval(self.config.print_interval * 2);
+        let mut spec_interval = tokio::time::interval(
+            self.config
+                .spec_interval
+                .unwrap_or(self.config.print_interval),
+        );
 
         // move one ticks
         task_print_interval.tick().await;
         peer_print_interval.tick().await;
+        spec_interval.tick().await;
 
         loop {
             tokio::select! {
@@ -91,17 +180,92 @@ impl DriaMonitorNode {
                     }
                     None => break, // channel closed, we can return now
                 },
-                _ = task_print_interval.tick() => self.handle_task_print(),
+                // record p2p events, the channel is never closed as we hold a commander
+                p2p_event = self.p2p_events.recv() => match p2p_event {
+                    Ok(event) => self.handle_p2p_event(event).await,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        log::warn!("Missed {} p2p events.", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = task_print_interval.tick() => {
+                    self.handle_task_print().await;
+                    self.handle_store_report().await;
+                    self.handle_alerts().await;
+                },
                 _ = peer_print_interval.tick() => self.handle_peer_print().await,
+                _ = spec_interval.tick(), if self.config.spec_interval.is_some() => {
+                    self.handle_spec_requests().await;
+                },
+                // handle spec responses, the channel is never closed as we hold a sender
+                Some((peer_id, request_id, result)) = self.spec_rx.recv() => {
+                    self.handle_spec_response(peer_id, &request_id, result).await;
+                },
                 _ = token.cancelled() => break,
             }
         }
     }
 
+    /// Records a p2p event, and logs the notable ones.
+    async fn handle_p2p_event(&mut self, event: DriaP2PEvent) {
+        match &event {
+            DriaP2PEvent::PeerBlacklisted { peer_id, protocol } => {
+                log::info!("Blacklisted peer {} with protocol {}", peer_id, protocol);
+            }
+            DriaP2PEvent::LivenessLost { kind } => {
+                log::warn!("Lost connection to all {} peers.", kind);
+            }
+            event => log::debug!("P2P event: {:?}", event),
+        }
+        self.stats.write().await.record_event(&event);
+    }
+
+    /// Evaluates the alert rules w.r.t the latest statistics.
+    ///
+    /// The actions are run in the background, so that the statistics are not locked meanwhile.
+    async fn handle_alerts(&mut self) {
+        let stats = self.stats.read().await;
+        self.alerter.check(&stats, get_current_time_nanos());
+    }
+
     async fn handle_peer_print(&self) {
         match self.p2p.peer_counts().await {
             Ok((mesh, all)) => {
                 log::info!("Peer count: {} / {}", mesh, all);
+
+                let mut stats = self.stats.write().await;
+                stats.mesh_peers = mesh;
+                stats.all_peers = all;
+                stats.peers_counted_at = Some(get_current_time_nanos());
+
+                let active_since =
+                    get_current_time_nanos().saturating_sub(PeerCensus::ACTIVE_WINDOW.as_nanos());
+                log::info!(
+                    "Active nodes: {} / {}",
+                    stats.census.active(active_since).len(),
+                    stats.census.len()
+                );
+                for (model, census) in stats.census.models(active_since) {
+                    log::info!(
+                        "Model {}: {} nodes, {} pending tasks",
+                        model,
+                        census.nodes,
+                        census.pending_tasks
+                    );
+                }
+
+                let inventory = stats.inventory.report();
+                if inventory.nodes > 0 {
+                    log::info!(
+                        "Inventory of {} nodes: {} GiB memory ({} GiB free), {} CPU cores ({:.1}% avg. usage), platforms {:?}",
+                        inventory.nodes,
+                        inventory.total_mem >> 30,
+                        inventory.free_mem >> 30,
+                        inventory.num_cpus,
+                        inventory.avg_cpu_usage,
+                        inventory.platforms
+                    );
+                }
             }
             Err(e) => {
                 log::error!("Error getting peer counts: {:?}", e);
@@ -109,14 +273,95 @@ impl DriaMonitorNode {
         }
     }
 
+    /// Requests the specs of the known peers that do not have a request waiting already.
+    ///
+    /// Each request is made in a separate task, and its response is received by the main loop.
+    async fn handle_spec_requests(&mut self) {
+        let peers = match self.p2p.peers().await {
+            Ok((_, all)) => all,
+            Err(e) => {
+                log::error!("Error getting peers: {:?}", e);
+                return;
+            }
+        };
+
+        let mut requested = 0;
+        for peer_id in peers {
+            if self.spec_requests.len() >= MAX_SPEC_REQUESTS {
+                break;
+            }
+            if self.spec_requests.contains(&peer_id) {
+                continue;
+            }
+
+            let request_id = uuid::Uuid::new_v4().to_string();
+            let data = match serde_json::to_vec(&specs::Request {
+                request_id: request_id.clone(),
+            }) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!("Could not serialize spec request: {:?}", e);
+                    return;
+                }
+            };
+
+            self.spec_requests.insert(peer_id);
+            let p2p = self.p2p.clone();
+            let spec_tx = self.spec_tx.clone();
+            tokio::spawn(async move {
+                let result = p2p.request(peer_id, data).await;
+                let _ = spec_tx.send((peer_id, request_id, result)).await;
+            });
+            requested += 1;
+        }
+
+        log::debug!("Requested specs from {} peers", requested);
+    }
+
+    /// Records the specs of a peer to the inventory, if the response is valid.
+    async fn handle_spec_response(
+        &mut self,
+        peer_id: PeerId,
+        request_id: &str,
+        result: Result<Vec<u8>>,
+    ) {
+        self.spec_requests.remove(&peer_id);
+
+        // nodes only respond to the known RPCs, so failures are expected
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => {
+                log::debug!("Could not get specs of {}: {:?}", peer_id, e);
+                return;
+            }
+        };
+
+        match serde_json::from_slice::<specs::Response>(&data) {
+            Ok(response) if response.request_id == request_id => {
+                log::debug!("Received specs of {}", peer_id);
+                self.stats.write().await.inventory.record(
+                    peer_id,
+                    &response.specs,
+                    get_current_time_nanos(),
+                );
+            }
+            Ok(response) => log::warn!(
+                "Received specs of {} with unexpected request id {}",
+                peer_id,
+                response.request_id
+            ),
+            Err(e) => log::warn!("Could not parse specs of {}: {:?}", peer_id, e),
+        }
+    }
+
     /// Handle incoming gossipsub message.
     ///
-    /// Records the `task` and `result` messages only, does not respond to anything else.
+    /// Records the `task` and `result` messages, and counts the rest; does not respond to anything.
     async fn handle_message(
         &mut self,
         (peer_id, message_id, gossipsub_message): (PeerId, MessageId, Message),
     ) -> Result<()> {
-        log::info!(
+        log::debug!(
             "Received {} message {} from {}",
             gossipsub_message.topic,
             message_id,
@@ -133,42 +378,265 @@ impl DriaMonitorNode {
             .await?;
 
         // parse message, ignore signatures
-        let message: DriaMessage = serde_json::from_slice(&gossipsub_message.data)?;
+        let message = DriaMessage::decode(&gossipsub_message.data)?;
+
+        // the original publisher of the message, if known
+        let source = gossipsub_message.source.unwrap_or(peer_id);
+        let observed_at = get_current_time_nanos();
+        if self.config.output == OutputFormat::Json {
+            print_message_json(&message, &message_id, &peer_id, &source, observed_at);
+        }
+        self.stats
+            .write()
+            .await
+            .record_message(&message.topic, &message.version);
 
         match message.topic.as_str() {
             WorkflowHandler::LISTEN_TOPIC => {
                 let payload: TaskRequestPayload<WorkflowPayload> = message.parse_payload(true)?;
-                self.tasks.insert(payload.task_id.clone(), payload);
+                self.store
+                    .insert_task(&payload, &source, observed_at)
+                    .await?;
+                self.tracker
+                    .insert_task(&payload.task_id, payload.deadline, observed_at);
             }
             WorkflowHandler::RESPONSE_TOPIC => {
-                let payload: TaskResponsePayload = message.parse_payload(false)?;
-                self.results.insert(payload.task_id.clone(), payload);
+                // results are unsigned, whereas errors & chunks are signed by the responder
+                if let Ok(payload) = message.parse_payload::<TaskResponsePayload>(false) {
+                    self.handle_result(&payload, &source, observed_at).await?;
+                } else if let Ok(chunk) = message.parse_payload::<TaskResultChunk>(true) {
+                    // chunks are only reassembled with the announcement of the same signer
+                    let signer = public_to_peer_id(&message.signer()?);
+                    if let Some((announcement, data)) = self.assembler.insert(signer, chunk)? {
+                        self.handle_reassembled(&announcement, &data, &signer, observed_at)
+                            .await?;
+                    }
+                } else if let Ok(announcement) =
+                    message.parse_payload::<TaskResultAnnouncement>(true)
+                {
+                    let signer = public_to_peer_id(&message.signer()?);
+                    log::info!(
+                        "Task {} result of {} bytes is chunked at {} (transfer {})",
+                        announcement.task_id,
+                        announcement.size,
+                        signer,
+                        announcement.transfer_id
+                    );
+                    if let Some((announcement, data)) =
+                        self.assembler.announce(signer, announcement)?
+                    {
+                        self.handle_reassembled(&announcement, &data, &signer, observed_at)
+                            .await?;
+                    }
+                } else {
+                    let payload: TaskErrorPayload = message.parse_payload(true)?;
+                    log::warn!(
+                        "Task {} failed at {} ({}): {}",
+                        payload.task_id,
+                        source,
+                        payload.model,
+                        payload.error
+                    );
+                    self.store
+                        .insert_error(&payload, &source, observed_at)
+                        .await?;
+                    self.tracker.insert_result(&payload.task_id);
+                    self.stats
+                        .write()
+                        .await
+                        .record_outcome(&payload.model, true);
+                }
+            }
+            PingpongHandler::LISTEN_TOPIC => {
+                self.stats.write().await.last_ping_at = Some(observed_at);
+            }
+            PingpongHandler::RESPONSE_TOPIC => {
+                let payload: PingpongResponse = message.parse_payload(true)?;
+                self.stats.write().await.census.record(
+                    source,
+                    &message.version,
+                    &payload,
+                    observed_at,
+                );
             }
-            _ => { /* ignore */ }
+            topic => log::info!("Received {} message from {}", topic, source),
         }
         Ok(())
     }
 
-    /// Print the tasks (ids) that have not been responded to.
-    fn handle_task_print(&self) {
-        let seen_task_ids = self.tasks.keys().collect::<Vec<_>>();
-        let seen_result_ids = self.results.keys().collect::<Vec<_>>();
+    /// Records a task result.
+    async fn handle_result(
+        &mut self,
+        payload: &TaskResponsePayload,
+        source: &PeerId,
+        observed_at: u128,
+    ) -> Result<()> {
+        self.store
+            .insert_result(payload, source, observed_at)
+            .await?;
+        self.stats
+            .write()
+            .await
+            .record_outcome(&payload.model, false);
+        self.tracker.insert_result(&payload.task_id);
+
+        Ok(())
+    }
 
-        // print the tasks that have not been responded to
-        let pending_tasks = seen_task_ids
-            .iter()
-            .filter(|id| !seen_result_ids.contains(*id))
-            .map(|id| self.tasks.get(*id).unwrap())
-            .collect::<Vec<_>>();
+    /// Records a task result that was reassembled from its chunks.
+    async fn handle_reassembled(
+        &mut self,
+        announcement: &TaskResultAnnouncement,
+        data: &[u8],
+        source: &PeerId,
+        observed_at: u128,
+    ) -> Result<()> {
+        let payload: TaskResponsePayload = serde_json::from_slice(data)?;
+        if payload.task_id != announcement.task_id {
+            return Err(eyre!(
+                "transfer {} is announced for task {} but contains task {}",
+                announcement.transfer_id,
+                announcement.task_id,
+                payload.task_id
+            ));
+        }
+
+        log::info!(
+            "Reassembled task {} result of {} bytes from {} chunks",
+            payload.task_id,
+            announcement.size,
+            announcement.chunks
+        );
+        self.handle_result(&payload, source, observed_at).await
+    }
+
+    /// Prunes the store w.r.t retention, and prints the expired tasks & latencies per model.
+    async fn handle_store_report(&self) {
+        let now = get_current_time_nanos();
+        match self
+            .store
+            .prune(now.saturating_sub(self.config.retention.as_nanos()))
+            .await
+        {
+            Ok(0) => {}
+            Ok(removed) => log::info!("Removed {} old rows from the store", removed),
+            Err(e) => log::error!("Error pruning store: {:?}", e),
+        }
+
+        match self.store.expired_tasks(now).await {
+            Ok(expired) if !expired.is_empty() => {
+                log::info!("Tasks with no result after deadline: {}", expired.len());
+                log::debug!(
+                    "Tasks with no result after deadline: {:#?}",
+                    expired
+                        .iter()
+                        .map(|t| format!(
+                            "{} (from {}, deadline {})",
+                            t.task_id, t.peer_id, t.deadline
+                        ))
+                        .collect::<Vec<_>>()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Error querying expired tasks: {:?}", e),
+        }
+
+        match self.store.latency_by_model().await {
+            Ok(latencies) => {
+                for (model, summary) in latencies {
+                    log::info!(
+                        "Latency of {} (ms, n={}): p50 {} | p90 {} | p99 {} | max {}",
+                        model,
+                        summary.count,
+                        summary.p50,
+                        summary.p90,
+                        summary.p99,
+                        summary.max
+                    );
+                }
+            }
+            Err(e) => log::error!("Error querying latencies: {:?}", e),
+        }
+
+        match self.store.latency_by_peer().await {
+            Ok(latencies) => {
+                for (peer_id, summary) in latencies {
+                    log::debug!(
+                        "Latency of {} (ms, n={}): p50 {} | p90 {} | p99 {} | max {}",
+                        peer_id,
+                        summary.count,
+                        summary.p50,
+                        summary.p90,
+                        summary.p99,
+                        summary.max
+                    );
+                }
+            }
+            Err(e) => log::error!("Error querying latencies: {:?}", e),
+        }
+    }
+
+    /// Expire the tracked tasks, and print the tasks (ids) that have not been responded to.
+    async fn handle_task_print(&mut self) {
+        self.assembler.prune();
+        let expired = self.tracker.expire(get_current_time_nanos());
+        if !expired.is_empty() {
+            log::warn!(
+                "Expired tasks without a result ({}): {:#?}",
+                expired.len(),
+                expired
+            );
+        }
 
         log::info!(
             "Pending tasks ({} / {}): {:#?}",
-            pending_tasks.len(),
-            self.tasks.len(),
-            pending_tasks
-                .iter()
-                .map(|t| t.task_id.clone())
-                .collect::<Vec<_>>()
+            self.tracker.pending(),
+            self.tracker.len(),
+            self.tracker.pending_ids()
         );
+
+        // also prune the peers that have not been seen (or reported) for a while
+        let census_since =
+            get_current_time_nanos().saturating_sub(PeerCensus::RETENTION.as_nanos());
+        let inventory_since =
+            get_current_time_nanos().saturating_sub(FleetInventory::RETENTION.as_nanos());
+
+        let mut stats = self.stats.write().await;
+        stats.pending_tasks = self.tracker.pending();
+        stats.tasks = self.tracker.counters().clone();
+        stats.census.prune(census_since);
+        stats.inventory.prune(inventory_since);
     }
 }
+
+/// Prints the message as a single JSON line to `stdout`.
+///
+/// The payload is decoded as JSON, with or without a signature in front; if it is not JSON
+/// it is printed as the base64 string as is.
+fn print_message_json(
+    message: &DriaMessage,
+    message_id: &MessageId,
+    peer_id: &PeerId,
+    source: &PeerId,
+    observed_at: u128,
+) {
+    let payload = message
+        .parse_payload::<serde_json::Value>(true)
+        .or_else(|_| message.parse_payload::<serde_json::Value>(false))
+        .unwrap_or_else(|_| serde_json::Value::String(message.payload.clone()));
+
+    println!(
+        "{}",
+        serde_json::json!({
+            "observedAt": observed_at,
+            "messageId": message_id.to_string(),
+            "peerId": peer_id.to_string(),
+            "source": source.to_string(),
+            "topic": message.topic,
+            "version": message.version,
+            "identity": message.identity,
+            "timestamp": message.timestamp,
+            "payload": payload,
+        })
+    );
+}
@@ -0,0 +1,410 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use dkn_compute::payloads::{TaskErrorPayload, TaskRequestPayload, TaskResponsePayload, TaskStats};
+use dkn_p2p::libp2p::PeerId;
+use eyre::{eyre, Context, Result};
+use rusqlite::{params, Connection};
+
+/// Latency percentiles in milliseconds, computed from the `TaskStats` of results.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct LatencySummary {
+    /// Number of results with stats.
+    pub count: usize,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl LatencySummary {
+    /// Computes the summary with nearest-rank percentiles over the given latencies.
+    pub fn from_latencies(mut latencies: Vec<u64>) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort_unstable();
+
+        let percentile = |p: usize| {
+            let rank = (p * latencies.len()).div_ceil(100).max(1);
+            latencies[rank - 1]
+        };
+
+        Self {
+            count: latencies.len(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: latencies[latencies.len() - 1],
+        }
+    }
+}
+
+/// A task that has not received any result before its deadline.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ExpiredTask {
+    pub task_id: String,
+    pub peer_id: String,
+    pub deadline: u128,
+}
+
+/// Persistent store of observed tasks & results, backed by SQLite.
+///
+/// Timestamps are stored in nanoseconds as text, since SQLite integers are 64-bit only.
+///
+/// The queries are blocking, so they are run within [`tokio::task::spawn_blocking`]
+/// to keep the async loop of the monitor responsive.
+#[derive(Clone)]
+pub struct MonitorStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl MonitorStore {
+    /// Opens (or creates) the store at the given path, `:memory:` opens an in-memory store.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open(path.as_ref())
+            .wrap_err(format!("could not open {}", path.as_ref().display()))?;
+        Self::new(conn)
+    }
+
+    /// Opens an in-memory store, which is lost on exit.
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS tasks (
+                task_id     TEXT PRIMARY KEY,
+                peer_id     TEXT NOT NULL,
+                deadline    TEXT NOT NULL,
+                observed_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS results (
+                task_id              TEXT NOT NULL,
+                peer_id              TEXT NOT NULL,
+                model                TEXT NOT NULL,
+                error                TEXT,
+                received_at          TEXT NOT NULL,
+                published_at         TEXT NOT NULL,
+                execution_started_at TEXT NOT NULL,
+                execution_ended_at   TEXT NOT NULL,
+                observed_at          TEXT NOT NULL,
+                PRIMARY KEY (task_id, peer_id)
+            );
+            CREATE INDEX IF NOT EXISTS tasks_observed_at ON tasks (observed_at);
+            CREATE INDEX IF NOT EXISTS tasks_deadline ON tasks (deadline);
+            CREATE INDEX IF NOT EXISTS results_observed_at ON results (observed_at);",
+        )
+        .wrap_err("could not create tables")?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs the given query on the connection within a blocking thread.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&Connection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn
+                .lock()
+                .map_err(|_| eyre!("store connection is poisoned"))?;
+            query(&conn)
+        })
+        .await
+        .wrap_err("store query panicked")?
+    }
+
+    /// Records a task request published by the given peer.
+    pub async fn insert_task<T>(
+        &self,
+        task: &TaskRequestPayload<T>,
+        peer_id: &PeerId,
+        observed_at: u128,
+    ) -> Result<()> {
+        let (task_id, peer_id) = (task.task_id.clone(), peer_id.to_string());
+        let (deadline, observed_at) = (encode_time(task.deadline), encode_time(observed_at));
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO tasks (task_id, peer_id, deadline, observed_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![task_id, peer_id, deadline, observed_at],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records a task result from the responding peer.
+    pub async fn insert_result(
+        &self,
+        result: &TaskResponsePayload,
+        peer_id: &PeerId,
+        observed_at: u128,
+    ) -> Result<()> {
+        self.insert_outcome(
+            &result.task_id,
+            peer_id,
+            &result.model,
+            None,
+            &result.stats,
+            observed_at,
+        )
+        .await
+    }
+
+    /// Records a task error from the responding peer.
+    pub async fn insert_error(
+        &self,
+        error: &TaskErrorPayload,
+        peer_id: &PeerId,
+        observed_at: u128,
+    ) -> Result<()> {
+        self.insert_outcome(
+            &error.task_id,
+            peer_id,
+            &error.model,
+            Some(&error.error),
+            &error.stats,
+            observed_at,
+        )
+        .await
+    }
+
+    async fn insert_outcome(
+        &self,
+        task_id: &str,
+        peer_id: &PeerId,
+        model: &str,
+        error: Option<&str>,
+        stats: &TaskStats,
+        observed_at: u128,
+    ) -> Result<()> {
+        let (task_id, peer_id, model) =
+            (task_id.to_string(), peer_id.to_string(), model.to_string());
+        let error = error.map(ToString::to_string);
+        let times = [
+            stats.received_at,
+            stats.published_at,
+            stats.execution_started_at,
+            stats.execution_ended_time,
+            observed_at,
+        ]
+        .map(encode_time);
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO results (
+                    task_id, peer_id, model, error,
+                    received_at, published_at, execution_started_at, execution_ended_at, observed_at
+                 ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    task_id, peer_id, model, error, times[0], times[1], times[2], times[3],
+                    times[4]
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the end-to-end latency percentiles (from `received_at` to `published_at`) per model.
+    pub async fn latency_by_model(&self) -> Result<HashMap<String, LatencySummary>> {
+        self.with_conn(|conn| latency_by(conn, "model")).await
+    }
+
+    /// Returns the end-to-end latency percentiles (from `received_at` to `published_at`) per responding peer.
+    pub async fn latency_by_peer(&self) -> Result<HashMap<String, LatencySummary>> {
+        self.with_conn(|conn| latency_by(conn, "peer_id")).await
+    }
+
+    /// Returns the tasks that have no result (or error) although their deadline has passed.
+    ///
+    /// Deadlines are compared as text, which is correct as they are encoded with a fixed width.
+    pub async fn expired_tasks(&self, now: u128) -> Result<Vec<ExpiredTask>> {
+        let now = encode_time(now);
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT task_id, peer_id, deadline FROM tasks
+                 WHERE deadline < ?1
+                 AND NOT EXISTS (SELECT 1 FROM results WHERE results.task_id = tasks.task_id)",
+            )?;
+            let rows = stmt.query_map(params![now], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?;
+
+            let mut expired = Vec::new();
+            for row in rows {
+                let (task_id, peer_id, deadline) = row?;
+                expired.push(ExpiredTask {
+                    task_id,
+                    peer_id,
+                    deadline: decode_time(&deadline)?,
+                });
+            }
+
+            Ok(expired)
+        })
+        .await
+    }
+
+    /// Removes tasks & results observed before the given time, returns the number of removed rows.
+    pub async fn prune(&self, before: u128) -> Result<usize> {
+        let before = encode_time(before);
+        self.with_conn(move |conn| {
+            let tasks =
+                conn.execute("DELETE FROM tasks WHERE observed_at < ?1", params![before])?;
+            let results = conn.execute(
+                "DELETE FROM results WHERE observed_at < ?1",
+                params![before],
+            )?;
+
+            Ok(tasks + results)
+        })
+        .await
+    }
+}
+
+/// Returns the end-to-end latency percentiles of the results, grouped by the given column.
+fn latency_by(conn: &Connection, column: &'static str) -> Result<HashMap<String, LatencySummary>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, received_at, published_at FROM results WHERE error IS NULL",
+        column
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+
+    let mut latencies: HashMap<String, Vec<u64>> = HashMap::new();
+    for row in rows {
+        let (key, received_at, published_at) = row?;
+        let (received_at, published_at) = (decode_time(&received_at)?, decode_time(&published_at)?);
+
+        // results without stats are skipped
+        if received_at == 0 || published_at < received_at {
+            continue;
+        }
+        let latency_ms = ((published_at - received_at) / 1_000_000) as u64;
+        latencies.entry(key).or_default().push(latency_ms);
+    }
+
+    Ok(latencies
+        .into_iter()
+        .map(|(key, latencies)| (key, LatencySummary::from_latencies(latencies)))
+        .collect())
+}
+
+/// Encodes a nanosecond timestamp as a fixed-width string, so that they compare correctly as text.
+fn encode_time(time: u128) -> String {
+    format!("{:039}", time)
+}
+
+fn decode_time(time: &str) -> Result<u128> {
+    time.parse().wrap_err("could not parse timestamp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND_NANOS: u128 = 1_000_000_000;
+
+    fn task(task_id: &str, deadline: u128) -> TaskRequestPayload<()> {
+        serde_json::from_value(serde_json::json!({
+            "taskId": task_id,
+            "deadline": deadline,
+            "input": null,
+            "filter": { "hex": "", "hashes": 0 },
+            "publicKey": "",
+        }))
+        .unwrap()
+    }
+
+    fn result(task_id: &str, model: &str, latency_ms: u128) -> TaskResponsePayload {
+        TaskResponsePayload {
+            task_id: task_id.to_string(),
+            signature: String::default(),
+            ciphertext: String::default(),
+            model: model.to_string(),
+            stats: TaskStats {
+                received_at: SECOND_NANOS,
+                published_at: SECOND_NANOS + latency_ms * 1_000_000,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        let summary = LatencySummary::from_latencies((1..=100).rev().collect());
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, 50);
+        assert_eq!(summary.p90, 90);
+        assert_eq!(summary.p99, 99);
+        assert_eq!(summary.max, 100);
+
+        assert_eq!(
+            LatencySummary::from_latencies(vec![]),
+            LatencySummary::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store() {
+        let store = MonitorStore::open_in_memory().unwrap();
+        let (requester, responder) = (PeerId::random(), PeerId::random());
+
+        store
+            .insert_task(&task("a", 10 * SECOND_NANOS), &requester, 1)
+            .await
+            .unwrap();
+        store
+            .insert_task(&task("b", 10 * SECOND_NANOS), &requester, 1)
+            .await
+            .unwrap();
+        store
+            .insert_task(&task("c", 30 * SECOND_NANOS), &requester, 1)
+            .await
+            .unwrap();
+        store
+            .insert_result(&result("a", "gpt-4o", 100), &responder, 2)
+            .await
+            .unwrap();
+        store
+            .insert_result(&result("c", "gpt-4o", 300), &responder, 2)
+            .await
+            .unwrap();
+
+        // only `b` has no result after its deadline
+        let expired = store.expired_tasks(20 * SECOND_NANOS).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].task_id, "b");
+        assert_eq!(expired[0].peer_id, requester.to_string());
+
+        let by_model = store.latency_by_model().await.unwrap();
+        assert_eq!(by_model["gpt-4o"].count, 2);
+        assert_eq!(by_model["gpt-4o"].max, 300);
+        let by_peer = store.latency_by_peer().await.unwrap();
+        assert_eq!(by_peer[&responder.to_string()].p50, 100);
+
+        // tasks observed at 1 are removed, results observed at 2 are kept
+        assert_eq!(store.prune(2).await.unwrap(), 3);
+        assert!(store.expired_tasks(u128::MAX).await.unwrap().is_empty());
+        assert_eq!(store.latency_by_model().await.unwrap()["gpt-4o"].count, 2);
+    }
+}
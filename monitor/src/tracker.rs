@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+/// Summary counters of the tracked tasks, which are kept after the tasks themselves expire.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCounters {
+    /// Number of tasks seen.
+    pub tasks: usize,
+    /// Number of results (or errors) seen.
+    pub results: usize,
+    /// Number of tasks that have received a result.
+    pub completed: usize,
+    /// Number of tasks that have expired without a result.
+    pub expired: usize,
+    /// Number of results for tasks that are not tracked, e.g. seen before the monitor started.
+    pub unmatched_results: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TaskState {
+    Pending,
+    Completed,
+}
+
+#[derive(Debug, Clone)]
+struct TrackedTask {
+    state: TaskState,
+    expires_at: u128,
+}
+
+/// A time-indexed store of tasks, matching them with their results in O(1).
+///
+/// Each task is kept until its deadline (capped with [`TaskTracker::MAX_TASK_AGE`]), so that
+/// late or duplicate results can still be matched; after that it is removed, and counted as expired
+/// if no result was seen for it.
+#[derive(Debug, Clone, Default)]
+pub struct TaskTracker {
+    tasks: HashMap<String, TrackedTask>,
+    /// Expiry index, ordered by `(expires_at, task_id)`.
+    expiries: BTreeSet<(u128, String)>,
+    /// Number of tasks in `Pending` state.
+    pending: usize,
+    counters: TaskCounters,
+}
+
+impl TaskTracker {
+    /// A task is not kept for longer than this, regardless of its deadline.
+    pub const MAX_TASK_AGE: Duration = Duration::from_secs(60 * 60);
+
+    /// Tracks a task with the given deadline, ignores it if it is already tracked.
+    pub fn insert_task(&mut self, task_id: &str, deadline: u128, now: u128) {
+        if self.tasks.contains_key(task_id) {
+            return;
+        }
+
+        let expires_at = deadline.min(now + Self::MAX_TASK_AGE.as_nanos());
+        self.tasks.insert(
+            task_id.to_string(),
+            TrackedTask {
+                state: TaskState::Pending,
+                expires_at,
+            },
+        );
+        self.expiries.insert((expires_at, task_id.to_string()));
+        self.pending += 1;
+        self.counters.tasks += 1;
+    }
+
+    /// Matches a result (or error) with its task.
+    ///
+    /// Returns `true` if this is the first result for a tracked task.
+    pub fn insert_result(&mut self, task_id: &str) -> bool {
+        self.counters.results += 1;
+
+        match self.tasks.get_mut(task_id) {
+            Some(task) if task.state == TaskState::Pending => {
+                task.state = TaskState::Completed;
+                self.pending -= 1;
+                self.counters.completed += 1;
+                true
+            }
+            Some(_) => false,
+            None => {
+                self.counters.unmatched_results += 1;
+                false
+            }
+        }
+    }
+
+    /// Removes the tasks that expire by the given time, returns the ids of the ones without a result.
+    pub fn expire(&mut self, now: u128) -> Vec<String> {
+        let mut expired = Vec::new();
+        while let Some((expires_at, _)) = self.expiries.first() {
+            if *expires_at > now {
+                break;
+            }
+
+            let (_, task_id) = self.expiries.pop_first().expect("should have first");
+            if let Some(task) = self.tasks.remove(&task_id) {
+                if task.state == TaskState::Pending {
+                    self.pending -= 1;
+                    self.counters.expired += 1;
+                    expired.push(task_id);
+                }
+            }
+        }
+
+        expired
+    }
+
+    /// Number of tasks that have not received a result yet.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Ids of the tasks that have not received a result yet.
+    pub fn pending_ids(&self) -> Vec<&str> {
+        self.tasks
+            .iter()
+            .filter(|(_, task)| task.state == TaskState::Pending)
+            .map(|(task_id, _)| task_id.as_str())
+            .collect()
+    }
+
+    /// Number of tasks currently tracked, including the completed ones.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Summary counters of all tasks seen so far.
+    #[inline]
+    pub fn counters(&self) -> &TaskCounters {
+        &self.counters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_tracker() {
+        let mut tracker = TaskTracker::default();
+
+        tracker.insert_task("a", 100, 0);
+        tracker.insert_task("b", 200, 0);
+        tracker.insert_task("c", 300, 0);
+        tracker.insert_task("a", 100, 0); // duplicate
+        assert_eq!(tracker.pending(), 3);
+
+        assert!(tracker.insert_result("a"));
+        assert!(!tracker.insert_result("a"), "should be a duplicate");
+        assert!(!tracker.insert_result("x"), "should be unmatched");
+        assert_eq!(tracker.pending(), 2);
+        assert_eq!(tracker.pending_ids().len(), 2);
+
+        // `a` is removed as completed, `b` as expired
+        assert_eq!(tracker.expire(200), vec!["b"]);
+        assert_eq!(tracker.len(), 1);
+        assert_eq!(tracker.pending(), 1);
+        assert_eq!(
+            tracker.counters(),
+            &TaskCounters {
+                tasks: 3,
+                results: 3,
+                completed: 1,
+                expired: 1,
+                unmatched_results: 1,
+            }
+        );
+
+        // a late result for an expired task is unmatched
+        assert!(!tracker.insert_result("b"));
+        assert_eq!(tracker.counters().unmatched_results, 2);
+    }
+
+    #[test]
+    fn test_task_tracker_max_age() {
+        let mut tracker = TaskTracker::default();
+        let max_age = TaskTracker::MAX_TASK_AGE.as_nanos();
+
+        tracker.insert_task("a", u128::MAX, 0);
+        assert!(tracker.expire(max_age - 1).is_empty());
+        assert_eq!(tracker.expire(max_age), vec!["a"]);
+        assert_eq!(tracker.len(), 0);
+    }
+}
@@ -1,6 +1,5 @@
 use std::collections::hash_map;
 use std::hash::{Hash, Hasher};
-use std::time::Duration;
 
 use eyre::{eyre, Context, Result};
 use libp2p::identity::{Keypair, PeerId, PublicKey};
@@ -10,10 +9,14 @@ use libp2p::{
     autonat, connection_limits, dcutr, gossipsub, identify, kad, relay, request_response,
 };
 
+use crate::scoring::{peer_score_params, peer_score_thresholds};
+use crate::transform::DriaDataTransform;
+use crate::{DriaP2PConfig, DriaP2PProtocol};
+
 #[derive(libp2p::swarm::NetworkBehaviour)]
 pub struct DriaBehaviour {
     pub relay: relay::client::Behaviour,
-    pub gossipsub: gossipsub::Behaviour,
+    pub gossipsub: gossipsub::Behaviour<DriaDataTransform>,
     pub kademlia: kad::Behaviour<MemoryStore>,
     pub identify: identify::Behaviour,
     pub autonat: autonat::Behaviour,
@@ -26,22 +29,24 @@ impl DriaBehaviour {
     pub fn new(
         key: &Keypair,
         relay_behaviour: relay::client::Behaviour,
-        identity_protocol: String,
-        kademlia_protocol: StreamProtocol,
-        reqres_protocol: StreamProtocol,
+        protocol: &DriaP2PProtocol,
+        config: &DriaP2PConfig,
     ) -> Result<Self> {
         let public_key = key.public();
         let peer_id = public_key.to_peer_id();
 
         Ok(Self {
-            connection_limits: create_connection_limits_behaviour(),
+            connection_limits: create_connection_limits_behaviour(config),
             relay: relay_behaviour,
             dcutr: create_dcutr_behaviour(peer_id),
             autonat: create_autonat_behaviour(peer_id),
-            identify: create_identify_behaviour(public_key, identity_protocol),
-            kademlia: create_kademlia_behaviour(peer_id, kademlia_protocol),
-            gossipsub: create_gossipsub_behaviour(peer_id)?,
-            request_response: create_request_response_behaviour(reqres_protocol),
+            identify: create_identify_behaviour(public_key, protocol.identity()),
+            kademlia: create_kademlia_behaviour(peer_id, protocol.kademlia(), config),
+            gossipsub: create_gossipsub_behaviour(peer_id, protocol, config)?,
+            request_response: create_request_response_behaviour(
+                protocol.request_response(),
+                config,
+            ),
         })
     }
 }
@@ -52,23 +57,23 @@ impl DriaBehaviour {
 #[inline]
 fn create_request_response_behaviour(
     protocol_name: StreamProtocol,
+    config: &DriaP2PConfig,
 ) -> request_response::cbor::Behaviour<Vec<u8>, Vec<u8>> {
     use request_response::{Behaviour, Config, ProtocolSupport};
 
-    Behaviour::new([(protocol_name, ProtocolSupport::Full)], Config::default())
+    Behaviour::new(
+        [(protocol_name, ProtocolSupport::Full)],
+        Config::default().with_request_timeout(config.request_timeout),
+    )
 }
 
 /// Configures the connection limits.
 #[inline]
-fn create_connection_limits_behaviour() -> connection_limits::Behaviour {
+fn create_connection_limits_behaviour(config: &DriaP2PConfig) -> connection_limits::Behaviour {
     use connection_limits::{Behaviour, ConnectionLimits};
 
-    /// Number of established outgoing connections limit, this is directly correlated to peer count
-    /// so limiting this will cause a limitation on peers as well.
-    const EST_OUTGOING_LIMIT: u32 = 300;
-
-    let limits =
-        ConnectionLimits::default().with_max_established_outgoing(Some(EST_OUTGOING_LIMIT));
+    let limits = ConnectionLimits::default()
+        .with_max_established_outgoing(Some(config.max_established_outgoing));
 
     Behaviour::new(limits)
 }
@@ -78,17 +83,13 @@ fn create_connection_limits_behaviour() -> connection_limits::Behaviour {
 fn create_kademlia_behaviour(
     local_peer_id: PeerId,
     protocol_name: StreamProtocol,
+    config: &DriaP2PConfig,
 ) -> kad::Behaviour<MemoryStore> {
     use kad::{Behaviour, Config};
 
-    const KADEMLIA_BOOTSTRAP_INTERVAL_SECS: u64 = 5 * 60; // default is 5 minutes
-    const QUERY_TIMEOUT_SECS: u64 = 3 * 60; // default is 1 minute
-
     let mut cfg = Config::new(protocol_name);
-    cfg.set_query_timeout(Duration::from_secs(QUERY_TIMEOUT_SECS))
-        .set_periodic_bootstrap_interval(Some(Duration::from_secs(
-            KADEMLIA_BOOTSTRAP_INTERVAL_SECS,
-        )));
+    cfg.set_query_timeout(config.kademlia_query_timeout)
+        .set_periodic_bootstrap_interval(Some(config.kademlia_bootstrap_interval));
 
     Behaviour::with_config(local_peer_id, MemoryStore::new(local_peer_id), cfg)
 }
@@ -131,42 +132,24 @@ fn create_autonat_behaviour(local_peer_id: PeerId) -> autonat::Behaviour {
 }
 
 /// Configures the Gossipsub behavior for pub/sub messaging across peers.
+///
+/// If message freshness is enabled by the protocol, messages are timestamped with
+/// [`TTLDataTransform`](crate::transform::TTLDataTransform) over the protocol's own Gossipsub protocol.
 #[inline]
-fn create_gossipsub_behaviour(author: PeerId) -> Result<gossipsub::Behaviour> {
+fn create_gossipsub_behaviour(
+    author: PeerId,
+    protocol: &DriaP2PProtocol,
+    config: &DriaP2PConfig,
+) -> Result<gossipsub::Behaviour<DriaDataTransform>> {
     use gossipsub::{
-        Behaviour, ConfigBuilder, Message, MessageAuthenticity, MessageId, ValidationMode,
+        AllowAllSubscriptionFilter, Behaviour, ConfigBuilder, Message, MessageAuthenticity,
+        MessageId, ValidationMode, Version,
     };
 
-    /// Message TTL in seconds
-    const MESSAGE_TTL_SECS: u64 = 100;
-
     /// We accept permissive validation mode, meaning that we accept all messages
     /// and check their fields based on whether they exist or not.
     const VALIDATION_MODE: ValidationMode = ValidationMode::Permissive;
 
-    /// Heartbeat interval in seconds
-    const HEARTBEAT_INTERVAL_SECS: u64 = 10;
-
-    /// Duplicate cache time in seconds
-    const DUPLICATE_CACHE_TIME_SECS: u64 = 120;
-
-    /// Gossip cache TTL in seconds
-    const GOSSIP_TTL_SECS: u64 = 100;
-
-    /// Message capacity for the gossipsub cache
-    const MESSAGE_CAPACITY: usize = 100;
-
-    /// Max transmit size for payloads 256 KB
-    const MAX_TRANSMIT_SIZE: usize = 256 << 10;
-
-    /// Max IHAVE length, this is much lower than the default
-    /// because we don't need historic messages at all
-    const MAX_IHAVE_LENGTH: usize = 100;
-
-    /// Max size of the send queue
-    /// This helps to avoid memory exhaustion during high load
-    const MAX_SEND_QUEUE_SIZE: usize = 400;
-
     // message id's are simply hashes of the message data, via SipHash13
     let message_id_fn = |message: &Message| {
         let mut hasher = hash_map::DefaultHasher::new();
@@ -175,22 +158,71 @@ fn create_gossipsub_behaviour(author: PeerId) -> Result<gossipsub::Behaviour> {
         MessageId::from(digest.to_be_bytes())
     };
 
-    Behaviour::new(
+    let mut builder = ConfigBuilder::default();
+    builder
+        .heartbeat_interval(config.heartbeat_interval)
+        .max_transmit_size(config.max_transmit_size)
+        .message_id_fn(message_id_fn)
+        .message_capacity(config.message_capacity)
+        .message_ttl(config.message_ttl)
+        .gossip_ttl(config.gossip_ttl)
+        .duplicate_cache_time(config.duplicate_cache_time)
+        .max_ihave_length(config.max_ihave_length)
+        .send_queue_size(config.max_send_queue_size)
+        .mesh_n(config.mesh_n)
+        .mesh_n_low(config.mesh_n_low)
+        .mesh_n_high(config.mesh_n_high)
+        .validation_mode(VALIDATION_MODE)
+        .validate_messages();
+    if let Some(gossipsub_protocol) = &protocol.gossipsub {
+        builder.protocol_id(gossipsub_protocol.to_string(), Version::V1_1);
+    }
+
+    let mut behaviour = Behaviour::new_with_transform(
         MessageAuthenticity::Author(author),
-        ConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(HEARTBEAT_INTERVAL_SECS))
-            .max_transmit_size(MAX_TRANSMIT_SIZE)
-            .message_id_fn(message_id_fn)
-            .message_capacity(MESSAGE_CAPACITY)
-            .message_ttl(Duration::from_secs(MESSAGE_TTL_SECS))
-            .gossip_ttl(Duration::from_secs(GOSSIP_TTL_SECS))
-            .duplicate_cache_time(Duration::from_secs(DUPLICATE_CACHE_TIME_SECS))
-            .max_ihave_length(MAX_IHAVE_LENGTH)
-            .send_queue_size(MAX_SEND_QUEUE_SIZE)
-            .validation_mode(VALIDATION_MODE)
-            .validate_messages()
+        builder
             .build()
             .wrap_err(eyre!("could not create Gossipsub config"))?,
+        None,
+        AllowAllSubscriptionFilter {},
+        DriaDataTransform::new(protocol.message_freshness),
     )
-    .map_err(|e| eyre!(e))
+    .map_err(|e| eyre!(e))?;
+
+    if config.peer_scoring {
+        behaviour
+            .with_peer_score(peer_score_params(), peer_score_thresholds())
+            .map_err(|e| eyre!(e))?;
+    }
+
+    Ok(behaviour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_gossipsub_config() {
+        let peer_id = PeerId::random();
+        let protocol = DriaP2PProtocol::default();
+        assert!(create_gossipsub_behaviour(peer_id, &protocol, &DriaP2PConfig::default()).is_ok());
+
+        // a larger mesh for high-throughput deployments
+        let config = DriaP2PConfig::default().with_mesh_size(8, 12, 24);
+        assert!(create_gossipsub_behaviour(peer_id, &protocol, &config).is_ok());
+
+        // the target mesh size must be within the bounds
+        let config = DriaP2PConfig::default().with_mesh_size(8, 6, 12);
+        assert!(create_gossipsub_behaviour(peer_id, &protocol, &config).is_err());
+
+        // without peer scoring
+        let config = DriaP2PConfig::default().without_peer_scoring();
+        assert!(create_gossipsub_behaviour(peer_id, &protocol, &config).is_ok());
+
+        // message freshness with its own protocol
+        let protocol = protocol.with_message_freshness(Duration::from_secs(60));
+        assert!(create_gossipsub_behaviour(peer_id, &protocol, &DriaP2PConfig::default()).is_ok());
+    }
 }
@@ -1,17 +1,23 @@
 use eyre::Result;
+use libp2p::core::transport::ListenerId;
 use libp2p::futures::StreamExt;
 use libp2p::gossipsub::{Message, MessageId};
 use libp2p::kad::{GetClosestPeersError, GetClosestPeersOk, QueryResult};
-use libp2p::request_response::{self, ResponseChannel};
+use libp2p::request_response::{self, OutboundRequestId, ResponseChannel};
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
 use libp2p::swarm::SwarmEvent;
-use libp2p::{autonat, gossipsub, identify, kad, multiaddr::Protocol, noise, tcp, yamux};
+use libp2p::{autonat, gossipsub, identify, kad, multiaddr::Protocol, noise, relay, tcp, yamux};
 use libp2p::{Multiaddr, PeerId, Swarm, SwarmBuilder};
 use libp2p_identity::Keypair;
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot};
 
 use crate::behaviour::{DriaBehaviour, DriaBehaviourEvent};
-use crate::{DriaNodes, DriaP2PProtocol};
+use crate::peerstore::PeerStore;
+use crate::reserved::{ReservedPeerChange, ReservedPeers};
+use crate::scoring::RPC_APPLICATION_SCORE;
+use crate::{DriaNodes, DriaP2PConfig, DriaP2PEvent, DriaP2PProtocol, ReservedPeerKind};
 
 use super::commands::DriaP2PCommand;
 use super::DriaP2PCommander;
@@ -30,15 +36,32 @@ pub struct DriaP2PClient {
     req_tx: mpsc::Sender<(PeerId, Vec<u8>, ResponseChannel<Vec<u8>>)>,
     /// Command receiver.
     cmd_rx: mpsc::Receiver<DriaP2PCommand>,
+    /// Outbound requests that are waiting for a response, w.r.t their request ids.
+    pending_requests: HashMap<OutboundRequestId, oneshot::Sender<Result<Vec<u8>>>>,
+    /// Whether private & loopback addresses of peers are added to Kademlia.
+    allow_local_addrs: bool,
+    /// RPC peers, given a higher application score for Gossipsub.
+    rpc_peer_ids: HashSet<PeerId>,
+    /// RPC, bootstrap & relay peers that are redialled when disconnected.
+    reserved: ReservedPeers,
+    /// Listeners through the relay peers, so that a relay is listened through once when redialled.
+    relay_listeners: HashMap<PeerId, ListenerId>,
+    /// Event sender, see [`DriaP2PCommander::events`].
+    event_tx: broadcast::Sender<DriaP2PEvent>,
+    /// Known good peers that are saved to disk, if enabled.
+    peer_store: Option<PeerStore>,
+    /// Interval of saving the peer store.
+    peer_store_interval: Duration,
 }
 
-// TODO: make all these configurable
-/// Number of seconds before an idle connection is closed.
-const IDLE_CONNECTION_TIMEOUT_SECS: u64 = 60;
 /// Buffer size for command channel.
 const COMMAND_CHANNEL_BUFSIZE: usize = 1024;
 /// Buffer size for events channel.
 const MSG_CHANNEL_BUFSIZE: usize = 1024;
+/// Buffer size for the broadcast of client events, slow subscribers miss the older ones.
+const EVENT_CHANNEL_BUFSIZE: usize = 256;
+/// Interval to check for the reserved peers that are due to be redialled.
+const REDIAL_CHECK_INTERVAL: Duration = Duration::from_millis(500);
 
 impl DriaP2PClient {
     /// Creates a new P2P client with the given keypair and listen address.
@@ -47,12 +70,15 @@ impl DriaP2PClient {
     ///
     /// The `version` is used to create the protocol strings for the client, and its very important that
     /// they match with the clients existing within the network.
+    ///
+    /// The behaviours are configured with the given `config`, see [`DriaP2PConfig::default`] for the defaults.
     #[allow(clippy::type_complexity)]
     pub fn new(
         keypair: Keypair,
         listen_addr: Multiaddr,
         nodes: &DriaNodes,
         protocol: DriaP2PProtocol,
+        config: DriaP2PConfig,
     ) -> Result<(
         DriaP2PClient,
         DriaP2PCommander,
@@ -73,18 +99,9 @@ impl DriaP2PClient {
             .with_quic()
             .with_relay_client(noise::Config::new, yamux::Config::default)?
             .with_behaviour(|key, relay_behaviour| {
-                DriaBehaviour::new(
-                    key,
-                    relay_behaviour,
-                    protocol.identity(),
-                    protocol.kademlia(),
-                    protocol.request_response(),
-                )
-                .map_err(Into::into)
+                DriaBehaviour::new(key, relay_behaviour, &protocol, &config).map_err(Into::into)
             })?
-            .with_swarm_config(|c| {
-                c.with_idle_connection_timeout(Duration::from_secs(IDLE_CONNECTION_TIMEOUT_SECS))
-            })
+            .with_swarm_config(|c| c.with_idle_connection_timeout(config.idle_connection_timeout))
             .build();
 
         // set mode to server so that RPC nodes add us to the DHT
@@ -93,14 +110,12 @@ impl DriaP2PClient {
             .kademlia
             .set_mode(Some(libp2p::kad::Mode::Server));
 
-        // initiate bootstrap
+        // add bootstrap nodes to Kademlia, they are dialled along with the other reserved peers below
         for addr in &nodes.bootstrap_nodes {
-            log::info!("Dialling bootstrap: {:#?}", addr);
             if let Some(peer_id) = addr.iter().find_map(|p| match p {
                 Protocol::P2p(peer_id) => Some(peer_id),
                 _ => None,
             }) {
-                swarm.dial(addr.clone())?;
                 log::info!("Adding {} to Kademlia routing table", addr);
                 swarm
                     .behaviour_mut()
@@ -111,6 +126,31 @@ impl DriaP2PClient {
             }
         }
 
+        // add the known good peers from the previous runs to Kademlia,
+        // so that we can join the network even if the bootstrap nodes are not reachable
+        let peer_store = config.peer_store_path.as_ref().and_then(|path| {
+            PeerStore::load(path)
+                .inspect_err(|e| log::error!("Could not load peer store: {:?}", e))
+                .ok()
+        });
+        if let Some(peer_store) = &peer_store {
+            let mut count = 0;
+            for (peer_id, record) in peer_store.peers_with_protocol(&protocol.identity) {
+                for addr in &record.addrs {
+                    swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(peer_id, addr.clone());
+                }
+                count += 1;
+            }
+            log::info!(
+                "Added {} known peers from {} to Kademlia routing table",
+                count,
+                peer_store.path().display()
+            );
+        }
+
         // do a random-walk on the DHT with a random peer
         log::info!("Searching for random peers.");
         let random_peer = PeerId::random();
@@ -118,42 +158,57 @@ impl DriaP2PClient {
             .behaviour_mut()
             .kademlia
             .get_closest_peers(random_peer);
-        swarm.behaviour_mut().kademlia.bootstrap()?;
+        if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+            // this is expected if we are the bootstrap node ourselves
+            log::warn!("Could not bootstrap Kademlia: {:?}", e);
+        }
 
         // listen on all interfaces for incoming connections
         log::info!("Listening p2p network on: {}", listen_addr);
         swarm.listen_on(listen_addr)?;
 
-        // listen on relay addresses with p2p circuit
-        for addr in &nodes.relay_nodes {
-            log::info!("Listening to relay: {}", addr);
-            swarm.listen_on(addr.clone().with(Protocol::P2pCircuit))?;
-        }
-
-        // dial rpc nodes
-        for rpc_addr in &nodes.rpc_nodes {
-            log::info!("Dialing RPC node: {}", rpc_addr);
-            if let Err(e) = swarm.dial(rpc_addr.clone()) {
-                log::error!("Error dialing RPC node: {:?}", e);
-            };
-        }
+        // all of the dria nodes are reserved, so that they are redialled when disconnected
+        // or when dialling them fails, and relays are listened through with a p2p circuit
+        let mut reserved =
+            ReservedPeers::new(config.redial_initial_backoff, config.redial_max_backoff);
+        let reserved_peers = [
+            reserved.insert(ReservedPeerKind::Rpc, nodes.rpc_nodes.iter().cloned()),
+            reserved.insert(
+                ReservedPeerKind::Bootstrap,
+                nodes.bootstrap_nodes.iter().cloned(),
+            ),
+            reserved.insert(ReservedPeerKind::Relay, nodes.relay_nodes.iter().cloned()),
+        ];
 
         // create commander
         let (cmd_tx, cmd_rx) = mpsc::channel(COMMAND_CHANNEL_BUFSIZE);
-        let commander = DriaP2PCommander::new(cmd_tx, protocol.clone());
+        let (event_tx, _) = broadcast::channel(EVENT_CHANNEL_BUFSIZE);
+        let commander = DriaP2PCommander::new(cmd_tx, protocol.clone(), event_tx.clone());
 
         // create p2p client itself
         let (msg_tx, msg_rx) = mpsc::channel(MSG_CHANNEL_BUFSIZE);
         let (req_tx, req_rx) = mpsc::channel(MSG_CHANNEL_BUFSIZE);
-        let client = Self {
+        let mut client = Self {
             peer_id,
             swarm,
             protocol,
             msg_tx,
             req_tx,
             cmd_rx,
+            pending_requests: HashMap::new(),
+            allow_local_addrs: config.allow_local_addrs,
+            rpc_peer_ids: nodes.rpc_peerids.clone(),
+            reserved,
+            relay_listeners: HashMap::new(),
+            event_tx,
+            peer_store,
+            peer_store_interval: config.peer_store_interval,
         };
 
+        for peer_id in reserved_peers.into_iter().flatten() {
+            client.dial_reserved(peer_id);
+        }
+
         Ok((client, commander, msg_rx, req_rx))
     }
 
@@ -161,6 +216,9 @@ impl DriaP2PClient {
     ///
     /// To terminate, the command channel must be closed.
     pub async fn run(mut self) {
+        let mut redial_interval = tokio::time::interval(REDIAL_CHECK_INTERVAL);
+        let mut peer_store_interval = tokio::time::interval(self.peer_store_interval);
+        peer_store_interval.tick().await; // move one tick
         loop {
             tokio::select! {
                 // this is a special keyword that changes the polling order from random to linear,
@@ -175,6 +233,8 @@ impl DriaP2PClient {
                     },
                 },
                 event = self.swarm.select_next_some() => self.handle_event(event).await,
+                _ = redial_interval.tick() => self.handle_redial(),
+                _ = peer_store_interval.tick(), if self.peer_store.is_some() => self.save_peer_store(),
             }
         }
     }
@@ -185,6 +245,21 @@ impl DriaP2PClient {
             DriaP2PCommand::Dial { peer_id, sender } => {
                 let _ = sender.send(self.swarm.dial(peer_id));
             }
+            DriaP2PCommand::Reserve {
+                kind,
+                addrs,
+                sender,
+            } => {
+                for peer_id in self.reserved.insert(kind, addrs) {
+                    if kind == ReservedPeerKind::Rpc {
+                        self.rpc_peer_ids.insert(peer_id);
+                    }
+                    if !self.swarm.is_connected(&peer_id) {
+                        self.dial_reserved(peer_id);
+                    }
+                }
+                let _ = sender.send(());
+            }
             DriaP2PCommand::NetworkInfo { sender } => {
                 let _ = sender.send(self.swarm.network_info());
             }
@@ -234,12 +309,13 @@ impl DriaP2PClient {
                 peer_id,
                 sender,
             } => {
-                let _ = sender.send(
-                    self.swarm
-                        .behaviour_mut()
-                        .request_response
-                        .send_request(&peer_id, data),
-                );
+                // the sender is answered when a response or a failure is received for this request
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .request_response
+                    .send_request(&peer_id, data);
+                self.pending_requests.insert(request_id, sender);
             }
             DriaP2PCommand::ValidateMessage {
                 msg_id,
@@ -280,6 +356,17 @@ impl DriaP2PClient {
                     .collect();
                 let _ = sender.send((mesh, all));
             }
+            DriaP2PCommand::PeerScores { sender } => {
+                let gossipsub = &self.swarm.behaviour().gossipsub;
+                let mut scores = gossipsub
+                    .all_peers()
+                    .filter_map(|(peer_id, _)| {
+                        gossipsub.peer_score(peer_id).map(|score| (*peer_id, score))
+                    })
+                    .collect::<Vec<_>>();
+                scores.sort_by(|a, b| a.1.total_cmp(&b.1));
+                let _ = sender.send(scores);
+            }
             DriaP2PCommand::PeerCounts { sender } => {
                 let mesh = self.swarm.behaviour().gossipsub.all_mesh_peers().count();
                 let all = self.swarm.behaviour().gossipsub.all_peers().count();
@@ -296,6 +383,9 @@ impl DriaP2PClient {
                 // remove own peerId from Autonat server list
                 self.swarm.behaviour_mut().autonat.remove_server(&peer_id);
 
+                // save the known peers for the next run
+                self.save_peer_store();
+
                 let _ = sender.send(());
             }
         }
@@ -328,18 +418,24 @@ impl DriaP2PClient {
                         log::error!("Could not send request-response request: {:?}", e);
                     }
                 }
+                // a response to one of our requests, we answer the respective sender
                 request_response::Message::Response {
                     request_id,
                     response,
-                } => {
-                    // while we support the protocol, we dont really make any requests
-                    // TODO: should p2p crate support this?
-                    log::warn!(
-                        "Unexpected response message with request_id {}: {:?}",
-                        request_id,
-                        response
-                    );
-                }
+                } => match self.pending_requests.remove(&request_id) {
+                    Some(sender) => {
+                        if sender.send(Ok(response)).is_err() {
+                            log::warn!("Response for request_id {} was dropped", request_id);
+                        }
+                    }
+                    None => {
+                        log::warn!(
+                            "Unexpected response message with request_id {} from {}",
+                            request_id,
+                            peer
+                        );
+                    }
+                },
             },
             SwarmEvent::Behaviour(DriaBehaviourEvent::RequestResponse(
                 request_response::Event::ResponseSent { peer, request_id },
@@ -363,6 +459,10 @@ impl DriaP2PClient {
                     request_id,
                     error
                 );
+                if let Some(sender) = self.pending_requests.remove(&request_id) {
+                    let _ =
+                        sender.send(Err(eyre::eyre!("request to {} failed: {:?}", peer, error)));
+                }
             }
             SwarmEvent::Behaviour(DriaBehaviourEvent::RequestResponse(
                 request_response::Event::InboundFailure {
@@ -400,11 +500,91 @@ impl DriaP2PClient {
                 new,
             })) => {
                 log::warn!("AutoNAT status changed from {:?} to {:?}", old, new);
+                self.emit(DriaP2PEvent::NatStatusChanged { old, new });
+            }
+
+            // relay events
+            SwarmEvent::Behaviour(DriaBehaviourEvent::Relay(
+                relay::client::Event::ReservationReqAccepted {
+                    relay_peer_id,
+                    renewal,
+                    ..
+                },
+            )) => {
+                log::info!("Relay reservation accepted by {}", relay_peer_id);
+                self.emit(DriaP2PEvent::RelayReservationAccepted {
+                    relay_peer_id,
+                    renewal,
+                });
+            }
+
+            // connection events, the reserved peers are kept track of as well
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint,
+                num_established,
+                ..
+            } => {
+                self.emit(DriaP2PEvent::ConnectionEstablished {
+                    peer_id,
+                    address: endpoint.get_remote_address().clone(),
+                    num_established: num_established.get(),
+                });
+                if let Some(change) = self.reserved.connected(&peer_id) {
+                    self.handle_reserved_change(peer_id, change, true);
+                }
+
+                // RPC peers are scored higher, so that they are preferred in the mesh
+                if self.rpc_peer_ids.contains(&peer_id)
+                    && self
+                        .swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .set_application_score(&peer_id, RPC_APPLICATION_SCORE)
+                {
+                    log::debug!("Set application score of RPC peer {}", peer_id);
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established,
+                cause,
+                ..
+            } => {
+                self.emit(DriaP2PEvent::ConnectionClosed {
+                    peer_id,
+                    num_established,
+                    cause: cause.map(|cause| cause.to_string()),
+                });
+                if num_established == 0 {
+                    if let Some(peer_store) = &mut self.peer_store {
+                        peer_store.disconnected(&peer_id);
+                    }
+                    if let Some(change) = self.reserved.disconnected(&peer_id, Instant::now()) {
+                        self.handle_reserved_change(peer_id, change, false);
+                    }
+                }
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                match peer_id {
+                    Some(peer_id) => {
+                        log::debug!("Could not connect to peer {}: {:?}", peer_id, error);
+                        if !self.swarm.is_connected(&peer_id) {
+                            self.reserved.dial_failed(&peer_id, Instant::now());
+                        }
+                    }
+                    None => log::debug!("Outgoing connection error: {:?}", error),
+                }
+                self.emit(DriaP2PEvent::OutgoingConnectionError {
+                    peer_id,
+                    error: error.to_string(),
+                });
             }
 
             // log listen addreses
             SwarmEvent::NewListenAddr { address, .. } => {
                 log::warn!("Local node is listening on {}", address);
+                self.emit(DriaP2PEvent::NewListenAddr { address });
             }
 
             // add external address of peers to Kademlia routing table
@@ -422,7 +602,8 @@ impl DriaP2PClient {
                 self.swarm
                     .behaviour_mut()
                     .kademlia
-                    .add_address(&peer_id, address);
+                    .add_address(&peer_id, address.clone());
+                self.emit(DriaP2PEvent::ExternalAddrConfirmed { address });
             }
 
             // SwarmEvent::IncomingConnectionError {
@@ -452,18 +633,106 @@ impl DriaP2PClient {
             //         send_back_addr
             //     );
             // }
-
-            // SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
-            //     if let Some(peer_id) = peer_id {
-            //         log::warn!("Could not connect to peer {}: {:?}", peer_id, error);
-            //     } else {
-            //         log::warn!("Outgoing connection error: {:?}", error);
-            //     }
-            // }
             event => log::trace!("Unhandled Swarm Event: {:?}", event),
         }
     }
 
+    /// Redials the reserved peers that are due.
+    fn handle_redial(&mut self) {
+        for (peer_id, _, _) in self.reserved.due(Instant::now()) {
+            self.dial_reserved(peer_id);
+        }
+    }
+
+    /// Dials a reserved peer at its known addresses, and schedules a redial if that fails right away.
+    ///
+    /// Relays are dialled by listening through them again, so that the circuit is restored as well;
+    /// the previous listener through the relay is removed beforehand.
+    fn dial_reserved(&mut self, peer_id: PeerId) {
+        let Some((kind, addrs)) = self.reserved.get(&peer_id) else {
+            return;
+        };
+        log::info!("Dialling {} peer {}", kind, peer_id);
+
+        let result = match (kind, addrs.first()) {
+            (ReservedPeerKind::Relay, Some(addr)) => {
+                if let Some(listener_id) = self.relay_listeners.remove(&peer_id) {
+                    self.swarm.remove_listener(listener_id);
+                }
+                self.swarm
+                    .listen_on(addr.clone().with(Protocol::P2pCircuit))
+                    .map(|listener_id| {
+                        self.relay_listeners.insert(peer_id, listener_id);
+                    })
+                    .map_err(|e| eyre::eyre!(e))
+            }
+            _ => self
+                .swarm
+                .dial(
+                    DialOpts::peer_id(peer_id)
+                        .addresses(addrs)
+                        .condition(PeerCondition::DisconnectedAndNotDialing)
+                        .build(),
+                )
+                .map_err(|e| eyre::eyre!(e)),
+        };
+
+        if let Err(e) = result {
+            log::warn!("Could not dial {} peer {}: {:?}", kind, peer_id, e);
+            self.reserved.dial_failed(&peer_id, Instant::now());
+        }
+    }
+
+    /// Broadcasts the events for a change in the connectivity of a reserved peer.
+    fn handle_reserved_change(&self, peer_id: PeerId, change: ReservedPeerChange, connected: bool) {
+        let kind = change.kind;
+        if connected {
+            log::info!("Connected to {} peer {}", kind, peer_id);
+            self.emit(DriaP2PEvent::ReservedPeerConnected { peer_id, kind });
+            if change.liveness_changed {
+                log::info!("Liveness of {} peers is restored.", kind);
+                self.emit(DriaP2PEvent::LivenessRestored { kind });
+            }
+        } else {
+            log::warn!("Disconnected from {} peer {}, redialling.", kind, peer_id);
+            self.emit(DriaP2PEvent::ReservedPeerDisconnected { peer_id, kind });
+            if change.liveness_changed {
+                log::error!("Lost connection to all {} peers!", kind);
+                self.emit(DriaP2PEvent::LivenessLost { kind });
+            }
+        }
+    }
+
+    /// Saves the peer store, if enabled.
+    fn save_peer_store(&mut self) {
+        if let Some(peer_store) = &mut self.peer_store {
+            match peer_store.save() {
+                Ok(()) => log::debug!("Saved {} known peers.", peer_store.len()),
+                Err(e) => log::error!("Could not save peer store: {:?}", e),
+            }
+        }
+    }
+
+    /// Broadcasts an event to the subscribers, if any.
+    fn emit(&self, event: DriaP2PEvent) {
+        // there may be no subscribers, which is fine
+        let _ = self.event_tx.send(event);
+    }
+
+    /// Blacklists & disconnects a peer with a different protocol, which is not redialled even if reserved.
+    fn blacklist(&mut self, peer_id: PeerId, protocol: String) {
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .blacklist_peer(&peer_id);
+        self.reserved.remove(&peer_id);
+        if let Some(peer_store) = &mut self.peer_store {
+            peer_store.remove(&peer_id);
+        }
+        let _ = self.swarm.disconnect_peer_id(peer_id);
+        self.emit(DriaP2PEvent::PeerBlacklisted { peer_id, protocol });
+    }
+
     /// Handles identify events.
     ///
     /// At the top level, we check the protocol string.
@@ -480,11 +749,7 @@ impl DriaP2PClient {
             );
 
             // blacklist & disconnect peers with different protocol
-            self.swarm
-                .behaviour_mut()
-                .gossipsub
-                .blacklist_peer(&peer_id);
-            let _ = self.swarm.disconnect_peer_id(peer_id);
+            self.blacklist(peer_id, info.protocol_version);
         } else {
             // check kademlia protocol
             if let Some(kad_protocol) = info
@@ -495,18 +760,21 @@ impl DriaP2PClient {
                 // if it matches our protocol, add it to the Kademlia routing table
                 if *kad_protocol == self.protocol.kademlia {
                     // filter listen addresses
+                    let allow_local_addrs = self.allow_local_addrs;
                     let addrs = info.listen_addrs.into_iter().filter(|listen_addr| {
                         if let Some(Protocol::Ip4(ipv4_addr)) = listen_addr.iter().next() {
-                            // ignore private & localhost addresses
-                            !(ipv4_addr.is_private() || ipv4_addr.is_loopback())
+                            // ignore private & localhost addresses, unless allowed
+                            allow_local_addrs
+                                || !(ipv4_addr.is_private() || ipv4_addr.is_loopback())
                         } else {
                             // ignore non ipv4 addresses
                             false
                         }
                     });
+                    let addrs = addrs.collect::<Vec<_>>();
 
                     // add them to kademlia
-                    for addr in addrs {
+                    for addr in &addrs {
                         log::info!(
                             "Identify: {} peer {} identified at {}",
                             self.protocol.kademlia,
@@ -517,7 +785,17 @@ impl DriaP2PClient {
                         self.swarm
                             .behaviour_mut()
                             .kademlia
-                            .add_address(&peer_id, addr);
+                            .add_address(&peer_id, addr.clone());
+                    }
+
+                    // remember them for the next run, once they stay connected
+                    if let Some(peer_store) = &mut self.peer_store {
+                        peer_store.identified(
+                            peer_id,
+                            addrs,
+                            &info.protocol_version,
+                            Instant::now(),
+                        );
                     }
                 } else {
                     log::warn!(
@@ -528,11 +806,8 @@ impl DriaP2PClient {
                     );
 
                     // blacklist & disconnect peers with different kademlia protocol
-                    self.swarm
-                        .behaviour_mut()
-                        .gossipsub
-                        .blacklist_peer(&peer_id);
-                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    let protocol = kad_protocol.to_string();
+                    self.blacklist(peer_id, protocol);
                 }
             }
         }
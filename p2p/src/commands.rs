@@ -1,8 +1,8 @@
 use eyre::{Context, Result};
 use libp2p::{gossipsub, kad, request_response, swarm, Multiaddr, PeerId};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
 
-use crate::DriaP2PProtocol;
+use crate::{DriaP2PEvent, DriaP2PProtocol, ReservedPeerKind};
 
 #[derive(Debug)]
 pub enum DriaP2PCommand {
@@ -22,11 +22,21 @@ pub enum DriaP2PCommand {
     PeerCounts {
         sender: oneshot::Sender<(usize, usize)>,
     },
+    /// Get the Gossipsub scores of all peers, empty if peer scoring is disabled.
+    PeerScores {
+        sender: oneshot::Sender<Vec<(PeerId, f64)>>,
+    },
     /// Dial a peer.
     Dial {
         peer_id: Multiaddr,
         sender: oneshot::Sender<Result<(), swarm::DialError>>,
     },
+    /// Reserve peers, so that they are dialled and redialled with backoff when disconnected.
+    Reserve {
+        kind: ReservedPeerKind,
+        addrs: Vec<Multiaddr>,
+        sender: oneshot::Sender<()>,
+    },
     /// Subscribe to a topic.
     Subscribe {
         topic: String,
@@ -49,13 +59,13 @@ pub enum DriaP2PCommand {
         channel: request_response::ResponseChannel<Vec<u8>>,
         sender: oneshot::Sender<Result<()>>,
     },
-    /// Request a request-response message.
+    /// Request a request-response message, the sender is kept by the client until the response arrives.
     /// Note that you are likely to be caught by the RPC peer id check,
     /// and your messages will be ignored.
     Request {
         peer_id: PeerId,
         data: Vec<u8>,
-        sender: oneshot::Sender<request_response::OutboundRequestId>,
+        sender: oneshot::Sender<Result<Vec<u8>>>,
     },
     /// Validates a GossipSub message for propagation, returns whether the message existed in cache.
     ///
@@ -75,14 +85,24 @@ pub enum DriaP2PCommand {
     Shutdown { sender: oneshot::Sender<()> },
 }
 
+#[derive(Clone)]
 pub struct DriaP2PCommander {
     sender: mpsc::Sender<DriaP2PCommand>,
     protocol: DriaP2PProtocol,
+    events: broadcast::Sender<DriaP2PEvent>,
 }
 
 impl DriaP2PCommander {
-    pub fn new(sender: mpsc::Sender<DriaP2PCommand>, protocol: DriaP2PProtocol) -> Self {
-        Self { sender, protocol }
+    pub fn new(
+        sender: mpsc::Sender<DriaP2PCommand>,
+        protocol: DriaP2PProtocol,
+        events: broadcast::Sender<DriaP2PEvent>,
+    ) -> Self {
+        Self {
+            sender,
+            protocol,
+            events,
+        }
     }
 
     /// Returns a reference to the protocol.
@@ -90,6 +110,11 @@ impl DriaP2PCommander {
         &self.protocol
     }
 
+    /// Subscribes to the events of the client, only the events after this call are received.
+    pub fn events(&self) -> broadcast::Receiver<DriaP2PEvent> {
+        self.events.subscribe()
+    }
+
     /// Returns the network information, such as the number of
     /// incoming and outgoing connections.
     pub async fn network_info(&self) -> Result<swarm::NetworkInfo> {
@@ -189,13 +214,15 @@ impl DriaP2PCommander {
             .wrap_err("could not publish")
     }
 
-    pub async fn request(
-        &mut self,
-        peer_id: PeerId,
-        data: Vec<u8>,
-    ) -> Result<request_response::OutboundRequestId> {
+    /// Makes a request to the given peer, and waits for its response.
+    ///
+    /// Fails if the request can not be made (e.g. peer is not reachable), or if the response
+    /// does not arrive within the request timeout of the client. The commander can be cloned
+    /// to make multiple requests concurrently.
+    pub async fn request(&self, peer_id: PeerId, data: Vec<u8>) -> Result<Vec<u8>> {
         let (sender, receiver) = oneshot::channel();
 
+        log::debug!("Making a request to peer: {}", peer_id);
         self.sender
             .send(DriaP2PCommand::Request {
                 data,
@@ -205,7 +232,10 @@ impl DriaP2PCommander {
             .await
             .wrap_err("could not send")?;
 
-        receiver.await.wrap_err("could not receive")
+        receiver
+            .await
+            .wrap_err("could not receive")?
+            .wrap_err("could not request")
     }
 
     /// Dials a given peer.
@@ -223,6 +253,25 @@ impl DriaP2PCommander {
             .wrap_err("could not dial")
     }
 
+    /// Reserves the peers at the given addresses, which must contain the peer id.
+    ///
+    /// Reserved peers are dialled if not connected, and redialled with backoff whenever
+    /// their connection is lost; see [`DriaP2PEvent`] for the respective events.
+    pub async fn reserve(&self, kind: ReservedPeerKind, addrs: Vec<Multiaddr>) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.sender
+            .send(DriaP2PCommand::Reserve {
+                kind,
+                addrs,
+                sender,
+            })
+            .await
+            .wrap_err("could not send")?;
+
+        receiver.await.wrap_err("could not receive")
+    }
+
     /// Validates a GossipSub message for propagation.
     ///
     /// - `Accept`: Accept the message and propagate it.
@@ -300,6 +349,20 @@ impl DriaP2PCommander {
         receiver.await.wrap_err("could not receive")
     }
 
+    /// Get the Gossipsub scores of all peers, sorted from the lowest to the highest score.
+    ///
+    /// Returns an empty list if peer scoring is disabled.
+    pub async fn peer_scores(&self) -> Result<Vec<(PeerId, f64)>> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.sender
+            .send(DriaP2PCommand::PeerScores { sender })
+            .await
+            .wrap_err("could not send")?;
+
+        receiver.await.wrap_err("could not receive")
+    }
+
     /// Sends a shutdown signal to the client.
     pub async fn shutdown(&mut self) -> Result<()> {
         let (sender, receiver) = oneshot::channel();
@@ -0,0 +1,206 @@
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
+
+/// Configurations of the peer-to-peer client and its behaviours.
+///
+/// The defaults are the values that the network is tuned for, so they
+/// should only be changed for specific deployments, e.g. high-throughput nodes.
+#[derive(Debug, Clone)]
+pub struct DriaP2PConfig {
+    /// Time-to-live of the messages in the Gossipsub cache.
+    pub message_ttl: Duration,
+    /// Gossipsub heartbeat interval.
+    pub heartbeat_interval: Duration,
+    /// Time to keep the message ids in the Gossipsub duplicate cache.
+    pub duplicate_cache_time: Duration,
+    /// Time-to-live of the gossip (IHAVE) messages.
+    pub gossip_ttl: Duration,
+    /// Message capacity of the Gossipsub cache.
+    pub message_capacity: usize,
+    /// Maximum size of a Gossipsub message in bytes.
+    pub max_transmit_size: usize,
+    /// Maximum number of messages within an IHAVE message.
+    pub max_ihave_length: usize,
+    /// Maximum size of the send queue for each peer, helps to avoid memory exhaustion during high load.
+    pub max_send_queue_size: usize,
+    /// Target number of peers in the Gossipsub mesh.
+    pub mesh_n: usize,
+    /// Minimum number of peers in the Gossipsub mesh, more are added below this.
+    pub mesh_n_low: usize,
+    /// Maximum number of peers in the Gossipsub mesh, some are removed above this.
+    pub mesh_n_high: usize,
+    /// Timeout of the Kademlia queries.
+    pub kademlia_query_timeout: Duration,
+    /// Interval of the periodic Kademlia bootstrap.
+    pub kademlia_bootstrap_interval: Duration,
+    /// Time to wait for a response to an outbound request.
+    pub request_timeout: Duration,
+    /// Number of established outgoing connections limit, this is directly correlated to peer count
+    /// so limiting this will cause a limitation on peers as well.
+    pub max_established_outgoing: u32,
+    /// Time before an idle connection is closed.
+    pub idle_connection_timeout: Duration,
+    /// Time to wait before redialling a disconnected reserved peer, doubled after each failed dial.
+    pub redial_initial_backoff: Duration,
+    /// Maximum time to wait between the redials of a reserved peer.
+    pub redial_max_backoff: Duration,
+    /// Path of the on-disk store of known good peers, which are added to Kademlia on startup.
+    ///
+    /// Disabled if `None`.
+    pub peer_store_path: Option<PathBuf>,
+    /// Interval of saving the peer store.
+    pub peer_store_interval: Duration,
+    /// Whether Gossipsub peer scoring is enabled, with parameters tuned for the Dria topics.
+    pub peer_scoring: bool,
+    /// Whether private & loopback addresses of the identified peers are added to Kademlia.
+    ///
+    /// This is useful for local networks & tests, where all peers are on the same host.
+    pub allow_local_addrs: bool,
+}
+
+impl Default for DriaP2PConfig {
+    fn default() -> Self {
+        Self {
+            message_ttl: Duration::from_secs(100),
+            heartbeat_interval: Duration::from_secs(10),
+            duplicate_cache_time: Duration::from_secs(120),
+            gossip_ttl: Duration::from_secs(100),
+            message_capacity: 100,
+            max_transmit_size: 256 << 10, // 256 KB
+            // much lower than the default because we don't need historic messages at all
+            max_ihave_length: 100,
+            max_send_queue_size: 400,
+            // gossipsub defaults
+            mesh_n: 6,
+            mesh_n_low: 5,
+            mesh_n_high: 12,
+            // default is 1 minute
+            kademlia_query_timeout: Duration::from_secs(3 * 60),
+            kademlia_bootstrap_interval: Duration::from_secs(5 * 60),
+            // default is 10 seconds
+            request_timeout: Duration::from_secs(10),
+            max_established_outgoing: 300,
+            idle_connection_timeout: Duration::from_secs(60),
+            redial_initial_backoff: Duration::from_secs(1),
+            redial_max_backoff: Duration::from_secs(5 * 60),
+            peer_store_path: None,
+            peer_store_interval: Duration::from_secs(5 * 60),
+            peer_scoring: true,
+            allow_local_addrs: false,
+        }
+    }
+}
+
+impl DriaP2PConfig {
+    /// Overrides the configurations with the given environment variables, if they exist.
+    ///
+    /// The environment variables are:
+    /// - `DKN_P2P_MESH_N`, `DKN_P2P_MESH_N_LOW` & `DKN_P2P_MESH_N_HIGH`: Gossipsub mesh sizes
+    /// - `DKN_P2P_MAX_TRANSMIT_SIZE`: maximum message size in bytes
+    /// - `DKN_P2P_MAX_SEND_QUEUE_SIZE`: send queue size for each peer
+    /// - `DKN_P2P_HEARTBEAT_INTERVAL_SECS`: Gossipsub heartbeat interval
+    /// - `DKN_P2P_MESSAGE_TTL_SECS`: Gossipsub message TTL
+    /// - `DKN_P2P_MAX_OUTGOING_CONNECTIONS`: limit of established outgoing connections
+    /// - `DKN_P2P_IDLE_CONNECTION_TIMEOUT_SECS`: idle connection timeout
+    /// - `DKN_P2P_REQUEST_TIMEOUT_SECS`: outbound request timeout
+    /// - `DKN_P2P_KADEMLIA_QUERY_TIMEOUT_SECS`: Kademlia query timeout
+    /// - `DKN_P2P_KADEMLIA_BOOTSTRAP_INTERVAL_SECS`: Kademlia bootstrap interval
+    /// - `DKN_P2P_PEER_STORE`: path of the peer store, disabled if empty
+    ///
+    /// Values that can not be parsed are ignored with a warning.
+    pub fn with_envs(mut self) -> Self {
+        env_override("DKN_P2P_MESH_N", &mut self.mesh_n);
+        env_override("DKN_P2P_MESH_N_LOW", &mut self.mesh_n_low);
+        env_override("DKN_P2P_MESH_N_HIGH", &mut self.mesh_n_high);
+        env_override("DKN_P2P_MAX_TRANSMIT_SIZE", &mut self.max_transmit_size);
+        env_override("DKN_P2P_MAX_SEND_QUEUE_SIZE", &mut self.max_send_queue_size);
+        env_override_secs(
+            "DKN_P2P_HEARTBEAT_INTERVAL_SECS",
+            &mut self.heartbeat_interval,
+        );
+        env_override_secs("DKN_P2P_MESSAGE_TTL_SECS", &mut self.message_ttl);
+        env_override(
+            "DKN_P2P_MAX_OUTGOING_CONNECTIONS",
+            &mut self.max_established_outgoing,
+        );
+        env_override_secs(
+            "DKN_P2P_IDLE_CONNECTION_TIMEOUT_SECS",
+            &mut self.idle_connection_timeout,
+        );
+        env_override_secs("DKN_P2P_REQUEST_TIMEOUT_SECS", &mut self.request_timeout);
+        env_override_secs(
+            "DKN_P2P_KADEMLIA_QUERY_TIMEOUT_SECS",
+            &mut self.kademlia_query_timeout,
+        );
+        env_override_secs(
+            "DKN_P2P_KADEMLIA_BOOTSTRAP_INTERVAL_SECS",
+            &mut self.kademlia_bootstrap_interval,
+        );
+        if let Ok(path) = env::var("DKN_P2P_PEER_STORE") {
+            self.peer_store_path = Some(path.trim())
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from);
+        }
+
+        self
+    }
+
+    /// Sets the Gossipsub mesh sizes, see [`DriaP2PConfig::mesh_n`].
+    pub fn with_mesh_size(mut self, mesh_n_low: usize, mesh_n: usize, mesh_n_high: usize) -> Self {
+        self.mesh_n_low = mesh_n_low;
+        self.mesh_n = mesh_n;
+        self.mesh_n_high = mesh_n_high;
+        self
+    }
+
+    /// Sets the maximum size of a Gossipsub message in bytes.
+    pub fn with_max_transmit_size(mut self, max_transmit_size: usize) -> Self {
+        self.max_transmit_size = max_transmit_size;
+        self
+    }
+
+    /// Sets the initial & maximum backoff of redialling the reserved peers.
+    pub fn with_redial_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.redial_initial_backoff = initial;
+        self.redial_max_backoff = max;
+        self
+    }
+
+    /// Enables the peer store at the given path, see [`DriaP2PConfig::peer_store_path`].
+    pub fn with_peer_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.peer_store_path = Some(path.into());
+        self
+    }
+
+    /// Disables Gossipsub peer scoring, which is enabled by default.
+    pub fn without_peer_scoring(mut self) -> Self {
+        self.peer_scoring = false;
+        self
+    }
+
+    /// Adds private & loopback addresses of the identified peers to Kademlia as well,
+    /// which are ignored by default.
+    pub fn with_local_addrs(mut self) -> Self {
+        self.allow_local_addrs = true;
+        self
+    }
+}
+
+/// Overrides the value with the parsed environment variable, if it exists.
+fn env_override<T: FromStr>(key: &str, value: &mut T) {
+    if let Ok(var) = env::var(key) {
+        match var.trim().parse() {
+            Ok(parsed) => *value = parsed,
+            Err(_) => log::warn!("Could not parse {}: {}", key, var),
+        }
+    }
+}
+
+/// Overrides the duration with the environment variable in seconds, if it exists.
+fn env_override_secs(key: &str, value: &mut Duration) {
+    if let Ok(var) = env::var(key) {
+        match var.trim().parse() {
+            Ok(secs) => *value = Duration::from_secs(secs),
+            Err(_) => log::warn!("Could not parse {}: {}", key, var),
+        }
+    }
+}
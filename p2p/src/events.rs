@@ -0,0 +1,64 @@
+use libp2p::{autonat::NatStatus, Multiaddr, PeerId};
+
+use crate::ReservedPeerKind;
+
+/// Events of the peer-to-peer client, broadcast to the subscribers of [`DriaP2PCommander::events`](crate::DriaP2PCommander::events).
+///
+/// Gossipsub messages & requests are not events, they are received over their own channels.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriaP2PEvent {
+    /// A connection to a peer is established.
+    ConnectionEstablished {
+        peer_id: PeerId,
+        /// Address of the peer, as dialled or as seen from our side.
+        address: Multiaddr,
+        /// Number of established connections to this peer, including this one.
+        num_established: u32,
+    },
+    /// A connection to a peer is closed.
+    ConnectionClosed {
+        peer_id: PeerId,
+        /// Number of connections to this peer that remain.
+        num_established: u32,
+        /// Cause of the closure, `None` if the connection was closed gracefully.
+        cause: Option<String>,
+    },
+    /// An outgoing connection could not be established.
+    OutgoingConnectionError {
+        peer_id: Option<PeerId>,
+        error: String,
+    },
+    /// The local node is listening on a new address.
+    NewListenAddr { address: Multiaddr },
+    /// An external address of the local node is confirmed, usually the one via a relay.
+    ExternalAddrConfirmed { address: Multiaddr },
+    /// AutoNAT status of the local node has changed.
+    NatStatusChanged { old: NatStatus, new: NatStatus },
+    /// A relay has accepted our reservation, so we are reachable through it.
+    RelayReservationAccepted {
+        relay_peer_id: PeerId,
+        /// Whether this is a renewal of an existing reservation.
+        renewal: bool,
+    },
+    /// A peer is blacklisted & disconnected because its protocol does not match ours.
+    PeerBlacklisted {
+        peer_id: PeerId,
+        /// The mismatching protocol of the peer.
+        protocol: String,
+    },
+    /// A reserved peer is connected.
+    ReservedPeerConnected {
+        peer_id: PeerId,
+        kind: ReservedPeerKind,
+    },
+    /// A reserved peer is disconnected, it is redialled with backoff.
+    ReservedPeerDisconnected {
+        peer_id: PeerId,
+        kind: ReservedPeerKind,
+    },
+    /// None of the reserved peers of this kind are connected anymore, e.g. no tasks
+    /// nor pings are received when RPC liveness is lost.
+    LivenessLost { kind: ReservedPeerKind },
+    /// A reserved peer of this kind is connected again after the liveness was lost.
+    LivenessRestored { kind: ReservedPeerKind },
+}
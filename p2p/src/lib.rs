@@ -1,15 +1,29 @@
 mod transform;
 
+mod scoring;
+
 mod behaviour;
 
+mod reserved;
+pub use reserved::ReservedPeerKind;
+
+mod peerstore;
+pub use peerstore::PeerRecord;
+
+mod events;
+pub use events::DriaP2PEvent;
+
 mod client;
 pub use client::DriaP2PClient;
 
+mod config;
+pub use config::DriaP2PConfig;
+
 mod commands;
 pub use commands::{DriaP2PCommand, DriaP2PCommander};
 
 mod protocol;
-pub use protocol::DriaP2PProtocol;
+pub use protocol::{DriaP2PProtocol, EnvelopeFormat};
 
 mod network;
 pub use network::DriaNetworkType;
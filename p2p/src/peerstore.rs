@@ -0,0 +1,277 @@
+use dkn_utils::get_current_time_nanos;
+use eyre::{Context, Result};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A known good peer, i.e. one that has identified itself with our protocol and stayed connected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerRecord {
+    /// Listen addresses of the peer.
+    pub addrs: Vec<Multiaddr>,
+    /// Last time the peer was seen, in seconds since the epoch.
+    pub last_seen: u64,
+    /// Identify protocol of the peer, e.g. `dria/0.2`.
+    pub protocol: String,
+}
+
+/// An on-disk store of the known good peers, so that a restarted node can fill its Kademlia
+/// routing table without depending on the bootstrap nodes alone.
+///
+/// An identified peer is only recorded once it has stayed connected for [`PeerStore::MIN_CONNECTED`],
+/// so that a peer can not fill the store by merely connecting & identifying with made up addresses.
+///
+/// The peers are kept in a JSON file, which is written atomically by renaming a temporary file.
+#[derive(Debug)]
+pub(crate) struct PeerStore {
+    path: PathBuf,
+    peers: HashMap<PeerId, PeerRecord>,
+    /// Identified peers that are not connected for long enough yet, along with the time they are identified.
+    pending: HashMap<PeerId, (Instant, PeerRecord)>,
+    /// Whether there are changes that are not saved yet.
+    dirty: bool,
+}
+
+impl PeerStore {
+    /// Maximum number of peers to keep, the least recently seen ones are dropped first.
+    pub const MAX_PEERS: usize = 1024;
+    /// Peers that have not been seen for this long are dropped.
+    pub const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+    /// Identified peers are recorded once they stay connected for this long.
+    pub const MIN_CONNECTED: Duration = Duration::from_secs(60);
+
+    /// Loads the store at the given path, starting empty if the file does not exist.
+    ///
+    /// Peers that are too old or have an unparsable id are dropped.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let peers = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<HashMap<String, PeerRecord>>(&data)
+                .wrap_err("could not parse peer store")?
+                .into_iter()
+                .filter_map(|(peer_id, record)| Some((peer_id.parse().ok()?, record)))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).wrap_err("could not read peer store"),
+        };
+
+        let mut store = Self {
+            path,
+            peers,
+            pending: HashMap::new(),
+            dirty: false,
+        };
+        store.prune();
+        Ok(store)
+    }
+
+    /// Path of the store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of peers in the store.
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Returns the peers with the given protocol, e.g. to be added to Kademlia.
+    pub fn peers_with_protocol<'a>(
+        &'a self,
+        protocol: &'a str,
+    ) -> impl Iterator<Item = (&'a PeerId, &'a PeerRecord)> {
+        self.peers
+            .iter()
+            .filter(move |(_, record)| record.protocol == protocol)
+    }
+
+    /// Notes a peer that is identified at the given time, along with its addresses.
+    ///
+    /// The peer is recorded by [`PeerStore::promote`] if it is still connected after [`PeerStore::MIN_CONNECTED`],
+    /// an already recorded peer keeps its record until then.
+    pub fn identified(
+        &mut self,
+        peer_id: PeerId,
+        addrs: Vec<Multiaddr>,
+        protocol: &str,
+        now: Instant,
+    ) {
+        if addrs.is_empty() {
+            return;
+        }
+
+        let record = PeerRecord {
+            addrs,
+            last_seen: now_secs(),
+            protocol: protocol.to_string(),
+        };
+        // keep the time of the first identification, as the peer may identify again over the same connection
+        self.pending
+            .entry(peer_id)
+            .and_modify(|(_, pending)| *pending = record.clone())
+            .or_insert((now, record));
+    }
+
+    /// Forgets a peer that is not recorded yet, as it has disconnected before [`PeerStore::MIN_CONNECTED`].
+    pub fn disconnected(&mut self, peer_id: &PeerId) {
+        self.pending.remove(peer_id);
+    }
+
+    /// Records the identified peers that have stayed connected for [`PeerStore::MIN_CONNECTED`] at the given time.
+    pub fn promote(&mut self, now: Instant) {
+        let connected = self
+            .pending
+            .iter()
+            .filter(|(_, (identified_at, _))| {
+                now.saturating_duration_since(*identified_at) >= Self::MIN_CONNECTED
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        for peer_id in connected {
+            if let Some((_, record)) = self.pending.remove(&peer_id) {
+                self.peers.insert(peer_id, record);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Removes a peer, e.g. when it is blacklisted.
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.pending.remove(peer_id);
+        if self.peers.remove(peer_id).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// Saves the store if there are unsaved changes, after recording the peers that have stayed connected
+    /// and dropping the old peers.
+    ///
+    /// The temporary file is synced before it is renamed, so that a crash does not leave an empty store behind.
+    pub fn save(&mut self) -> Result<()> {
+        self.promote(Instant::now());
+        if !self.dirty {
+            return Ok(());
+        }
+        self.prune();
+
+        let peers = self
+            .peers
+            .iter()
+            .map(|(peer_id, record)| (peer_id.to_string(), record))
+            .collect::<HashMap<_, _>>();
+        let data = serde_json::to_vec_pretty(&peers).wrap_err("could not serialize peer store")?;
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).wrap_err("could not create peer store directory")?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp_path).wrap_err("could not write peer store")?;
+        file.write_all(&data)
+            .and_then(|_| file.sync_all())
+            .wrap_err("could not write peer store")?;
+        std::fs::rename(&tmp_path, &self.path).wrap_err("could not write peer store")?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Drops the peers that are too old, and the least recently seen ones above the limit.
+    fn prune(&mut self) {
+        let min_last_seen = now_secs().saturating_sub(Self::MAX_AGE.as_secs());
+        let len = self.peers.len();
+        self.peers
+            .retain(|_, record| record.last_seen >= min_last_seen);
+
+        if self.peers.len() > Self::MAX_PEERS {
+            let mut peers = std::mem::take(&mut self.peers)
+                .into_iter()
+                .collect::<Vec<_>>();
+            peers.sort_unstable_by_key(|(_, record)| Reverse(record.last_seen));
+            peers.truncate(Self::MAX_PEERS);
+            self.peers = peers.into_iter().collect();
+        }
+
+        self.dirty |= self.peers.len() != len;
+    }
+}
+
+/// Returns the current time in seconds since the epoch.
+fn now_secs() -> u64 {
+    (get_current_time_nanos() / 1_000_000_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_store() {
+        let path = std::env::temp_dir().join(format!("dkn-peers-{}.json", PeerId::random()));
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+
+        let mut store = PeerStore::load(&path).unwrap();
+        assert_eq!(store.len(), 0);
+        let now = Instant::now();
+        store.identified(peer_a, vec![addr.clone()], "dria/0.2", now);
+        store.identified(peer_b, vec![addr.clone()], "dria/0.1", now);
+        store.identified(PeerId::random(), vec![], "dria/0.2", now);
+
+        // peers are recorded only once they stay connected for long enough
+        let peer_c = PeerId::random();
+        store.identified(peer_c, vec![addr.clone()], "dria/0.2", now);
+        store.disconnected(&peer_c);
+        store.promote(now + PeerStore::MIN_CONNECTED / 2);
+        assert_eq!(store.len(), 0);
+        store.promote(now + PeerStore::MIN_CONNECTED);
+        assert_eq!(store.len(), 2);
+        store.save().unwrap();
+
+        // only the peers with the same protocol are returned after reloading
+        let mut store = PeerStore::load(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        let peers = store.peers_with_protocol("dria/0.2").collect::<Vec<_>>();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].0, &peer_a);
+        assert_eq!(peers[0].1.addrs, vec![addr]);
+
+        // old peers are dropped
+        store.peers.get_mut(&peer_b).unwrap().last_seen = 0;
+        store.dirty = true;
+        store.save().unwrap();
+        assert_eq!(PeerStore::load(&path).unwrap().len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_peer_store_limit() {
+        let path = std::env::temp_dir().join(format!("dkn-peers-{}.json", PeerId::random()));
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+        let mut store = PeerStore::load(&path).unwrap();
+
+        // all peers are seen at the same second, yet only the limit is kept
+        let now = Instant::now();
+        for _ in 0..PeerStore::MAX_PEERS + 10 {
+            store.identified(PeerId::random(), vec![addr.clone()], "dria/0.2", now);
+        }
+        store.promote(now + PeerStore::MIN_CONNECTED);
+        store.prune();
+        assert_eq!(store.len(), PeerStore::MAX_PEERS);
+
+        // the least recently seen ones are dropped first
+        let recent = PeerId::random();
+        store.identified(recent, vec![addr], "dria/0.2", now);
+        store.promote(now + PeerStore::MIN_CONNECTED);
+        store.peers.get_mut(&recent).unwrap().last_seen += 1;
+        store.prune();
+        assert_eq!(store.len(), PeerStore::MAX_PEERS);
+        assert!(store.peers.contains_key(&recent));
+    }
+}
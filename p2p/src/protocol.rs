@@ -1,5 +1,38 @@
 use libp2p::StreamProtocol;
-use std::env;
+use std::{env, time::Duration};
+
+/// Wire format of the messages that are published by the peers.
+///
+/// Receivers detect the format of each message, but the format is part of the Gossipsub protocol
+/// so that peers only receive messages in a format that they have opted into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnvelopeFormat {
+    /// JSON, with a base64 payload and hex signatures.
+    #[default]
+    Json,
+    /// CBOR, with raw payload and signature bytes.
+    Cbor,
+}
+
+impl EnvelopeFormat {
+    /// Parses the format from its name, e.g. `json` or `cbor`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EnvelopeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Cbor => write!(f, "cbor"),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct DriaP2PProtocol {
@@ -24,6 +57,18 @@ pub struct DriaP2PProtocol {
     /// which is mandatory for a `StreamProtocol`.
     ///
     pub request_response: StreamProtocol,
+    /// Gossipsub protocol, `None` for the default `meshsub` protocols of libp2p.
+    ///
+    /// This is usually `/{name}/meshsub-{features}/{version}` when a feature that changes the messages is enabled,
+    /// e.g. `/{name}/meshsub-ttl/{version}` with message freshness, so that peers with & without it
+    /// do not exchange messages that they can not read.
+    pub gossipsub: Option<StreamProtocol>,
+    /// Maximum age of the Gossipsub messages, checked by a timestamp that is prepended to each message.
+    ///
+    /// Disabled by default, see [`DriaP2PProtocol::with_message_freshness`].
+    pub message_freshness: Option<Duration>,
+    /// Wire format of the published messages, JSON by default.
+    pub envelope: EnvelopeFormat,
 }
 
 impl std::fmt::Display for DriaP2PProtocol {
@@ -57,7 +102,52 @@ impl DriaP2PProtocol {
             identity,
             kademlia,
             request_response,
+            gossipsub: None,
+            message_freshness: None,
+            envelope: EnvelopeFormat::default(),
+        }
+    }
+
+    /// Sets the wire format of the published messages.
+    ///
+    /// Peers that only know JSON can not read CBOR messages, so with CBOR the Gossipsub protocol is changed to
+    /// `/{name}/meshsub-cbor/{version}` (along with the other features, e.g. `meshsub-ttl-cbor`) as well.
+    pub fn with_envelope(mut self, envelope: EnvelopeFormat) -> Self {
+        self.envelope = envelope;
+        self.with_gossipsub_features()
+    }
+
+    /// Enables message freshness, where each Gossipsub message is timestamped and
+    /// messages older than `max_age` are dropped by the receivers.
+    ///
+    /// The timestamp changes the message format, so the Gossipsub protocol is changed to
+    /// `/{name}/meshsub-ttl/{version}` as well; peers without freshness remain connected
+    /// (e.g. for Kademlia & requests) but do not exchange Gossipsub messages with us.
+    pub fn with_message_freshness(mut self, max_age: Duration) -> Self {
+        self.message_freshness = Some(max_age);
+        self.with_gossipsub_features()
+    }
+
+    /// Sets the Gossipsub protocol w.r.t the enabled features, in a fixed order.
+    fn with_gossipsub_features(mut self) -> Self {
+        let mut features = Vec::new();
+        if self.message_freshness.is_some() {
+            features.push("ttl");
         }
+        if self.envelope == EnvelopeFormat::Cbor {
+            features.push("cbor");
+        }
+
+        self.gossipsub = (!features.is_empty()).then(|| {
+            StreamProtocol::try_from_owned(format!(
+                "/{}/meshsub-{}/{}",
+                self.name,
+                features.join("-"),
+                self.version
+            ))
+            .unwrap()
+        });
+        self
     }
 
     /// Creates a new instance of the protocol with the given `name` and the current version as per Cargo.toml.
@@ -137,4 +227,33 @@ mod tests {
         assert!(protocol.is_common_kademlia(&matching_protocol));
         assert!(!protocol.is_common_kademlia(&non_matching_protocol));
     }
+
+    #[test]
+    fn test_message_freshness() {
+        let protocol = DriaP2PProtocol::new("test", "1.0");
+        assert!(protocol.gossipsub.is_none());
+        assert!(protocol.message_freshness.is_none());
+
+        let protocol = protocol.with_message_freshness(Duration::from_secs(60));
+        assert_eq!(
+            protocol.gossipsub.map(|p| p.to_string()),
+            Some("/test/meshsub-ttl/1.0".to_string())
+        );
+        assert_eq!(protocol.message_freshness, Some(Duration::from_secs(60)));
+        assert_eq!(protocol.identity, "test/1.0");
+    }
+
+    #[test]
+    fn test_envelope() {
+        let protocol = DriaP2PProtocol::new("test", "1.0").with_envelope(EnvelopeFormat::Json);
+        assert!(protocol.gossipsub.is_none());
+
+        let protocol = protocol
+            .with_envelope(EnvelopeFormat::Cbor)
+            .with_message_freshness(Duration::from_secs(60));
+        assert_eq!(
+            protocol.gossipsub.map(|p| p.to_string()),
+            Some("/test/meshsub-ttl-cbor/1.0".to_string())
+        );
+    }
 }
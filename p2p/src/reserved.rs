@@ -0,0 +1,250 @@
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Kind of a reserved peer, i.e. why the connection to it is kept alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReservedPeerKind {
+    /// RPC nodes, which publish the tasks & pings.
+    Rpc,
+    /// Bootstrap nodes of the Kademlia DHT.
+    Bootstrap,
+    /// Relay nodes, which we listen through with a circuit.
+    Relay,
+}
+
+impl std::fmt::Display for ReservedPeerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rpc => write!(f, "RPC"),
+            Self::Bootstrap => write!(f, "bootstrap"),
+            Self::Relay => write!(f, "relay"),
+        }
+    }
+}
+
+/// State of a single reserved peer.
+#[derive(Debug)]
+struct ReservedPeer {
+    kind: ReservedPeerKind,
+    addrs: HashSet<Multiaddr>,
+    /// Whether there is an established connection to this peer.
+    connected: bool,
+    /// Number of failed dials since the last connection.
+    failures: u32,
+    /// Time of the next dial, `None` if connected or a dial is in progress.
+    next_dial: Option<Instant>,
+}
+
+/// Change in the connectivity of a reserved peer, see [`ReservedPeers::connected`] & [`ReservedPeers::disconnected`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ReservedPeerChange {
+    pub kind: ReservedPeerKind,
+    /// Whether this change lost or restored the liveness of its kind,
+    /// i.e. it was the last peer of its kind to disconnect or the first to reconnect after that.
+    pub liveness_changed: bool,
+}
+
+/// Peers that we keep a connection to, and redial with an exponential backoff when the connection is lost.
+#[derive(Debug)]
+pub(crate) struct ReservedPeers {
+    peers: HashMap<PeerId, ReservedPeer>,
+    /// Kinds that had a connection, and lost all of them.
+    lost: HashSet<ReservedPeerKind>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl ReservedPeers {
+    pub fn new(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            peers: HashMap::new(),
+            lost: HashSet::new(),
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    /// Reserves the peers with the given addresses, which must contain a `/p2p/<peer-id>` component;
+    /// addresses without a peer id are ignored with a warning.
+    ///
+    /// Returns the newly reserved peers, which are expected to be dialled by the caller.
+    pub fn insert(
+        &mut self,
+        kind: ReservedPeerKind,
+        addrs: impl IntoIterator<Item = Multiaddr>,
+    ) -> HashSet<PeerId> {
+        let mut inserted = HashSet::new();
+        for addr in addrs {
+            let Some(peer_id) = peer_id_of(&addr) else {
+                log::warn!("Missing peerID in reserved {} address: {}", kind, addr);
+                continue;
+            };
+
+            self.peers
+                .entry(peer_id)
+                .or_insert_with(|| {
+                    inserted.insert(peer_id);
+                    ReservedPeer {
+                        kind,
+                        addrs: HashSet::new(),
+                        connected: false,
+                        failures: 0,
+                        next_dial: None,
+                    }
+                })
+                .addrs
+                .insert(addr);
+        }
+
+        inserted
+    }
+
+    /// Returns the kind & addresses of a reserved peer.
+    pub fn get(&self, peer_id: &PeerId) -> Option<(ReservedPeerKind, Vec<Multiaddr>)> {
+        self.peers
+            .get(peer_id)
+            .map(|peer| (peer.kind, peer.addrs.iter().cloned().collect()))
+    }
+
+    /// Removes a peer from the reserved peers, e.g. when it has a different protocol.
+    pub fn remove(&mut self, peer_id: &PeerId) -> bool {
+        self.peers.remove(peer_id).is_some()
+    }
+
+    /// Marks the peer as connected, returns `None` if it is not reserved or already connected.
+    pub fn connected(&mut self, peer_id: &PeerId) -> Option<ReservedPeerChange> {
+        let peer = self.peers.get_mut(peer_id).filter(|peer| !peer.connected)?;
+        peer.connected = true;
+        peer.failures = 0;
+        peer.next_dial = None;
+
+        let kind = peer.kind;
+        Some(ReservedPeerChange {
+            kind,
+            liveness_changed: self.lost.remove(&kind),
+        })
+    }
+
+    /// Marks the peer as disconnected & schedules a redial, returns `None` if it is not reserved or not connected.
+    pub fn disconnected(&mut self, peer_id: &PeerId, now: Instant) -> Option<ReservedPeerChange> {
+        let peer = self.peers.get_mut(peer_id).filter(|peer| peer.connected)?;
+        peer.connected = false;
+        peer.next_dial = Some(now + self.initial_backoff);
+
+        let kind = peer.kind;
+        let liveness_changed = !self.is_live(kind) && self.lost.insert(kind);
+        Some(ReservedPeerChange {
+            kind,
+            liveness_changed,
+        })
+    }
+
+    /// Schedules a redial of the peer with an increased backoff, if it is reserved and not connected.
+    pub fn dial_failed(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(peer) = self.peers.get_mut(peer_id).filter(|peer| !peer.connected) {
+            // the first failure is redialled after the initial backoff, which doubles afterwards
+            peer.failures = peer.failures.saturating_add(1);
+            let backoff = self
+                .initial_backoff
+                .saturating_mul(1 << (peer.failures - 1).min(16))
+                .min(self.max_backoff);
+            log::debug!(
+                "Redialling {} peer {} in {:?} ({} failures)",
+                peer.kind,
+                peer_id,
+                backoff,
+                peer.failures
+            );
+            peer.next_dial = Some(now + backoff);
+        }
+    }
+
+    /// Returns the peers that are due to be dialled, and marks them as dialling.
+    pub fn due(&mut self, now: Instant) -> Vec<(PeerId, ReservedPeerKind, Vec<Multiaddr>)> {
+        self.peers
+            .iter_mut()
+            .filter(|(_, peer)| peer.next_dial.is_some_and(|at| at <= now))
+            .map(|(peer_id, peer)| {
+                peer.next_dial = None;
+                (*peer_id, peer.kind, peer.addrs.iter().cloned().collect())
+            })
+            .collect()
+    }
+
+    /// Returns `true` if any reserved peer of the given kind is connected.
+    pub fn is_live(&self, kind: ReservedPeerKind) -> bool {
+        self.peers
+            .values()
+            .any(|peer| peer.kind == kind && peer.connected)
+    }
+}
+
+/// Returns the peer id within the address, if any.
+fn peer_id_of(addr: &Multiaddr) -> Option<PeerId> {
+    addr.iter().find_map(|p| match p {
+        Protocol::P2p(peer_id) => Some(peer_id),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_peers() {
+        let mut reserved = ReservedPeers::new(Duration::from_secs(1), Duration::from_secs(10));
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+        let addr_of = |peer_id| {
+            "/ip4/127.0.0.1/tcp/4001"
+                .parse::<Multiaddr>()
+                .unwrap()
+                .with(Protocol::P2p(peer_id))
+        };
+        let inserted = reserved.insert(ReservedPeerKind::Rpc, [addr_of(peer_a), addr_of(peer_b)]);
+        assert_eq!(inserted.len(), 2);
+        let inserted = reserved.insert(
+            ReservedPeerKind::Rpc,
+            [addr_of(peer_a), "/ip4/127.0.0.1/tcp/4001".parse().unwrap()],
+        );
+        assert!(inserted.is_empty());
+
+        // new peers are dialled by the caller, so they are not due
+        let now = Instant::now();
+        assert!(reserved.due(now).is_empty());
+
+        // the first connection does not restore liveness, as it was never lost
+        let change = reserved.connected(&peer_a).unwrap();
+        assert!(!change.liveness_changed);
+        assert!(reserved.connected(&peer_a).is_none());
+        assert!(reserved.connected(&PeerId::random()).is_none());
+
+        // backoff increases with failures, up to the maximum
+        reserved.dial_failed(&peer_b, now);
+        assert!(reserved.due(now + Duration::from_millis(500)).is_empty());
+        assert_eq!(reserved.due(now + Duration::from_secs(1)).len(), 1);
+        reserved.dial_failed(&peer_b, now);
+        assert!(reserved.due(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(reserved.due(now + Duration::from_secs(2)).len(), 1);
+        for _ in 0..10 {
+            reserved.dial_failed(&peer_b, now);
+        }
+        assert!(reserved.due(now + Duration::from_secs(9)).is_empty());
+        assert_eq!(reserved.due(now + Duration::from_secs(10)).len(), 1);
+
+        // liveness is lost when the last connected peer disconnects, and restored by any peer
+        let change = reserved.disconnected(&peer_a, now).unwrap();
+        assert!(change.liveness_changed);
+        assert!(!reserved.is_live(ReservedPeerKind::Rpc));
+        assert_eq!(reserved.due(now + Duration::from_secs(1))[0].0, peer_a);
+        assert!(reserved.connected(&peer_b).unwrap().liveness_changed);
+        assert!(!reserved.connected(&peer_a).unwrap().liveness_changed);
+        assert!(
+            !reserved
+                .disconnected(&peer_a, now)
+                .unwrap()
+                .liveness_changed
+        );
+    }
+}
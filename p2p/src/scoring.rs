@@ -0,0 +1,95 @@
+use libp2p::gossipsub::{
+    score_parameter_decay, IdentTopic, PeerScoreParams, PeerScoreThresholds, TopicScoreParams,
+};
+use std::time::Duration;
+
+/// Application-specific score given to the RPC peers, so that they are kept
+/// in the mesh even if they share an IP address with many other peers.
+pub const RPC_APPLICATION_SCORE: f64 = 100.0;
+
+/// Topics that are published by the RPC peers, where first deliveries are rewarded.
+const RPC_TOPICS: [&str; 2] = ["ping", "task"];
+
+/// Topics that are published by the compute nodes.
+const NODE_TOPICS: [&str; 2] = ["pong", "results"];
+
+/// Returns the peer score parameters for the Dria topics.
+///
+/// - First deliveries of `ping` & `task` messages are rewarded, as they are published by the RPCs.
+/// - Rejected messages (see `MessageAcceptance::Reject`) are penalised on all topics,
+///   and a handful of them within the decay window are enough to graylist a peer.
+/// - Peers that share an IP address with more than a few others are penalised.
+/// - Mesh delivery rates are not scored, as the heartbeat topics have low traffic.
+pub fn peer_score_params() -> PeerScoreParams {
+    let mut params = PeerScoreParams {
+        app_specific_weight: 1.0,
+        ip_colocation_factor_weight: -5.0,
+        ip_colocation_factor_threshold: 10.0,
+        ..Default::default()
+    };
+
+    for topic in RPC_TOPICS {
+        params
+            .topics
+            .insert(IdentTopic::new(topic).hash(), topic_score_params(1.0));
+    }
+    for topic in NODE_TOPICS {
+        params
+            .topics
+            .insert(IdentTopic::new(topic).hash(), topic_score_params(0.0));
+    }
+
+    params
+}
+
+/// Returns the peer score thresholds, below which peers are ignored step by step.
+pub fn peer_score_thresholds() -> PeerScoreThresholds {
+    PeerScoreThresholds {
+        gossip_threshold: -10.0,
+        publish_threshold: -50.0,
+        graylist_threshold: -80.0,
+        ..Default::default()
+    }
+}
+
+/// Returns the topic score parameters with the given weight for first deliveries.
+fn topic_score_params(first_message_deliveries_weight: f64) -> TopicScoreParams {
+    TopicScoreParams {
+        topic_weight: 1.0,
+        // a little reward for staying in the mesh, up to an hour
+        time_in_mesh_weight: 0.01,
+        time_in_mesh_quantum: Duration::from_secs(1),
+        time_in_mesh_cap: 3600.0,
+        first_message_deliveries_weight,
+        first_message_deliveries_decay: score_parameter_decay(Duration::from_secs(60 * 60)),
+        first_message_deliveries_cap: 100.0,
+        // heartbeat topics have low traffic, so delivery rates are not scored
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        // the penalty is squared, so a handful of rejections within the decay window hit the graylist
+        invalid_message_deliveries_weight: -5.0,
+        invalid_message_deliveries_decay: score_parameter_decay(Duration::from_secs(10 * 60)),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_score_params() {
+        let params = peer_score_params();
+        assert!(params.validate().is_ok());
+        assert!(peer_score_thresholds().validate().is_ok());
+        assert_eq!(params.topics.len(), 4);
+
+        // a single rejection is not enough to stop gossiping with a peer
+        let topic = &params.topics[&IdentTopic::new("task").hash()];
+        assert!(topic.invalid_message_deliveries_weight > peer_score_thresholds().gossip_threshold);
+        assert!(
+            topic.invalid_message_deliveries_weight * 16.0
+                <= peer_score_thresholds().graylist_threshold
+        );
+    }
+}
@@ -1,6 +1,40 @@
-use libp2p::gossipsub::{DataTransform, Message, RawMessage, TopicHash};
+use libp2p::gossipsub::{DataTransform, IdentityTransform, Message, RawMessage, TopicHash};
 use std::io::{Error, ErrorKind};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The data transform of the Gossipsub behaviour, chosen w.r.t the protocol.
+pub enum DriaDataTransform {
+    /// Messages are sent & received as is.
+    Identity(IdentityTransform),
+    /// Messages are timestamped, and stale ones are dropped.
+    Ttl(TTLDataTransform),
+}
+
+impl DriaDataTransform {
+    /// Returns the TTL transform if a maximum message age is given, identity otherwise.
+    pub fn new(message_freshness: Option<Duration>) -> Self {
+        match message_freshness {
+            Some(ttl) => Self::Ttl(TTLDataTransform::new(ttl)),
+            None => Self::Identity(IdentityTransform),
+        }
+    }
+}
+
+impl DataTransform for DriaDataTransform {
+    fn inbound_transform(&self, raw_message: RawMessage) -> Result<Message, Error> {
+        match self {
+            Self::Identity(transform) => transform.inbound_transform(raw_message),
+            Self::Ttl(transform) => transform.inbound_transform(raw_message),
+        }
+    }
+
+    fn outbound_transform(&self, topic: &TopicHash, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Identity(transform) => transform.outbound_transform(topic, data),
+            Self::Ttl(transform) => transform.outbound_transform(topic, data),
+        }
+    }
+}
 
 /// A [DataTransform](https://docs.rs/libp2p-gossipsub/latest/libp2p_gossipsub/trait.DataTransform.html)
 /// implementation that adds & checks a timestamp to the message.
@@ -11,6 +45,15 @@ pub struct TTLDataTransform {
 
 impl TTLDataTransform {
     const MID_SIZE: usize = 8;
+    /// Messages may be timestamped this far in the future, to tolerate clock skew among peers.
+    const MAX_CLOCK_SKEW_SECS: u64 = 60;
+
+    /// Creates a transform that drops the messages older than `ttl`, with seconds precision.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl_secs: ttl.as_secs(),
+        }
+    }
 
     #[inline(always)]
     fn get_time_secs(&self) -> u64 {
@@ -40,10 +83,18 @@ impl DataTransform for TTLDataTransform {
         let raw_data = raw_message.data.split_off(Self::MID_SIZE);
         let msg_time = u64::from_be_bytes(raw_message.data[0..Self::MID_SIZE].try_into().unwrap());
 
-        // check ttl
-        if msg_time + self.ttl_secs < self.get_time_secs() {
+        // check ttl, the time is chosen by the sender so it must not overflow
+        let now = self.get_time_secs();
+        if msg_time.saturating_add(self.ttl_secs) < now {
             return Err(Error::new(ErrorKind::InvalidInput, "Message TTL expired"));
         }
+        // otherwise a message from the future would stay fresh for longer than the ttl
+        if msg_time > now.saturating_add(Self::MAX_CLOCK_SKEW_SECS) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Message time is in the future",
+            ));
+        }
 
         Ok(Message {
             source: raw_message.source,
@@ -103,4 +154,55 @@ mod tests {
 
         assert_eq!(message.data, data);
     }
+
+    /// A raw message with the given time prepended to `[1, 2, 3]`.
+    fn timed_raw_message(msg_time: u64, topic: &TopicHash) -> RawMessage {
+        let mut data = msg_time.to_be_bytes().to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+
+        RawMessage {
+            source: Some(PeerId::random()),
+            data,
+            sequence_number: None,
+            topic: topic.clone(),
+            signature: Default::default(),
+            key: Default::default(),
+            validated: false,
+        }
+    }
+
+    #[test]
+    fn test_ttl_data_transform_expired() {
+        let ttl_data_transform = TTLDataTransform::new(Duration::from_secs(100));
+        let topic = TopicHash::from_raw("topic");
+
+        // a message from 200 seconds ago
+        let msg_time = ttl_data_transform.get_time_secs() - 200;
+        let raw_message = timed_raw_message(msg_time, &topic);
+        assert!(ttl_data_transform.inbound_transform(raw_message).is_err());
+
+        // identity transform does not touch the data
+        let identity = DriaDataTransform::new(None);
+        let transformed = identity.outbound_transform(&topic, vec![1, 2, 3]).unwrap();
+        assert_eq!(transformed, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_ttl_data_transform_future() {
+        let ttl_data_transform = TTLDataTransform::new(Duration::from_secs(100));
+        let topic = TopicHash::from_raw("topic");
+        let now = ttl_data_transform.get_time_secs();
+
+        // a slightly skewed clock is tolerated
+        let raw_message = timed_raw_message(now + 10, &topic);
+        assert!(ttl_data_transform.inbound_transform(raw_message).is_ok());
+
+        // a message from the future is rejected
+        let raw_message = timed_raw_message(now + 3600, &topic);
+        assert!(ttl_data_transform.inbound_transform(raw_message).is_err());
+
+        // the ttl check does not overflow for the maximum time
+        let raw_message = timed_raw_message(u64::MAX, &topic);
+        assert!(ttl_data_transform.inbound_transform(raw_message).is_err());
+    }
 }
@@ -0,0 +1,258 @@
+//! An in-process network of `DriaP2PClient`s on localhost, so that tests require no network access.
+//!
+//! Each integration test uses a different subset of this module.
+#![allow(dead_code)]
+
+use dkn_p2p::{
+    DriaNetworkType, DriaNodes, DriaP2PClient, DriaP2PCommander, DriaP2PConfig, DriaP2PEvent,
+    DriaP2PProtocol,
+};
+use eyre::{eyre, Result};
+use libp2p::futures::StreamExt;
+use libp2p::gossipsub::{Message, MessageId};
+use libp2p::multiaddr::Protocol;
+use libp2p::request_response::ResponseChannel;
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{identify, noise, relay, tcp, yamux, Multiaddr, PeerId, SwarmBuilder};
+use libp2p_identity::Keypair;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// Default timeout for the waits within tests.
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval to poll the clients while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Initializes the logger for tests, once.
+pub fn init_logger() {
+    let _ = env_logger::builder()
+        .filter_level(log::LevelFilter::Off)
+        .filter_module("dkn_p2p", log::LevelFilter::Debug)
+        .is_test(true)
+        .try_init();
+}
+
+/// Returns a localhost TCP address with a port that is free at the moment.
+pub fn local_addr() -> Multiaddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("could not bind");
+    let port = listener.local_addr().expect("could not get address").port();
+
+    Multiaddr::empty()
+        .with(Protocol::Ip4([127, 0, 0, 1].into()))
+        .with(Protocol::Tcp(port))
+}
+
+/// A `DriaP2PClient` running on localhost in a separate task.
+pub struct LocalNode {
+    pub peer_id: PeerId,
+    /// Listen address, along with the peer id.
+    pub addr: Multiaddr,
+    pub commander: DriaP2PCommander,
+    pub msg_rx: mpsc::Receiver<(PeerId, MessageId, Message)>,
+    pub req_rx: mpsc::Receiver<(PeerId, Vec<u8>, ResponseChannel<Vec<u8>>)>,
+    /// Events of the client, subscribed before it starts running.
+    pub events: broadcast::Receiver<DriaP2PEvent>,
+    handle: JoinHandle<()>,
+}
+
+impl LocalNode {
+    /// Spawns a node with the default protocol, where local addresses are ignored by Kademlia.
+    pub fn spawn(nodes: &DriaNodes) -> Result<Self> {
+        Self::spawn_with(nodes, DriaP2PProtocol::default(), false)
+    }
+
+    /// Spawns a node with the given protocol, and whether local addresses are added to Kademlia.
+    pub fn spawn_with(
+        nodes: &DriaNodes,
+        protocol: DriaP2PProtocol,
+        allow_local_addrs: bool,
+    ) -> Result<Self> {
+        let config = if allow_local_addrs {
+            DriaP2PConfig::default().with_local_addrs()
+        } else {
+            DriaP2PConfig::default()
+        };
+
+        Self::spawn_at(
+            nodes,
+            protocol,
+            config,
+            Keypair::generate_secp256k1(),
+            local_addr(),
+        )
+    }
+
+    /// Spawns a node with the given keypair & listen address, e.g. to restart a node that was shut down.
+    pub fn spawn_at(
+        nodes: &DriaNodes,
+        protocol: DriaP2PProtocol,
+        config: DriaP2PConfig,
+        keypair: Keypair,
+        listen_addr: Multiaddr,
+    ) -> Result<Self> {
+        let peer_id = keypair.public().to_peer_id();
+        let (client, commander, msg_rx, req_rx) =
+            DriaP2PClient::new(keypair, listen_addr.clone(), nodes, protocol, config)?;
+        let events = commander.events();
+        let handle = tokio::spawn(async move { client.run().await });
+
+        Ok(Self {
+            peer_id,
+            addr: listen_addr.with(Protocol::P2p(peer_id)),
+            commander,
+            msg_rx,
+            req_rx,
+            events,
+            handle,
+        })
+    }
+
+    /// Returns the nodes with this node as the only bootstrap node.
+    pub fn as_bootstrap(&self) -> DriaNodes {
+        empty_nodes().with_bootstrap_nodes([self.addr.clone()])
+    }
+
+    /// Waits until the given peer is within the peers of this node, either in the mesh or in all peers.
+    pub async fn wait_for_peer(&self, peer_id: &PeerId, in_mesh: bool) -> Result<()> {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            loop {
+                let (mesh, all) = self.commander.peers().await?;
+                let peers = if in_mesh { mesh } else { all };
+                if peers.contains(peer_id) {
+                    return Ok::<_, eyre::Report>(());
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .map_err(|_| eyre!("timed out waiting for peer {}", peer_id))?
+    }
+
+    /// Waits for the next gossipsub message, with the given timeout.
+    pub async fn recv_message(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(PeerId, MessageId, Message)> {
+        tokio::time::timeout(timeout, self.msg_rx.recv())
+            .await
+            .map_err(|_| eyre!("timed out waiting for message"))?
+            .ok_or_else(|| eyre!("message channel closed"))
+    }
+
+    /// Waits until an event of the client matching the predicate is received.
+    pub async fn wait_for_event(
+        &mut self,
+        predicate: impl Fn(&DriaP2PEvent) -> bool,
+    ) -> Result<()> {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            loop {
+                match self.events.recv().await {
+                    Ok(event) if predicate(&event) => return Ok::<_, eyre::Report>(()),
+                    Ok(event) => log::debug!("Skipped event: {:?}", event),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        })
+        .await
+        .map_err(|_| eyre!("timed out waiting for event"))?
+    }
+
+    /// Shuts down the client and waits for its task to finish.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.commander.shutdown().await?;
+        self.msg_rx.close();
+        self.req_rx.close();
+        self.handle.await?;
+
+        Ok(())
+    }
+}
+
+/// Returns nodes with no bootstrap, relay or RPC nodes.
+pub fn empty_nodes() -> DriaNodes {
+    DriaNodes::new(DriaNetworkType::Test)
+}
+
+#[derive(NetworkBehaviour)]
+struct RelayBehaviour {
+    relay: relay::Behaviour,
+    identify: identify::Behaviour,
+}
+
+/// A relay server running on localhost in a separate task, reporting its events over a channel.
+pub struct LocalRelay {
+    pub peer_id: PeerId,
+    /// Listen address, along with the peer id.
+    pub addr: Multiaddr,
+    pub events: mpsc::UnboundedReceiver<relay::Event>,
+    handle: JoinHandle<()>,
+}
+
+impl LocalRelay {
+    /// Spawns a relay server, identifying itself with the given protocol so that clients accept it.
+    pub fn spawn(protocol: &DriaP2PProtocol) -> Result<Self> {
+        let identity = protocol.identity();
+        let mut swarm = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )?
+            .with_behaviour(|key| RelayBehaviour {
+                relay: relay::Behaviour::new(key.public().to_peer_id(), Default::default()),
+                identify: identify::Behaviour::new(identify::Config::new(identity, key.public())),
+            })?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+
+        let peer_id = *swarm.local_peer_id();
+        let listen_addr = local_addr();
+        swarm.listen_on(listen_addr.clone())?;
+        // reservations must include an address of the relay
+        swarm.add_external_address(listen_addr.clone());
+
+        let (event_tx, events) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(async move {
+            loop {
+                if let SwarmEvent::Behaviour(RelayBehaviourEvent::Relay(event)) =
+                    swarm.select_next_some().await
+                {
+                    if event_tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            peer_id,
+            addr: listen_addr.with(Protocol::P2p(peer_id)),
+            events,
+            handle,
+        })
+    }
+
+    /// Waits until an event matching the predicate is received.
+    pub async fn wait_for(&mut self, predicate: impl Fn(&relay::Event) -> bool) -> Result<()> {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            while let Some(event) = self.events.recv().await {
+                log::debug!("Relay event: {:?}", event);
+                if predicate(&event) {
+                    return Ok::<_, eyre::Report>(());
+                }
+            }
+            Err(eyre!("relay event channel closed"))
+        })
+        .await
+        .map_err(|_| eyre!("timed out waiting for relay event"))?
+    }
+
+    /// Stops the relay server.
+    pub fn shutdown(self) {
+        self.handle.abort();
+    }
+}
@@ -1,4 +1,4 @@
-use dkn_p2p::{DriaNodes, DriaP2PClient, DriaP2PProtocol};
+use dkn_p2p::{DriaNodes, DriaP2PClient, DriaP2PConfig, DriaP2PProtocol};
 use eyre::Result;
 use libp2p_identity::Keypair;
 
@@ -35,6 +35,7 @@ async fn test_gossipsub() -> Result<()> {
         listen_addr,
         &nodes,
         DriaP2PProtocol::default(),
+        DriaP2PConfig::default(),
     )?;
     let task_handle = tokio::spawn(async move { client.run().await });
 
@@ -0,0 +1,353 @@
+use std::time::Duration;
+
+use dkn_p2p::{DriaP2PConfig, DriaP2PEvent, DriaP2PProtocol, ReservedPeerKind};
+use eyre::Result;
+use libp2p::gossipsub::MessageAcceptance;
+use libp2p::multiaddr::Protocol;
+use libp2p::{relay, PeerId};
+use libp2p_identity::Keypair;
+
+mod common;
+use common::{empty_nodes, init_logger, local_addr, LocalNode, LocalRelay, WAIT_TIMEOUT};
+
+/// Time to wait for a message that is not expected to arrive.
+const SILENCE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const TOPIC: &str = "test-topic";
+
+/// Two nodes subscribe to a topic, and one publishes a message to the other.
+#[tokio::test]
+async fn test_local_subscribe_publish() -> Result<()> {
+    init_logger();
+
+    let mut bootstrap = LocalNode::spawn(&empty_nodes())?;
+    let mut node = LocalNode::spawn(&bootstrap.as_bootstrap())?;
+    bootstrap.commander.subscribe(TOPIC).await?;
+    node.commander.subscribe(TOPIC).await?;
+    bootstrap.wait_for_peer(&node.peer_id, true).await?;
+
+    node.commander
+        .publish(TOPIC, b"hello world".to_vec())
+        .await?;
+    let (peer_id, message_id, message) = bootstrap.recv_message(WAIT_TIMEOUT).await?;
+    assert_eq!(peer_id, node.peer_id);
+    assert_eq!(message.source, Some(node.peer_id));
+    assert_eq!(message.topic.as_str(), TOPIC);
+    assert_eq!(message.data, b"hello world");
+    bootstrap
+        .commander
+        .validate_message(&message_id, &peer_id, MessageAcceptance::Accept)
+        .await?;
+
+    // unsubscribed nodes do not receive anything
+    bootstrap.commander.unsubscribe(TOPIC).await?;
+    node.commander
+        .publish(TOPIC, b"hello again".to_vec())
+        .await
+        .ok();
+    assert!(bootstrap.recv_message(SILENCE_TIMEOUT).await.is_err());
+
+    node.shutdown().await?;
+    bootstrap.shutdown().await?;
+    Ok(())
+}
+
+/// A message is propagated by the middle node only if it is accepted there, `A - B - C`.
+#[tokio::test]
+async fn test_local_message_validation() -> Result<()> {
+    init_logger();
+
+    // local addresses are not allowed, so that `A` and `C` can not discover each other
+    let mut node_b = LocalNode::spawn(&empty_nodes())?;
+    let mut node_a = LocalNode::spawn(&node_b.as_bootstrap())?;
+    let mut node_c = LocalNode::spawn(&node_b.as_bootstrap())?;
+    for node in [&node_a, &node_b, &node_c] {
+        node.commander.subscribe(TOPIC).await?;
+    }
+    node_b.wait_for_peer(&node_a.peer_id, true).await?;
+    node_b.wait_for_peer(&node_c.peer_id, true).await?;
+
+    // rejected message is not propagated
+    node_a
+        .commander
+        .publish(TOPIC, b"rejected".to_vec())
+        .await?;
+    let (peer_id, message_id, message) = node_b.recv_message(WAIT_TIMEOUT).await?;
+    assert_eq!(message.data, b"rejected");
+    node_b
+        .commander
+        .validate_message(&message_id, &peer_id, MessageAcceptance::Reject)
+        .await?;
+    assert!(node_c.recv_message(SILENCE_TIMEOUT).await.is_err());
+
+    // the rejection is penalised in the score of `A`
+    let scores = node_b.commander.peer_scores().await?;
+    let (_, score) = scores
+        .iter()
+        .find(|(peer_id, _)| *peer_id == node_a.peer_id)
+        .expect("should have a score for A");
+    assert!(*score < 0.0);
+
+    // accepted message is propagated
+    node_a
+        .commander
+        .publish(TOPIC, b"accepted".to_vec())
+        .await?;
+    let (peer_id, message_id, message) = node_b.recv_message(WAIT_TIMEOUT).await?;
+    assert_eq!(message.data, b"accepted");
+    node_b
+        .commander
+        .validate_message(&message_id, &peer_id, MessageAcceptance::Accept)
+        .await?;
+    let (peer_id, _, message) = node_c.recv_message(WAIT_TIMEOUT).await?;
+    assert_eq!(peer_id, node_b.peer_id);
+    assert_eq!(message.source, Some(node_a.peer_id));
+    assert_eq!(message.data, b"accepted");
+
+    for node in [node_a, node_b, node_c] {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// A node makes a request to another, which responds to it.
+#[tokio::test]
+async fn test_local_request_response() -> Result<()> {
+    init_logger();
+
+    let mut responder = LocalNode::spawn(&empty_nodes())?;
+    let requester = LocalNode::spawn(&responder.as_bootstrap())?;
+    responder.wait_for_peer(&requester.peer_id, false).await?;
+    let (requester_peer_id, responder_peer_id) = (requester.peer_id, responder.peer_id);
+
+    // the request is answered while the requester is waiting for it
+    let respond = async {
+        let (peer_id, request, channel) =
+            tokio::time::timeout(WAIT_TIMEOUT, responder.req_rx.recv())
+                .await?
+                .expect("should receive request");
+        assert_eq!(peer_id, requester_peer_id);
+        assert_eq!(request, b"ping");
+        responder.commander.respond(b"pong".to_vec(), channel).await
+    };
+    let request = requester
+        .commander
+        .request(responder_peer_id, b"ping".to_vec());
+    let (response, responded) = tokio::join!(request, respond);
+    responded?;
+    assert_eq!(response?, b"pong");
+
+    // requests to unknown peers fail
+    assert!(requester
+        .commander
+        .request(PeerId::random(), b"ping".to_vec())
+        .await
+        .is_err());
+
+    requester.shutdown().await?;
+    responder.shutdown().await?;
+    Ok(())
+}
+
+/// Peers with a different protocol version are disconnected after Identify.
+#[tokio::test]
+async fn test_local_identify_rejection() -> Result<()> {
+    init_logger();
+
+    let mut bootstrap = LocalNode::spawn(&empty_nodes())?;
+    let mut node = LocalNode::spawn(&bootstrap.as_bootstrap())?;
+    let mut other = LocalNode::spawn_with(
+        &bootstrap.as_bootstrap(),
+        DriaP2PProtocol::new("dria", "0.0"),
+        false,
+    )?;
+    for node in [&bootstrap, &node, &other] {
+        node.commander.subscribe(TOPIC).await?;
+    }
+
+    // the node with the same protocol is connected, the other one is blacklisted
+    let other_peer_id = other.peer_id;
+    bootstrap
+        .wait_for_event(|e| {
+            matches!(e, DriaP2PEvent::PeerBlacklisted { peer_id, protocol }
+                if *peer_id == other_peer_id && protocol.contains("0.0"))
+        })
+        .await?;
+    bootstrap.wait_for_peer(&node.peer_id, true).await?;
+    tokio::time::sleep(SILENCE_TIMEOUT).await;
+    let (_, all) = bootstrap.commander.peers().await?;
+    assert!(!all.contains(&other.peer_id));
+    assert!(other
+        .commander
+        .publish(TOPIC, b"hi".to_vec())
+        .await
+        .is_err());
+    assert!(node.commander.publish(TOPIC, b"hi".to_vec()).await.is_ok());
+
+    for node in [bootstrap, node, other] {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// Nodes with message freshness exchange timestamped messages, and do not mix them with nodes without it.
+#[tokio::test]
+async fn test_local_message_freshness() -> Result<()> {
+    init_logger();
+
+    let protocol = DriaP2PProtocol::default().with_message_freshness(Duration::from_secs(60));
+    let mut bootstrap = LocalNode::spawn_with(&empty_nodes(), protocol.clone(), false)?;
+    let mut node = LocalNode::spawn_with(&bootstrap.as_bootstrap(), protocol, false)?;
+    bootstrap.commander.subscribe(TOPIC).await?;
+    node.commander.subscribe(TOPIC).await?;
+    bootstrap.wait_for_peer(&node.peer_id, true).await?;
+
+    // the timestamp is removed at the receiver
+    node.commander.publish(TOPIC, b"fresh".to_vec()).await?;
+    let (_, message_id, message) = bootstrap.recv_message(WAIT_TIMEOUT).await?;
+    assert_eq!(message.data, b"fresh");
+    bootstrap
+        .commander
+        .validate_message(&message_id, &node.peer_id, MessageAcceptance::Accept)
+        .await?;
+
+    // a node without freshness connects to the bootstrap node, but not over gossipsub
+    let mut other = LocalNode::spawn(&bootstrap.as_bootstrap())?;
+    other.commander.subscribe(TOPIC).await?;
+    tokio::time::sleep(SILENCE_TIMEOUT).await;
+    let (_, all) = bootstrap.commander.peers().await?;
+    assert!(!all.contains(&other.peer_id));
+
+    // so neither side receives the messages of the other
+    node.commander
+        .publish(TOPIC, b"fresh again".to_vec())
+        .await?;
+    assert!(other.recv_message(SILENCE_TIMEOUT).await.is_err());
+    let (_, message_id, message) = bootstrap.recv_message(WAIT_TIMEOUT).await?;
+    assert_eq!(message.data, b"fresh again");
+    bootstrap
+        .commander
+        .validate_message(&message_id, &node.peer_id, MessageAcceptance::Accept)
+        .await?;
+    other.commander.publish(TOPIC, b"stale".to_vec()).await.ok();
+    assert!(bootstrap.recv_message(SILENCE_TIMEOUT).await.is_err());
+
+    for node in [bootstrap, node, other] {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// A node redials its reserved RPC node after it restarts, and reports the liveness of the connection.
+#[tokio::test]
+async fn test_local_reserved_redial() -> Result<()> {
+    init_logger();
+
+    let (rpc_keypair, rpc_listen_addr) = (Keypair::generate_secp256k1(), local_addr());
+    let rpc = LocalNode::spawn_at(
+        &empty_nodes(),
+        DriaP2PProtocol::default(),
+        DriaP2PConfig::default(),
+        rpc_keypair.clone(),
+        rpc_listen_addr.clone(),
+    )?;
+    let rpc_peer_id = rpc.peer_id;
+    let mut node = LocalNode::spawn_at(
+        &empty_nodes().with_rpc_nodes([rpc.addr.clone()]),
+        DriaP2PProtocol::default(),
+        DriaP2PConfig::default()
+            .with_redial_backoff(Duration::from_millis(100), Duration::from_secs(1)),
+        Keypair::generate_secp256k1(),
+        local_addr(),
+    )?;
+    let rpc_kind = ReservedPeerKind::Rpc;
+    node.wait_for_event(|e| {
+        *e == DriaP2PEvent::ReservedPeerConnected {
+            peer_id: rpc_peer_id,
+            kind: rpc_kind,
+        }
+    })
+    .await?;
+
+    // liveness is lost when the only RPC node goes down
+    rpc.shutdown().await?;
+    node.wait_for_event(|e| *e == DriaP2PEvent::LivenessLost { kind: rpc_kind })
+        .await?;
+
+    // and it is restored by redialling once the RPC node is back
+    let rpc = LocalNode::spawn_at(
+        &empty_nodes(),
+        DriaP2PProtocol::default(),
+        DriaP2PConfig::default(),
+        rpc_keypair,
+        rpc_listen_addr,
+    )?;
+    node.wait_for_event(|e| *e == DriaP2PEvent::LivenessRestored { kind: rpc_kind })
+        .await?;
+
+    for node in [rpc, node] {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// Two nodes with the same bootstrap node discover each other through Kademlia.
+#[tokio::test]
+async fn test_local_kademlia_discovery() -> Result<()> {
+    init_logger();
+
+    let protocol = DriaP2PProtocol::default();
+    let bootstrap = LocalNode::spawn_with(&empty_nodes(), protocol.clone(), true)?;
+    let node_a = LocalNode::spawn_with(&bootstrap.as_bootstrap(), protocol.clone(), true)?;
+    bootstrap.wait_for_peer(&node_a.peer_id, false).await?;
+
+    // `B` only knows the bootstrap node, and finds `A` from there
+    let mut node_b = LocalNode::spawn_with(&bootstrap.as_bootstrap(), protocol, true)?;
+    bootstrap.wait_for_peer(&node_b.peer_id, false).await?;
+    node_b.commander.refresh().await?;
+    node_b.wait_for_peer(&node_a.peer_id, false).await?;
+    node_a.wait_for_peer(&node_b.peer_id, false).await?;
+
+    for node in [bootstrap, node_a, node_b] {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// A node listens through a relay, and another node reaches it with a circuit.
+#[tokio::test]
+async fn test_local_relay() -> Result<()> {
+    init_logger();
+
+    let protocol = DriaP2PProtocol::default();
+    let mut relay = LocalRelay::spawn(&protocol)?;
+    let node = LocalNode::spawn(&empty_nodes().with_relay_nodes([relay.addr.clone()]))?;
+    let node_peer_id = node.peer_id;
+    relay
+        .wait_for(|event| {
+            matches!(event, relay::Event::ReservationReqAccepted { src_peer_id, .. }
+                if *src_peer_id == node_peer_id)
+        })
+        .await?;
+
+    // dial the node through the relay
+    let mut dialer = LocalNode::spawn(&empty_nodes())?;
+    let circuit_addr = relay
+        .addr
+        .clone()
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(node.peer_id));
+    dialer.commander.dial(circuit_addr).await?;
+    let dialer_peer_id = dialer.peer_id;
+    relay
+        .wait_for(|event| {
+            matches!(event, relay::Event::CircuitReqAccepted { src_peer_id, dst_peer_id }
+                if *src_peer_id == dialer_peer_id && *dst_peer_id == node_peer_id)
+        })
+        .await?;
+
+    dialer.shutdown().await?;
+    node.shutdown().await?;
+    relay.shutdown();
+    Ok(())
+}
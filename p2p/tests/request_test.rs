@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use dkn_p2p::DriaNetworkType::Community;
-use dkn_p2p::{DriaNodes, DriaP2PClient, DriaP2PProtocol};
+use dkn_p2p::{DriaNodes, DriaP2PClient, DriaP2PConfig, DriaP2PProtocol};
 use eyre::Result;
 use libp2p::PeerId;
 use libp2p_identity::Keypair;
@@ -36,6 +36,7 @@ async fn test_request_message() -> Result<()> {
         listen_addr,
         &nodes,
         DriaP2PProtocol::default(),
+        DriaP2PConfig::default(),
     )
     .expect("could not create p2p client");
 
@@ -48,12 +49,13 @@ async fn test_request_message() -> Result<()> {
     let peer_id =
         PeerId::from_str("16Uiu2HAmB5HGdwLNHX81u7ey1fvDx5Mr4ofa2PdSSVxFKrrcErAN").unwrap();
     log::info!("Making a request to peer: {}", peer_id);
-    commander
+    match commander
         .request(peer_id, b"here is some data".into())
-        .await?;
-
-    log::info!("Waiting for response logs for a few moments...");
-    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        .await
+    {
+        Ok(response) => log::info!("Response: {}", String::from_utf8_lossy(&response)),
+        Err(e) => log::warn!("Request failed: {:?}", e),
+    }
 
     // close command channel
     commander.shutdown().await.expect("could not shutdown");